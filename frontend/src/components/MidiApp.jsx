@@ -101,6 +101,18 @@ const MidiApp = ({ showExtraControls, cpbRef, wasmModule, toggleExtraControls })
     { label: "Hungarian", value: "hungarian" },
     { label: "Half-Whole", value: "half_whole" },
     { label: "Whole-Half", value: "whole_half" },
+    { label: "Ionian", value: "ionian" },
+    { label: "Dorian", value: "dorian" },
+    { label: "Phrygian", value: "phrygian" },
+    { label: "Lydian", value: "lydian" },
+    { label: "Mixolydian", value: "mixolydian" },
+    { label: "Locrian", value: "locrian" },
+    { label: "Blues", value: "blues" },
+    { label: "Major pentatonic", value: "major_pentatonic" },
+    { label: "Bebop", value: "bebop" },
+    { label: "Altered", value: "altered" },
+    { label: "Whole tone", value: "whole_tone" },
+    { label: "Phrygian dominant", value: "phrygian_dominant" },
     { label: "No pruning, but clone chords with optional notes", value: "all_notes" }
   ];
 
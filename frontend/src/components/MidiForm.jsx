@@ -226,7 +226,9 @@ const MidiForm = ({
 
   const chordPickingMethods = [
     { label: "Original - 2D", value: "original" },
-    { label: "1D", value: "1D" }
+    { label: "1D", value: "1D" },
+    { label: "Markov", value: "markov" },
+    { label: "Voice leading", value: "voice leading" }
   ];
 
   const modes = [
@@ -234,7 +236,9 @@ const MidiForm = ({
     { label: "Chords", value: "chords" },
     { label: "Melody v2", value: "melody v2" },
     { label: "Melody v3", value: "melody v3" },
-    { label: "Intended Placement", value: "intended" }
+    { label: "Intended Placement", value: "intended" },
+    { label: "Arpeggio", value: "arpeggio" },
+    { label: "Strum", value: "strum" }
   ];
 
   return (
@@ -1,6 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use midly::Smf;
-use musicgen::{music_modules_v2::midi::MidiFile, test_utils::generate_midi_all_chord_types};
+use musicgen::{music_modules_v2::{config::{ChordPickingMethod, GenerationConfig, GenerationMode}, midi::MidiFile}, test_utils::generate_midi_all_chord_types};
 use sha2::{digest::Output, Digest, Sha256};
 
 const NUM_CHORDS: usize = 10_000;
@@ -39,14 +39,13 @@ fn generate_chords(c: &mut Criterion) {
 fn setup_chord_choices() -> MidiFile {
     let hash: Output<Sha256> = Sha256::digest("test".as_bytes());
     let mut musician = musicgen::music_modules_v2::Music::smoke_hash_all_custom_handpicked_chords(hash, KEY);
-    musician.make_music_no_finalize(
-        NUM_CHORDS, 
-        "chords", 
-        false, 
-        "original", 
-        0,
-        4
-    )
+    let config = GenerationConfig::builder()
+        .generation_mode(GenerationMode::Chords)
+        .chord_picking_method(ChordPickingMethod::Original)
+        .num_chords(NUM_CHORDS)
+        .should_use_same_chords(false)
+        .build();
+    musician.make_music_no_finalize(&config)
 }
 
 #[inline]
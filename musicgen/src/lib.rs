@@ -1,17 +1,27 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
-use std::{collections::HashSet, fmt::Display};
-
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashSet;
 #[cfg(target_arch = "wasm32")]
 use js_sys::Array;
-use midly::Smf;
 #[allow(unused)]
-use music_modules_v2::{midi::MidiFile, Music};
+use music_modules_v2::{config::GenerationConfig, midi::{write_smf, MidiFile}, Music};
+use music_modules_v2::{chord_symbol::parse_progression, roman::realize_progression, utils::parse_key_signature};
+use music_modules_v2::score::Score;
+#[cfg(any(target_arch = "wasm32", test))]
+use music_modules_v2::config::NotationFormat;
+#[cfg(target_arch = "wasm32")]
+use music_modules_v2::chord::Chord;
+#[cfg(target_arch = "wasm32")]
+use music_modules_v2::chord_library::ChordLibrary;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use sha2::{Digest, Sha256};
 
 pub mod music_modules_v2;
 
+pub use music_modules_v2::error::Error;
+
 #[wasm_bindgen]
 #[cfg(target_arch = "wasm32")]
 extern "C" {
@@ -40,51 +50,69 @@ macro_rules! console_log {
     };
 }
 
-#[derive(Debug)]
-pub enum Error {
-    MidlyError(midly::Error),
-    StrError(String),
-    SerdeError(serde_json::Error),
+/// Generates a MIDI file, using the hash of `file_content` as the seed for 
+/// every random decision.
+/// 
+/// When `config` reharmonizes a melody without any notes, `file_content` is 
+/// read as a MIDI file, and its top line is the melody. The chords then 
+/// follow the time signature of the file unless `config` has a time 
+/// signature other than 4/4, and a `random` key is replaced by the key that 
+/// the file is most likely in.
+pub fn generate_midi_with_config(file_content: &[u8], config: &GenerationConfig) -> Result<Vec<u8>, Error> {
+    generate_song(file_content, config).map(|(_, midi)| midi)
 }
 
-impl Into<JsValue> for Error {
-    fn into(self) -> JsValue {
-        JsValue::from_str("Error")
-    }
+/// Generates the same song as `generate_midi_with_config`, returning the MIDI 
+/// file along with a score of it that can be written as MusicXML, LilyPond 
+/// or ABC notation.
+pub fn generate_midi_and_score_with_config(file_content: &[u8], config: &GenerationConfig) -> Result<(Vec<u8>, Score), Error> {
+    let (musician, midi) = generate_song(file_content, config)?;
+    Ok((midi, musician.score()?))
 }
 
-impl From<midly::Error> for Error {
-    fn from(value: midly::Error) -> Self {
-        Self::MidlyError(value)
-    }
-}
+/// Generates the song of `generate_midi_with_config`, returning the musician 
+/// that placed its chords and tracks along with the MIDI file.
+fn generate_song(file_content: &[u8], config: &GenerationConfig) -> Result<(Music, Vec<u8>), Error> {
+    let hash = Sha256::digest(file_content);
+    let config = read_melody(file_content, config)?;
 
-impl From<&str> for Error {
-    fn from(value: &str) -> Self {
-        Self::StrError(value.to_string())
-    }
-}
+    // smoke the hash
+    let mut musician = Music::smoke_hash(hash, &config)?;
+    let midi = write_smf(musician.make_tracks(&config)?)?;
 
-impl From<serde_json::Error> for Error {
-    fn from(value: serde_json::Error) -> Self {
-        Self::SerdeError(value)
-    }
+    Ok((musician, midi))
 }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            Self::MidlyError(v) => v.to_string(),
-            Self::SerdeError(v) => v.to_string(),
-            Self::StrError(v) => v.to_string()
-        };
-        f.write_str(&s)
+/// Reads the melody of `file_content` when `config` reharmonizes a melody 
+/// without any notes. See `generate_midi_with_config`.
+fn read_melody<'a>(file_content: &[u8], config: &'a GenerationConfig) -> Result<std::borrow::Cow<'a, GenerationConfig>, Error> {
+    use music_modules_v2::analysis::MidiClip;
+    let mut config = std::borrow::Cow::Borrowed(config);
+    if config.reharmonize.as_ref().is_some_and(|reharmonizer| reharmonizer.melody.is_empty()) {
+        let clip = MidiClip::parse(file_content)?;
+        let config = config.to_mut();
+        if config.time_signature == Default::default() {
+            config.time_signature = clip.time_signature;
+        }
+        if config.key == "random" {
+            if let Some(candidate) = clip.detect_key().first() {
+                config.key = candidate.key.to_string();
+            }
+        }
+        if let Some(reharmonizer) = config.reharmonize.as_mut() {
+            reharmonizer.melody = clip.melody();
+        }
     }
+    Ok(config)
 }
 
-impl From<Error> for JsError {
-    fn from(value: Error) -> Self {
-        Self::new(&value.to_string())
+/// Parses the chord library passed in from JS, which may be JSON, TOML, or 
+/// missing.
+#[cfg(target_arch="wasm32")]
+fn parse_chord_library(chord_library: Option<String>) -> Result<ChordLibrary, Error> {
+    match chord_library.as_deref().map(str::trim) {
+        None | Some("") => Ok(ChordLibrary::default()),
+        Some(document) => ChordLibrary::parse(document)
     }
 }
 
@@ -97,6 +125,7 @@ pub fn get_chords_of_key(
     scale: &str,
     table_scheme: &str,
     show_probabilities: bool,
+    chord_library: Option<String>,
 ) -> Result<String, JsError> {
     use serde_json::json;
 
@@ -107,14 +136,18 @@ pub fn get_chords_of_key(
     let chord_selection_hashset: HashSet<String> = chord_selection.iter()
         .map(|js_val| js_val.as_string().unwrap_or_default())
         .collect();
-    let mut musician = Music::smoke_hash(Default::default(), "Cmin", &chord_selection_hashset, chord_type_group, scale, false, use_all_roots)?;
-
-    match table_scheme {
-        "contains_note" => musician.rotate_chords(key),
-        "highest_note" => musician.rearrange_by_highest_note(key),
-        "lowest_note" => musician.rearrange_by_lowest_note(key),
-        _ => return Err(JsError::new("table_scheme did not match"))
-    }
+    let config = GenerationConfig::builder()
+        .key("Cmin")
+        .chord_selection(chord_selection_hashset)
+        .chord_library(parse_chord_library(chord_library)?)
+        .chord_type_group(chord_type_group.parse()?)
+        .scale_str(scale)?
+        .table_scheme(table_scheme.parse()?)
+        .use_all_roots(use_all_roots)
+        .build();
+    let mut musician = Music::smoke_hash(Default::default(), &config)?;
+
+    musician.arrange_chord_table(key, config.table_scheme)?;
 
     if show_probabilities {
         musician.set_probabilities();
@@ -145,9 +178,10 @@ pub fn chord_finder(
     chord_type_group: &str,
     scale: &str,
     notes: Array,
-    table_scheme: &str
+    table_scheme: &str,
+    chord_library: Option<String>,
 ) -> Result<String, JsError> {
-    use music_modules_v2::{chord::Chord, utils::{parse_key, sets::{SetMath, SetOpsCollection}}};
+    use music_modules_v2::{chord_symbol::ChordSymbol, config::TableScheme, key::PitchClass, utils::sets::{SetMath, SetOpsCollection}};
     use serde_json::json;
     let use_all_roots = key.eq("random");
     if use_all_roots {
@@ -156,33 +190,41 @@ pub fn chord_finder(
     let chord_selection_hashset: HashSet<String> = chord_selection.iter()
         .map(|js_val| js_val.as_string().unwrap_or_default())
         .collect();
-    let notes_vec: Vec<usize> = notes
-        .iter()
-        .map(|js_val| parse_key(&js_val.as_string().unwrap_or_default()) as usize)
-        .collect();
+    // each entry is either a note name, or a chord symbol whose notes are all 
+    // used
+    let mut notes_vec: Vec<usize> = Vec::new();
+    for js_val in notes.iter() {
+        let note = js_val.as_string().unwrap_or_default();
+        match note.parse::<PitchClass>() {
+            Ok(pitch_class) => notes_vec.push(pitch_class.value() as usize),
+            Err(err) => match note.parse::<ChordSymbol>() {
+                Ok(symbol) => notes_vec.extend(symbol.to_chord().get_notes().iter().map(|note| *note as usize % 12)),
+                Err(_) => return Err(err.into())
+            }
+        }
+    }
     if notes_vec.is_empty() {
         return Ok(json!({}).to_string())
     }
-    let mut musician = Music::smoke_hash(Default::default(), "Cmin", &chord_selection_hashset, chord_type_group, scale, false, use_all_roots)?;
-
-    musician.rotate_chords(key);
-
-    let mut intersected_chords: HashSet<Chord> = HashSet::from_iter(musician.chord_table[notes_vec[0]].iter().cloned());
-    for note in notes_vec.iter().skip(1) {
-        intersected_chords = intersected_chords
-            .intersection(
-                &HashSet::from_iter(musician.chord_table[*note % 12]
-                    .iter()
-                    .cloned()
-                )
-            ).to_set();
-    }
-
-    match table_scheme {
-        "contains_note" => (),
-        "highest_note" => musician.rearrange_by_highest_note(key),
-        "lowest_note" => musician.rearrange_by_lowest_note(key),
-        _ => return Err(JsError::new("table_scheme did not match"))
+    let config = GenerationConfig::builder()
+        .key("Cmin")
+        .chord_selection(chord_selection_hashset)
+        .chord_library(parse_chord_library(chord_library)?)
+        .chord_type_group(chord_type_group.parse()?)
+        .scale_str(scale)?
+        .table_scheme(table_scheme.parse()?)
+        .use_all_roots(use_all_roots)
+        .build();
+    let mut musician = Music::smoke_hash(Default::default(), &config)?;
+
+    musician.rotate_chords(key)?;
+
+    let intersected_chords: HashSet<Chord> = musician.chords_containing(&notes_vec);
+
+    match config.table_scheme {
+        TableScheme::ContainsNote => (),
+        TableScheme::HighestNote => musician.rearrange_by_highest_note(key)?,
+        TableScheme::LowestNote => musician.rearrange_by_lowest_note(key)?,
     }
 
     for chords in musician.chord_table.iter_mut() {
@@ -207,6 +249,10 @@ pub fn chord_finder(
     Ok(json.to_string())
 }
 
+/// Generates a MIDI file from the settings of the web app. Every other 
+/// setting, such as the tempo, voice leading, bass line or drums, is in 
+/// `config_json`: a `GenerationConfig` as JSON, whose fields replace the ones 
+/// from the other parameters.
 #[wasm_bindgen]
 #[cfg(target_arch="wasm32")]
 pub fn generate_midi(
@@ -223,74 +269,259 @@ pub fn generate_midi(
     is_reproducible: bool,
     pattern_to_use: &str,
     duration: u32,
+    chord_library: Option<String>,
+    markov_model: Option<String>,
+    config_json: Option<String>,
 ) -> Result<Vec<u8>, Error> {
-    let hash = Sha256::digest(file_content);
-    
+    use crate::music_modules_v2::{config::GenerationMode, patterns::grammar::Pattern};
+
     let chord_selection_hashset: HashSet<String> = chord_selection.iter()
         .map(|js_val| js_val.as_string().unwrap_or_default())
         .collect();
-    let pattern_to_use: Vec<u8> = match generation_mode {
-        "chords" => {
-            use crate::music_modules_v2::patterns::validation::validate_pattern;
-
-            let (is_valid, pattern) = validate_pattern(pattern_to_use);
-            if !is_valid {
-                Vec::new()
-            } else {
-                pattern
-            }
-        },
-        _ => Vec::new(),
+    // the web app uses `--` for "no pattern", and the pattern is only used in 
+    // "chords" mode
+    let pattern: Pattern = match generation_mode.parse()? {
+        GenerationMode::Chords if !pattern_to_use.trim_matches('-').is_empty() => pattern_to_use.parse()?,
+        _ => Default::default()
     };
-    let mut max_chords = num_chords;
-    for chord_idx in pattern_to_use.iter() {
-        max_chords = max_chords.max(*chord_idx as usize);
+    let mut config = GenerationConfig::builder()
+        .key(key)
+        .chord_selection(chord_selection_hashset)
+        .chord_library(parse_chord_library(chord_library)?)
+        .chord_type_group(chord_type_group.parse()?)
+        .scale_str(scale)?
+        .is_reproducible(is_reproducible)
+        .generation_mode(generation_mode.parse()?)
+        .chord_picking_method(chord_picking_method.parse()?)
+        .num_chords(num_chords)
+        .should_use_same_chords(should_use_same_chords)
+        .min_number_of_unique_chords(min_number_of_unique_chords)
+        .pattern(pattern)
+        .duration(duration)
+        .build();
+    // a trained model from `train_markov_model`, used with the `markov` 
+    // chord picking method
+    if let Some(model) = markov_model.as_deref().map(str::trim).filter(|model| !model.is_empty()) {
+        config.markov_model = Some(serde_json::from_str(model)?);
+    }
+    // every other setting, as a `GenerationConfig` in JSON
+    if let Some(json) = config_json.as_deref().map(str::trim).filter(|json| !json.is_empty()) {
+        config = config.with_json(json)?;
     }
-    let num_chords = max_chords;
-    // smoke the hash
-    let mut musician = Music::smoke_hash(hash, key, &chord_selection_hashset, chord_type_group, scale, is_reproducible, false)?;
-    let track = musician.make_music(num_chords, generation_mode, should_use_same_chords, chord_picking_method, min_number_of_unique_chords, &pattern_to_use, duration)?;
 
-    let smf = Smf {
-        header: midly::Header { format: midly::Format::SingleTrack, timing: midly::Timing::Metrical(96.into()) },
-        tracks: vec![track]
-    };
+    generate_midi_with_config(file_content, &config)
+}
 
-    let mut output = Vec::new();
+/// Returns `[midi, score]`, where `midi` is a `Uint8Array` and `score` is a 
+/// string, so that the score is of the same song as the MIDI file.
+#[cfg(target_arch="wasm32")]
+fn midi_and_score(midi: Vec<u8>, score: String) -> Array {
+    Array::of2(&js_sys::Uint8Array::from(midi.as_slice()), &JsValue::from(score))
+}
 
-    smf.write(&mut output)?;
+/// `generate_notation` with the `musicxml` format, which was the only format 
+/// before the others were added.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn generate_musicxml(file_content: &[u8], config: &str) -> Result<Array, Error> {
+    generate_notation(file_content, config, "musicxml")
+}
 
-    Ok(output)
+/// Generates the song that `generate_midi_with_config` makes along with a 
+/// score of it written in `format`: `musicxml`, `lilypond` or `abc`, as 
+/// `[midi, score]`. `config` is a `GenerationConfig` as JSON, and missing 
+/// fields use their defaults.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn generate_notation(file_content: &[u8], config: &str, format: &str) -> Result<Array, Error> {
+    let format: NotationFormat = format.parse()?;
+    let config: GenerationConfig = serde_json::from_str(config)?;
+    let (midi, score) = generate_midi_and_score_with_config(file_content, &config)?;
+    Ok(midi_and_score(midi, score.write(format)))
 }
 
+/// Trains a Markov model for the `markov` chord picking method, returning it 
+/// as JSON. `progressions` are strings of Roman numerals or chord symbols, and 
+/// `midi_files` are the bytes of MIDI files. Chord symbols and MIDI files are 
+/// read in `key`, or in the key of their first chord when `key` is missing.
 #[wasm_bindgen]
 #[cfg(target_arch="wasm32")]
-pub fn generate_midi_chord_progression(chords: JsValue) -> Result<Vec<u8>, JsError> {
-    let chords: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(chords).expect("Bad input");
-    
+pub fn train_markov_model(progressions: Array, midi_files: Array, order: usize, key: Option<String>) -> Result<String, Error> {
+    use music_modules_v2::markov::MarkovModel;
+    let key = match key.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(key) => Some(parse_key_signature(key)?)
+    };
+    let mut model = MarkovModel::new(order);
+    for progression in progressions.iter() {
+        let progression = progression
+            .as_string()
+            .ok_or_else(|| Error::InvalidInput("expected each progression to be a string".to_string()))?;
+        model.train_progression(&progression, key)?;
+    }
+    for midi in midi_files.iter() {
+        model.train_midi(&js_sys::Uint8Array::new(&midi).to_vec(), key)?;
+    }
+    Ok(serde_json::to_string(&model)?)
+}
+
+/// Returns the pitch classes of a scale on C, where `scale` is either the name 
+/// of a scale, or a custom scale such as `2-1-2-2-1-2-2` or 
+/// `0, 2, 3, 5, 7, 8, 10`. `disabled` has no notes.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn get_scale_notes(scale: &str) -> Result<Vec<u8>, Error> {
+    let config = GenerationConfig::builder().scale_str(scale)?.build();
+    Ok(config.scale_notes(&Default::default())?.map(|notes| notes.into()).unwrap_or_default())
+}
+
+/// Writes a MIDI file where each chord is played for 4 beats. 
+pub fn write_chord_progression(chords: &[Vec<usize>]) -> Result<Vec<u8>, Error> {
     let mut track = MidiFile::new();
     let mut time = 0f64;
     for chord in chords.iter() {
         for note in chord {
+            if *note > 127 {
+                return Err(Error::InvalidInput(format!("{} is not a MIDI note number between 0 and 127", note)));
+            }
             track.add_note_beats(*note as u8, time, 4f64, 80);
         }
         time += 4f64;
     }
-    let track = track.finalize();
-    let smf = Smf {
-        header: midly::Header { format: midly::Format::SingleTrack, timing: midly::Timing::Metrical(96.into()) },
-        tracks: vec![track]
-    };
-    let mut output = Vec::new();
-    smf.write(&mut output).expect("Should be valid");
-    Ok(output)
+    write_smf(vec![track.finalize()])
+}
+
+/// Writes a MIDI file from chord symbols such as `Cm7 - Fm9 - Bb13 - Ebmaj7`, 
+/// where each chord is played for 4 beats. 
+pub fn generate_midi_from_chord_symbols(progression: &str) -> Result<Vec<u8>, Error> {
+    let chords = parse_progression(progression)?
+        .iter()
+        .map(|symbol| symbol
+            .to_chord()
+            .get_notes_vec()
+            .iter()
+            .map(|note| *note as usize)
+            .collect()
+        )
+        .collect::<Vec<Vec<usize>>>();
+    write_chord_progression(&chords)
+}
+
+/// Writes a MIDI file from Roman numerals such as `ii7-V7-Imaj7` in `key`, 
+/// where each chord is played for 4 beats.
+pub fn generate_midi_from_roman_numerals(progression: &str, key: &str) -> Result<Vec<u8>, Error> {
+    let chords = realize_progression(progression, &parse_key_signature(key)?)?
+        .iter()
+        .map(|chord| chord.get_notes_vec().iter().map(|note| *note as usize).collect())
+        .collect::<Vec<Vec<usize>>>();
+    write_chord_progression(&chords)
+}
+
+/// `chords` is either an array of arrays of MIDI note numbers, or a string of 
+/// chord symbols such as `Cm7 - Fm9 - Bb13 - Ebmaj7`.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn generate_midi_chord_progression(chords: JsValue) -> Result<Vec<u8>, Error> {
+    if let Some(progression) = chords.as_string() {
+        return generate_midi_from_chord_symbols(&progression);
+    }
+    let chords: Vec<Vec<usize>> = serde_wasm_bindgen::from_value(chords)
+        .map_err(|e| Error::InvalidInput(format!("expected an array of arrays of MIDI note numbers or a string of chord symbols: {}", e)))?;
+    write_chord_progression(&chords)
+}
+
+/// Recognizes the chords of a MIDI file, returning them as JSON along with 
+/// their start times and lengths in beats, bass notes and confidence. 
+/// `segmentation` is either `bar` or `onsets`.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn recognize_chords(file_content: &[u8], segmentation: &str) -> Result<String, Error> {
+    use music_modules_v2::analysis::recognize_midi_chords;
+    let chords = recognize_midi_chords(file_content, segmentation.parse()?)?
+        .iter()
+        .map(|chord| {
+            let mut json = serde_json::to_value(chord)?;
+            json["name"] = chord.name().into();
+            Ok(json)
+        })
+        .collect::<Result<Vec<serde_json::Value>, Error>>()?;
+    Ok(serde_json::to_string(&chords)?)
+}
+
+/// Returns the key candidates as JSON, where `key` and `scale` can be passed 
+/// to `generate_midi` to prune the chord table to the detected scale.
+#[cfg(target_arch="wasm32")]
+fn key_candidates_json(candidates: &[music_modules_v2::key_detection::KeyCandidate]) -> Result<String, Error> {
+    use serde_json::json;
+    let candidates: Vec<serde_json::Value> = candidates
+        .iter()
+        .map(|candidate| json!({
+            "name": candidate.to_string(),
+            "key": candidate.key.to_string(),
+            "scale": candidate.scale.to_string(),
+            "correlation": candidate.correlation
+        }))
+        .collect();
+    Ok(serde_json::to_string(&candidates)?)
+}
+
+/// Ranks the keys and scales that the notes of a MIDI file could be in, 
+/// returning them as JSON with the best key first.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn detect_key_of_midi(file_content: &[u8]) -> Result<String, Error> {
+    use music_modules_v2::analysis::MidiClip;
+    key_candidates_json(&MidiClip::parse(file_content)?.detect_key())
+}
+
+/// Ranks the keys and scales that a list of MIDI notes could be in, returning 
+/// them as JSON with the best key first.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn detect_key_of_notes(notes: Vec<u8>) -> Result<String, Error> {
+    use music_modules_v2::key_detection::detect_key;
+    let notes: Vec<(u8, f64)> = notes.into_iter().map(|note| (note, 1.0)).collect();
+    key_candidates_json(&detect_key(&notes))
+}
+
+/// Parses chord symbols such as `Cm7 - Fm9 - Bb13 - Ebmaj7`, returning the 
+/// chords as JSON.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn parse_chord_symbols(progression: &str) -> Result<String, Error> {
+    let chords: Vec<Chord> = parse_progression(progression)?
+        .iter()
+        .map(|symbol| symbol.to_chord())
+        .collect();
+    Ok(serde_json::to_string(&chords)?)
+}
+
+/// Realizes Roman numerals such as `ii7-V7-Imaj7` in `key`, returning the 
+/// chords as JSON.
+#[wasm_bindgen]
+#[cfg(target_arch="wasm32")]
+pub fn parse_roman_numerals(progression: &str, key: &str) -> Result<String, Error> {
+    use music_modules_v2::roman;
+    let key = parse_key_signature(key)?;
+    let chords = roman::parse_progression(progression)?
+        .iter()
+        .map(|numeral| {
+            let mut chord = serde_json::to_value(numeral.to_chord(&key)?)?;
+            // the chord is spelled from its own root, so the numeral is taken 
+            // from the progression
+            chord["numeral"] = numeral.to_string().into();
+            chord["function"] = serde_json::to_value(numeral.function())?;
+            Ok(chord)
+        })
+        .collect::<Result<Vec<serde_json::Value>, Error>>()?;
+    Ok(serde_json::to_string(&chords)?)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod test_utils {
     use super::*;
     
-    pub const GENERATION_MODES: [&str; 5] = ["melody", "chords", "intended", "melody v2", "melody_v3"];
+    pub const GENERATION_MODES: [&str; 5] = ["melody", "chords", "intended", "melody v2", "melody v3"];
     pub const FILENAMES: [&str; 5] = ["melody_", "chords_", "intended_", "melody_v2_", "melody_v3_"];
 
     /// Rust variant for testing.
@@ -305,23 +536,18 @@ pub mod test_utils {
         chord_picking_method: &str,
         min_number_of_unique_chords: u32,
     ) -> Result<Vec<u8>, Error> {
-        let hash = Sha256::digest(file_content);
-        
-        let chord_selection_hashset: HashSet<String> = HashSet::new();
-        // smoke the hash
-        let mut musician = Music::smoke_hash(hash, key, &chord_selection_hashset, chord_type_group, "disabled", true, false).unwrap();
-        let track = musician.make_music(num_chords as usize, generation_mode, should_use_same_chords, chord_picking_method, min_number_of_unique_chords, &Vec::new(), 4).unwrap();
-
-        let smf = Smf {
-            header: midly::Header { format: midly::Format::SingleTrack, timing: midly::Timing::Metrical(96.into()) },
-            tracks: vec![track]
-        };
-
-        let mut output = Vec::new();
-
-        smf.write(&mut output)?;
-
-        Ok(output)
+        let config = GenerationConfig::builder()
+            .key(key)
+            .chord_type_group(chord_type_group.parse()?)
+            .is_reproducible(true)
+            .generation_mode(generation_mode.parse()?)
+            .chord_picking_method(chord_picking_method.parse()?)
+            .num_chords(num_chords as usize)
+            .should_use_same_chords(should_use_same_chords)
+            .min_number_of_unique_chords(min_number_of_unique_chords)
+            .build();
+
+        generate_midi_with_config(file_content, &config)
     }
 
     pub fn generate_midi_all_chord_types(
@@ -333,21 +559,17 @@ pub mod test_utils {
         min_number_of_unique_chords: u32,
     ) -> Vec<u8> {
         let hash = Sha256::digest(text_input.as_bytes());
+        let config = GenerationConfig::builder()
+            .generation_mode(generation_mode.parse().unwrap())
+            .chord_picking_method(chord_picking_method.parse().unwrap())
+            .num_chords(num_chords)
+            .should_use_same_chords(false)
+            .min_number_of_unique_chords(min_number_of_unique_chords)
+            .build();
         let mut musician = Music::smoke_hash_all_custom_handpicked_chords(hash, key);
-        let track = musician.make_music(num_chords, generation_mode, false, chord_picking_method, min_number_of_unique_chords, &Vec::new(), 4).unwrap();
-
-        let smf = Smf {
-            header: midly::Header {
-                format: midly::Format::SingleTrack,
-                timing: midly::Timing::Metrical(96.into())
-            },
-            tracks: vec![track]
-        };
-
-        let mut output = Vec::new();
-        smf.write(&mut output).unwrap();
+        let track = musician.make_music(&config).unwrap();
 
-        output
+        write_smf(vec![track]).unwrap()
     }
 
     /// A shorthand way to create a MIDI file for a test.
@@ -420,4 +642,138 @@ mod tests {
         seed.copy_from_slice(&hash);
         let mut _rng = StdRng::from_seed(seed);
     }
+
+    #[test]
+    fn chord_symbol_progressions() {
+        let from_symbols = generate_midi_from_chord_symbols("Cm - Fm7").unwrap();
+        let from_notes = write_chord_progression(&[vec![48, 51, 55], vec![53, 56, 60, 63]]).unwrap();
+        assert_eq!(from_symbols, from_notes);
+        assert!(matches!(generate_midi_from_chord_symbols("Cm - Q"), Err(Error::InvalidChordSymbol { .. })));
+    }
+
+    #[test]
+    fn roman_numeral_progressions() {
+        let from_numerals = generate_midi_from_roman_numerals("ii7-V7-Imaj7", "Bbmaj").unwrap();
+        let from_symbols = generate_midi_from_chord_symbols("Cm7 - F7 - Bbmaj7").unwrap();
+        assert_eq!(from_numerals, from_symbols);
+        assert!(matches!(generate_midi_from_roman_numerals("ii7 - VIII", "Cmaj"), Err(Error::InvalidRomanNumeral { .. })));
+    }
+
+    #[test]
+    fn chord_track_settings() {
+        use midly::{MetaMessage, Smf, TrackEventKind};
+        use music_modules_v2::midi::TrackSettings;
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .chord_track(TrackSettings::new("chords", 2, Some(4)))
+            .build();
+        let bytes = generate_midi_with_config(b"tracks", &config).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.tracks[0][0].kind, TrackEventKind::Meta(MetaMessage::TrackName(b"chords")));
+        assert!(smf.tracks[0].iter().all(|event| match event.kind {
+            TrackEventKind::Midi { channel, .. } => channel == 2,
+            _ => true
+        }));
+
+        let config = GenerationConfig { chord_track: TrackSettings { channel: 16, ..Default::default() }, ..config };
+        assert!(generate_midi_with_config(b"tracks", &config).is_err());
+    }
+
+    #[test]
+    fn meta_events() {
+        use midly::{MetaMessage, Smf, TrackEventKind};
+        let config = GenerationConfig::builder()
+            .key("Ebmaj")
+            .title("Song")
+            .bpm(90.0)
+            .time_signature("3/4".parse().unwrap())
+            .build();
+        let bytes = generate_midi_with_config(b"meta", &config).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        let meta: Vec<MetaMessage> = smf.tracks[0]
+            .iter()
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Meta(message) => Some(message),
+                _ => None
+            })
+            .collect();
+        assert_eq!(meta, [
+            MetaMessage::TrackName(b"Song"),
+            MetaMessage::Tempo(666_667.into()),
+            MetaMessage::TimeSignature(3, 2, 24, 8),
+            MetaMessage::KeySignature(-3, false),
+            MetaMessage::EndOfTrack,
+        ]);
+        assert_eq!(smf.tracks[0].last().unwrap().kind, TrackEventKind::Meta(MetaMessage::EndOfTrack));
+
+        let config = GenerationConfig { bpm: 0.0, ..config };
+        assert!(generate_midi_with_config(b"meta", &config).is_err());
+    }
+
+    #[test]
+    fn reharmonizing_keeps_the_time_signature() {
+        use music_modules_v2::reharmonize::Reharmonizer;
+        let three_four = "3/4".parse().unwrap();
+        let upload = generate_midi_from_chord_symbols("C - F - G - C").unwrap();
+        let config = GenerationConfig::builder().time_signature(three_four).reharmonize(Reharmonizer::default()).build();
+        assert_eq!(read_melody(&upload, &config).unwrap().time_signature, three_four);
+
+        // the time signature of the file replaces the default
+        let upload = generate_midi_with_config(b"waltz", &GenerationConfig::builder().time_signature(three_four).build()).unwrap();
+        let config = GenerationConfig::builder().reharmonize(Reharmonizer::default()).build();
+        assert_eq!(read_melody(&upload, &config).unwrap().time_signature, three_four);
+    }
+
+    #[test]
+    fn musicxml_alongside_midi() {
+        let config = GenerationConfig::builder().key("Ebmaj").title("Song").build();
+        let (midi, score) = generate_midi_and_score_with_config(b"score", &config).unwrap();
+        assert_eq!(midi, generate_midi_with_config(b"score", &config).unwrap());
+        let musicxml = score.write(NotationFormat::MusicXml);
+        assert!(musicxml.contains("<work-title>Song</work-title>"));
+        assert!(musicxml.contains("<harmony>"));
+    }
+
+    #[test]
+    fn lilypond_and_abc_for_every_mode() {
+        use music_modules_v2::config::GenerationMode;
+
+        for mode in [GenerationMode::Chords, GenerationMode::MelodyV2, GenerationMode::MelodyV3] {
+            let config = GenerationConfig::builder().key("Ebmaj").title("Song").generation_mode(mode).build();
+            let (_, score) = generate_midi_and_score_with_config(b"notation", &config).unwrap();
+            let lilypond = score.write(NotationFormat::LilyPond);
+            assert!(lilypond.contains("\\chordmode {"), "{}", lilypond);
+            assert!(lilypond.contains("\\key ees \\major"), "{}", lilypond);
+            let abc = score.write(NotationFormat::Abc);
+            assert!(abc.contains("T:Song\n"), "{}", abc);
+            assert!(abc.contains("K:Eb\n"), "{}", abc);
+            assert!(abc.trim_end().ends_with("|]"), "{}", abc);
+        }
+    }
+
+    #[test]
+    fn scores_need_whole_16ths_in_a_bar() {
+        for time_signature in ["6/8", "7/16", "1/64", "3/32"] {
+            let config = GenerationConfig::builder().time_signature(time_signature.parse().unwrap()).build();
+            let score = generate_midi_and_score_with_config(b"meter", &config);
+            match time_signature {
+                "1/64" | "3/32" => assert!(matches!(score, Err(Error::InvalidTimeSignature { .. })), "{}", time_signature),
+                _ => assert!(score.unwrap().1.to_abc().contains(&format!("M:{}\n", time_signature)), "{}", time_signature)
+            }
+        }
+    }
+
+    #[test]
+    fn markov_chord_picking() {
+        use music_modules_v2::config::{ChordPickingMethod, GenerationMode};
+        let config = GenerationConfig::builder()
+            .key("Ebmaj")
+            .generation_mode(GenerationMode::Chords)
+            .chord_picking_method(ChordPickingMethod::Markov)
+            .num_chords(8)
+            .build();
+        let first = generate_midi_with_config(b"seed 1", &config).unwrap();
+        assert_eq!(first, generate_midi_with_config(b"seed 1", &config).unwrap());
+        assert_ne!(first, generate_midi_with_config(b"seed 2", &config).unwrap());
+    }
 }
\ No newline at end of file
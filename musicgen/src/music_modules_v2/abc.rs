@@ -0,0 +1,132 @@
+//! ABC notation export.
+//!
+//! Writes a `Score` as an ABC tune, with a voice for each part and the chords
+//! as chord symbols above the first voice. The unit note length is a
+//! sixteenth note, so every duration is a whole number of units.
+
+use super::{key::Mode, score::{Accidentals, Clef, Score, ScoreEvent}};
+
+/// The number of measures on each line of a voice.
+const MEASURES_PER_LINE: usize = 4;
+
+/// Removes the characters of `text` that would end a quoted ABC string.
+fn escape(text: &str) -> String {
+    text.replace('"', "'").replace('\n', " ")
+}
+
+impl Score {
+    /// Writes the score as an ABC tune.
+    pub fn to_abc(&self) -> String {
+        let mut out = String::from("X:1\n");
+        if let Some(title) = self.title.as_ref() {
+            out.push_str(&format!("T:{}\n", escape(title)));
+        }
+        out.push_str(&format!("M:{}/{}\nL:1/16\nQ:1/4={}\n", self.time_signature.numerator(), self.time_signature.denominator(), self.bpm.round()));
+        for (index, part) in self.parts.iter().enumerate() {
+            let clef = match part.clef() {
+                Clef::Percussion => "perc",
+                Clef::Bass => "bass",
+                Clef::Treble => "treble"
+            };
+            out.push_str(&format!("V:{} name=\"{}\" clef={}\n", index + 1, escape(&self.part_name(index)), clef));
+        }
+        let key = self.key_signature_tonic();
+        out.push_str(&format!("K:{}{}\n", key.tonic_name(), if key.mode == Mode::Minor { "m" } else { "" }));
+        for index in 0..self.parts.len() {
+            self.write_voice(&mut out, index);
+        }
+        out
+    }
+
+    /// Writes the part at `index` as a voice.
+    fn write_voice(&self, out: &mut String, index: usize) {
+        let mut accidentals = Accidentals::new(self.key_signature.0);
+        let measures: Vec<String> = self.measures(index)
+            .iter()
+            .map(|events| {
+                accidentals.clear();
+                let events: Vec<String> = events.iter().map(|event| self.abc_event(event, &mut accidentals)).collect();
+                events.join(" ")
+            })
+            .collect();
+        out.push_str(&format!("[V:{}]\n", index + 1));
+        let lines: Vec<&[String]> = measures.chunks(MEASURES_PER_LINE).collect();
+        for (i, line) in lines.iter().enumerate() {
+            let end = if i + 1 == lines.len() { " |]" } else { " |" };
+            out.push_str(&format!("{}{}\n", line.join(" | "), end));
+        }
+    }
+
+    /// A note, chord or rest with its chord symbols, such as `"C major"C4-`,
+    /// `[_EG-]12` or `z2`.
+    fn abc_event(&self, event: &ScoreEvent, accidentals: &mut Accidentals) -> String {
+        let symbols: String = event.chords.iter().map(|chord| format!("\"{}\"", escape(&chord.name))).collect();
+        let duration = match event.duration {
+            1 => String::new(),
+            duration => duration.to_string()
+        };
+        let notes: Vec<String> = event.notes
+            .iter()
+            .map(|note| {
+                let (name, octave) = self.spell(note.pitch);
+                let accidental = match accidentals.next(name, octave, note.tie_stop) {
+                    Some(-2) => "__",
+                    Some(-1) => "_",
+                    Some(1) => "^",
+                    Some(2) => "^^",
+                    Some(_) => "=",
+                    None => ""
+                };
+                let pitch = match octave {
+                    octave if octave >= 5 => format!("{}{}", name.letter().to_ascii_lowercase(), "'".repeat(octave as usize - 5)),
+                    octave => format!("{}{}", name.letter(), ",".repeat((4 - octave) as usize))
+                };
+                (format!("{}{}", accidental, pitch), note.tie_start)
+            })
+            .map(|(pitch, tie_start)| match (event.notes.len(), tie_start) {
+                (1, true) => format!("{}{}-", pitch, duration),
+                (1, false) => format!("{}{}", pitch, duration),
+                (_, true) => format!("{}-", pitch),
+                (_, false) => pitch
+            })
+            .collect();
+        let event = match notes.len() {
+            0 => format!("z{}", duration),
+            1 => notes[0].clone(),
+            _ => format!("[{}]{}", notes.concat(), duration)
+        };
+        symbols + &event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_modules_v2::{meter::TimeSignature, midi::TrackSettings, music::PlacedChord, score::{ScoreNote, ScorePart}};
+
+    #[test]
+    fn voices_accidentals_and_ties() {
+        let note = |pitch: u8, start: f64, length: f64| ScoreNote { pitch, start, length };
+        let score = Score {
+            title: Some("Blues".to_string()),
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+            key_signature: (-3, true),
+            key: "Cmin".parse().unwrap(),
+            chords: vec![PlacedChord { name: "C major".to_string(), root: 0, pitch_classes: vec![0, 4, 7], start: 0.0, length: 6.0 }],
+            parts: vec![
+                ScorePart {
+                    settings: TrackSettings::default(),
+                    notes: vec![note(60, 0.0, 6.0), note(64, 0.0, 6.0), note(63, 6.0, 1.0), note(64, 7.0, 1.0)],
+                },
+                ScorePart { settings: TrackSettings::new("Bass", 1, None), notes: vec![note(36, 0.0, 1.0), note(72, 1.0, 0.25)] },
+            ],
+        };
+        let abc = score.to_abc();
+        assert!(abc.starts_with("X:1\nT:Blues\nM:4/4\nL:1/16\nQ:1/4=120\nV:1 name=\"Chords\" clef=treble\nV:2 name=\"Bass\" clef=bass\nK:Cm\n"), "{}", abc);
+        // the E natural is tied over the barline, so the E flat after it
+        // needs an accidental
+        assert!(abc.contains("[V:1]\n\"C major\"[C-=E-]16 | [CE]8 _E4 =E4 |]\n"), "{}", abc);
+        assert!(abc.contains("[V:2]\nC,,4 c z8 z3 | z16 |]\n"), "{}", abc);
+    }
+}
@@ -0,0 +1,330 @@
+//! Reading MIDI files and recognizing their chords.
+//!
+//! A MIDI clip is cut into segments, either one per bar or one per group of
+//! notes that start together, and the notes that sound in each segment are
+//! matched against the chord types of the crate. A chord can be recognized
+//! in any inversion, and without its fifth.
+
+use std::collections::HashMap;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use serde::Serialize;
+
+use super::{chord::Chord, chord_type::{recognizable_chord_types, ChordType}, config::Segmentation, error::Error, key::PitchClass, meter::{Meter, TimeSignature}};
+
+/// Notes that start less than this many beats apart are grouped together by
+/// `Segmentation::Onsets`.
+const ONSET_TOLERANCE: f64 = 0.125;
+
+/// Chords that explain less of a segment than this are not recognized.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// How much less confident a chord without its fifth is.
+const MISSING_FIFTH_PENALTY: f64 = 0.1;
+
+/// How much a chord whose root is in the bass is preferred, when ranking the
+/// chords that fit a segment.
+const ROOT_IN_BASS_BONUS: f64 = 0.1;
+
+/// A note of a MIDI file. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImportedNote {
+    pub start: f64,
+    pub length: f64,
+    pub pitch: u8,
+    pub velocity: u8,
+    pub channel: u8,
+}
+
+/// The notes of a MIDI file, from every track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiClip {
+    /// The notes, sorted by their start times. The drum channel is left out.
+    pub notes: Vec<ImportedNote>,
+    /// The first time signature of the file, or 4/4 when it does not have one.
+    pub time_signature: TimeSignature,
+}
+
+impl MidiClip {
+    /// Reads a Standard MIDI File.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let smf = Smf::parse(bytes)?;
+        let ticks_per_beat = match smf.header.timing {
+            Timing::Metrical(ticks) => ticks.as_int() as f64,
+            Timing::Timecode(..) => return Err(Error::InvalidInput("MIDI files that are timed in SMPTE frames are not supported".to_string()))
+        };
+        let mut notes = Vec::new();
+        let mut time_signature = None;
+        for track in smf.tracks.iter() {
+            let mut time = 0u64;
+            // the start times and velocities of the notes that are playing
+            let mut playing: HashMap<(u8, u8), Vec<(u64, u8)>> = HashMap::new();
+            for event in track {
+                time += event.delta.as_int() as u64;
+                let (channel, key, velocity) = match event.kind {
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, ..)) => {
+                        if time_signature.is_none() && denominator < 8 {
+                            time_signature = TimeSignature::new(numerator, 1 << denominator).ok();
+                        }
+                        continue;
+                    },
+                    TrackEventKind::Midi { channel, message: MidiMessage::NoteOn { key, vel } } => (channel.as_int(), key.as_int(), vel.as_int()),
+                    TrackEventKind::Midi { channel, message: MidiMessage::NoteOff { key, .. } } => (channel.as_int(), key.as_int(), 0),
+                    _ => continue
+                };
+                if channel == 9 {
+                    continue;
+                }
+                let starts = playing.entry((channel, key)).or_default();
+                match velocity {
+                    0 => if !starts.is_empty() {
+                        let (start, velocity) = starts.remove(0);
+                        notes.push(ImportedNote {
+                            start: start as f64 / ticks_per_beat,
+                            length: (time - start) as f64 / ticks_per_beat,
+                            pitch: key,
+                            velocity,
+                            channel,
+                        });
+                    },
+                    velocity => starts.push((time, velocity))
+                }
+            }
+        }
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.pitch.cmp(&b.pitch)));
+        Ok(MidiClip { notes, time_signature: time_signature.unwrap_or_default() })
+    }
+
+    /// The time that the last note ends, in beats.
+    pub fn length(&self) -> f64 {
+        self.notes.iter().map(|note| note.start + note.length).fold(0.0, f64::max)
+    }
+
+    /// Returns the start and end times of the segments of the clip.
+    pub fn segments(&self, segmentation: Segmentation) -> Vec<(f64, f64)> {
+        let length = self.length();
+        let starts: Vec<f64> = match segmentation {
+            Segmentation::Bar => {
+                let bar = Meter::new(self.time_signature, 1.0).unwrap_or_default().bar_length();
+                (0..).map(|i| i as f64 * bar).take_while(|start| *start < length).collect()
+            },
+            Segmentation::Onsets => {
+                let mut starts: Vec<f64> = Vec::new();
+                for note in self.notes.iter() {
+                    if starts.last().is_none_or(|start| note.start - start > ONSET_TOLERANCE) {
+                        starts.push(note.start);
+                    }
+                }
+                starts
+            }
+        };
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| (*start, starts.get(i + 1).copied().unwrap_or(length)))
+            .collect()
+    }
+
+    /// Recognizes the chords of the clip against `chord_types`. Segments
+    /// without a chord are left out, and a chord that lasts several segments
+    /// is only listed once.
+    pub fn recognize_chords(&self, segmentation: Segmentation, chord_types: &[ChordType]) -> Vec<RecognizedChord> {
+        let mut chords: Vec<RecognizedChord> = Vec::new();
+        for (start, end) in self.segments(segmentation) {
+            // each note is weighted by how long it sounds in the segment
+            let notes: Vec<(u8, f64)> = self.notes
+                .iter()
+                .map(|note| (note.pitch, (note.start + note.length).min(end) - note.start.max(start)))
+                .filter(|(_, length)| *length > 1e-9)
+                .collect();
+            let Some(mut chord) = recognize_chord(&notes, chord_types) else {
+                continue;
+            };
+            chord.start = start;
+            chord.length = end - start;
+            match chords.last_mut() {
+                Some(last) if last.is_same_chord(&chord) && (last.start + last.length - start).abs() < 1e-9 => {
+                    last.confidence = (last.confidence * last.length + chord.confidence * chord.length) / (last.length + chord.length);
+                    last.length += chord.length;
+                },
+                _ => chords.push(chord)
+            }
+        }
+        chords
+    }
+}
+
+/// A chord that was recognized in a MIDI clip.
+#[derive(Clone, Debug, Serialize)]
+pub struct RecognizedChord {
+    /// The start time in beats.
+    pub start: f64,
+    /// The length in beats.
+    pub length: f64,
+    pub chord: Chord,
+    /// The pitch class of the lowest note, when it is not the root.
+    pub bass: Option<u8>,
+    /// Which chord tone is in the bass, from `0` for the root position. A
+    /// bass note that is not a chord tone is also `0`.
+    pub inversion: u8,
+    pub is_missing_fifth: bool,
+    /// How much of the segment the chord explains, from 0 to 1.
+    pub confidence: f64,
+}
+
+impl RecognizedChord {
+    /// The name of the chord, followed by the bass note when it is not the
+    /// root, such as `Ab major 7/C`.
+    pub fn name(&self) -> String {
+        match self.bass {
+            Some(bass) => format!("{}/{}", self.chord.get_name(), self.chord.key_signature.spell(PitchClass::new(bass as i16))),
+            None => self.chord.get_name()
+        }
+    }
+
+    fn is_same_chord(&self, other: &RecognizedChord) -> bool {
+        self.chord == other.chord && self.bass == other.bass
+    }
+}
+
+/// Recognizes the chord of `notes`, which are pairs of MIDI notes and their
+/// weights, such as how long they sound. The chord types are matched on every
+/// root, and all of their notes have to be there, except for the fifth.
+/// Returns `None` when fewer than two pitch classes sound, or when no chord
+/// type explains enough of the notes.
+pub fn recognize_chord(notes: &[(u8, f64)], chord_types: &[ChordType]) -> Option<RecognizedChord> {
+    let mut weights = [0.0; 12];
+    for (pitch, weight) in notes {
+        weights[(pitch % 12) as usize] += weight;
+    }
+    let total: f64 = weights.iter().sum();
+    if weights.iter().filter(|weight| **weight > 0.0).count() < 2 {
+        return None;
+    }
+    let bass = notes.iter().map(|(pitch, _)| *pitch).min()? % 12;
+
+    let mut best: Option<(f64, RecognizedChord)> = None;
+    for chord_type in chord_types {
+        let mut intervals: Vec<u8> = chord_type.note_intervals.iter().map(|interval| interval % 12).collect();
+        intervals.sort_unstable();
+        intervals.dedup();
+        if intervals.len() < 2 {
+            continue;
+        }
+        for root in 0..12u8 {
+            let is_sounding = |interval: u8| weights[((root + interval) % 12) as usize] > 0.0;
+            let missing: Vec<u8> = intervals.iter().copied().filter(|interval| !is_sounding(*interval)).collect();
+            let is_missing_fifth = match missing[..] {
+                [] => false,
+                // a triad without its fifth is still two notes
+                [7] if intervals.len() >= 3 => true,
+                _ => continue
+            };
+            let explained: f64 = intervals.iter().map(|interval| weights[((root + interval) % 12) as usize]).sum::<f64>() / total;
+            let confidence = (explained - if is_missing_fifth { MISSING_FIFTH_PENALTY } else { 0.0 }).max(0.0);
+            if confidence < MIN_CONFIDENCE {
+                continue;
+            }
+            let bass_interval = (bass + 12 - root) % 12;
+            // simpler chords are preferred when they explain as much
+            let score = confidence
+                + if bass_interval == 0 { ROOT_IN_BASS_BONUS } else { 0.0 }
+                - 0.001 * intervals.len() as f64;
+            if best.as_ref().is_none_or(|(best_score, _)| score > *best_score + 1e-9) {
+                best = Some((score, RecognizedChord {
+                    start: 0.0,
+                    length: 0.0,
+                    chord: Chord::new(root, chord_type),
+                    bass: (bass_interval != 0).then_some(bass),
+                    inversion: intervals.iter().position(|interval| *interval == bass_interval).unwrap_or(0) as u8,
+                    is_missing_fifth,
+                    confidence,
+                }));
+            }
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+/// Reads a MIDI file and recognizes its chords against the chord types of
+/// `recognizable_chord_types`.
+pub fn recognize_midi_chords(bytes: &[u8], segmentation: Segmentation) -> Result<Vec<RecognizedChord>, Error> {
+    Ok(MidiClip::parse(bytes)?.recognize_chords(segmentation, &recognizable_chord_types()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_modules_v2::midi::{write_smf, MidiFile};
+
+    fn recognize(notes: &[u8]) -> Option<RecognizedChord> {
+        let notes: Vec<(u8, f64)> = notes.iter().map(|note| (*note, 1.0)).collect();
+        recognize_chord(&notes, &recognizable_chord_types())
+    }
+
+    #[test]
+    fn chords_inversions_and_missing_fifths() {
+        let chord = recognize(&[60, 64, 67]).unwrap();
+        assert_eq!((chord.chord.root, chord.chord.chord_type.name.as_str()), (0, "major"));
+        assert_eq!((chord.bass, chord.inversion, chord.confidence), (None, 0, 1.0));
+
+        // the first inversion of A minor
+        let chord = recognize(&[48, 57, 64]).unwrap();
+        assert_eq!((chord.chord.root, chord.chord.chord_type.name.as_str()), (9, "minor"));
+        assert_eq!((chord.bass, chord.inversion), (Some(0), 1));
+        assert_eq!(chord.name(), "A minor/C");
+
+        // a dominant 7th without its fifth
+        let chord = recognize(&[43, 59, 65]).unwrap();
+        assert_eq!((chord.chord.root, chord.chord.chord_type.name.as_str()), (7, "dominant 7"));
+        assert!(chord.is_missing_fifth);
+        assert!(chord.confidence < 1.0);
+
+        // the bass decides between chords with the same notes
+        assert_eq!(recognize(&[48, 57, 64, 67]).unwrap().chord.chord_type.name, "major 6");
+        assert_eq!(recognize(&[45, 60, 64, 67]).unwrap().chord.chord_type.name, "minor 7");
+
+        assert!(recognize(&[60]).is_none());
+        assert!(recognize(&[60, 61, 62, 63]).is_none());
+    }
+
+    #[test]
+    fn reading_and_segmenting_midi() {
+        let mut track = MidiFile::new();
+        // C major for a bar, then G major, then an arpeggio of F major
+        for note in [48, 52, 55] {
+            track.add_note_beats(note, 0.0, 4.0, 80);
+        }
+        for note in [43, 59, 62] {
+            track.add_note_beats(note, 4.0, 2.0, 80);
+            track.add_note_beats(note, 6.0, 2.0, 80);
+        }
+        for (i, note) in [53, 57, 60, 65].iter().enumerate() {
+            track.add_note_beats(*note, 8.0 + i as f64, 4.0 - i as f64, 80);
+        }
+        let mut drums = MidiFile::with_settings(crate::music_modules_v2::midi::TrackSettings::new("drums", 9, None));
+        drums.add_note_beats(61, 0.0, 12.0, 100);
+        let bytes = write_smf(vec![track.finalize(), drums.finalize()]).unwrap();
+
+        let clip = MidiClip::parse(&bytes).unwrap();
+        assert_eq!(clip.notes.len(), 13);
+        assert_eq!(clip.length(), 12.0);
+        assert_eq!(clip.segments(Segmentation::Bar), [(0.0, 4.0), (4.0, 8.0), (8.0, 12.0)]);
+        assert_eq!(clip.segments(Segmentation::Onsets).len(), 7);
+
+        let names = |segmentation| -> Vec<(f64, f64, String)> {
+            recognize_midi_chords(&bytes, segmentation)
+                .unwrap()
+                .iter()
+                .map(|chord| (chord.start, chord.length, chord.name()))
+                .collect()
+        };
+        let expected = [(0.0, 4.0, "C major".to_string()), (4.0, 4.0, "G major".to_string()), (8.0, 4.0, "F major".to_string())];
+        assert_eq!(names(Segmentation::Bar), expected);
+        // the first note of the arpeggio is not a chord on its own, and the
+        // repeated G major chord is merged
+        assert_eq!(names(Segmentation::Onsets), [expected[0].clone(), expected[1].clone(), (9.0, 3.0, "F major".to_string())]);
+
+        assert!(MidiClip::parse(b"not midi").is_err());
+    }
+}
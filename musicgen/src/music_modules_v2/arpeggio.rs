@@ -0,0 +1,152 @@
+//! Arpeggios.
+//!
+//! An arpeggio plays the notes of a chord one at a time, at a fixed rate, and
+//! repeats them until the chord ends.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::{ArpeggioOrder, ArpeggioRate}, error::Error, utils::MathMagician};
+
+/// The settings for `GenerationMode::Arpeggio`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Arpeggio {
+    pub order: ArpeggioOrder,
+    pub rate: ArpeggioRate,
+    /// How long each note is held, as a fraction of the time between notes.
+    /// Values above 1 make the notes overlap.
+    pub gate: f64,
+    /// The number of octaves that the notes are spread over.
+    pub octaves: u8,
+}
+
+impl Default for Arpeggio {
+    fn default() -> Self {
+        Arpeggio { order: ArpeggioOrder::Up, rate: ArpeggioRate::Sixteenth, gate: 0.8, octaves: 1 }
+    }
+}
+
+impl ArpeggioRate {
+    /// The time between notes, in beats.
+    pub fn step(&self) -> f64 {
+        match self {
+            ArpeggioRate::Quarter => 1.0,
+            ArpeggioRate::Eighth => 0.5,
+            ArpeggioRate::EighthTriplet => 1.0 / 3.0,
+            ArpeggioRate::Sixteenth => 0.25,
+            ArpeggioRate::SixteenthTriplet => 1.0 / 6.0
+        }
+    }
+}
+
+impl Arpeggio {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(self.gate > 0.0 && self.gate <= 2.0) {
+            return Err(Error::InvalidInput(format!("the gate of an arpeggio must be more than 0 and at most 2, but it is {}", self.gate)));
+        }
+        if !(1..=4).contains(&self.octaves) {
+            return Err(Error::InvalidInput(format!("an arpeggio must span 1 to 4 octaves, but it spans {}", self.octaves)));
+        }
+        Ok(())
+    }
+
+    /// Returns the notes to cycle through, spread over the octaves. Notes
+    /// above 127 are left out. `Random` returns the notes from low to high,
+    /// to be picked from by `notes_in`.
+    pub fn sequence(&self, notes: &[u8]) -> Vec<u8> {
+        let spread = |notes: &[u8]| -> Vec<u8> {
+            (0..self.octaves)
+                .flat_map(|octave| notes.iter().map(move |note| *note as u16 + 12 * octave as u16))
+                .filter(|note| *note <= 127)
+                .map(|note| note as u8)
+                .collect()
+        };
+        let mut sorted = notes.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        match self.order {
+            ArpeggioOrder::Up | ArpeggioOrder::Random => spread(&sorted),
+            ArpeggioOrder::Down => spread(&sorted).into_iter().rev().collect(),
+            ArpeggioOrder::UpDown => {
+                let up = spread(&sorted);
+                let down = up.iter().rev().skip(1).take(up.len().saturating_sub(2)).copied();
+                up.iter().copied().chain(down).collect()
+            },
+            ArpeggioOrder::AsPlayed => spread(notes)
+        }
+    }
+
+    /// Returns the start time, length and pitch of every note of an arpeggio
+    /// that lasts `length` beats.
+    pub fn notes_in(&self, notes: &[u8], length: f64, math_magician: &mut MathMagician) -> Vec<(f64, f64, u8)> {
+        let sequence = self.sequence(notes);
+        if sequence.is_empty() {
+            return Vec::new();
+        }
+        let step = self.rate.step();
+        let mut result = Vec::new();
+        let mut i = 0;
+        // rounding errors could otherwise add a tiny note at the end
+        while (i as f64) * step < length - 1e-9 {
+            let start = i as f64 * step;
+            let note = match self.order {
+                ArpeggioOrder::Random => sequence[math_magician.big_decision(0, sequence.len() as u16 - 1) as usize],
+                _ => sequence[i % sequence.len()]
+            };
+            result.push((start, (step * self.gate).min(length - start), note));
+            i += 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders() {
+        let arpeggio = |order, octaves| Arpeggio { order, octaves, ..Default::default() };
+        let notes = [64, 60, 67];
+        assert_eq!(arpeggio(ArpeggioOrder::Up, 1).sequence(&notes), [60, 64, 67]);
+        assert_eq!(arpeggio(ArpeggioOrder::Down, 1).sequence(&notes), [67, 64, 60]);
+        assert_eq!(arpeggio(ArpeggioOrder::UpDown, 1).sequence(&notes), [60, 64, 67, 64]);
+        assert_eq!(arpeggio(ArpeggioOrder::AsPlayed, 1).sequence(&notes), [64, 60, 67]);
+        assert_eq!(arpeggio(ArpeggioOrder::Up, 2).sequence(&notes), [60, 64, 67, 72, 76, 79]);
+        assert_eq!(arpeggio(ArpeggioOrder::UpDown, 2).sequence(&notes), [60, 64, 67, 72, 76, 79, 76, 72, 67, 64]);
+        assert_eq!(arpeggio(ArpeggioOrder::UpDown, 1).sequence(&[60, 67]), [60, 67]);
+        // notes above 127 are left out
+        assert_eq!(arpeggio(ArpeggioOrder::Up, 3).sequence(&[110, 120]), [110, 120, 122]);
+    }
+
+    #[test]
+    fn rates_and_gates() {
+        let mut math_magician = MathMagician::share_hash([0; 32]);
+        let arpeggio = Arpeggio { rate: ArpeggioRate::EighthTriplet, gate: 0.5, ..Default::default() };
+        let notes = arpeggio.notes_in(&[60, 64, 67], 2.0, &mut math_magician);
+        assert_eq!(notes.len(), 6);
+        assert_eq!(notes[3].2, 60);
+        assert!((notes[5].0 - 5.0 / 3.0).abs() < 1e-9);
+        assert!((notes[5].1 - 1.0 / 6.0).abs() < 1e-9);
+
+        // the last note is cut short by the end of the chord
+        let arpeggio = Arpeggio { rate: ArpeggioRate::Quarter, gate: 2.0, ..Default::default() };
+        let notes = arpeggio.notes_in(&[60, 64, 67], 1.5, &mut math_magician);
+        assert_eq!(notes, [(0.0, 1.5, 60), (1.0, 0.5, 64)]);
+
+        let arpeggio = Arpeggio { order: ArpeggioOrder::Random, ..Default::default() };
+        let notes = arpeggio.notes_in(&[60, 64, 67], 4.0, &mut math_magician);
+        assert_eq!(notes.len(), 16);
+        assert!(notes.iter().all(|(_, _, note)| [60, 64, 67].contains(note)));
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Arpeggio::default().validate().is_ok());
+        assert!(Arpeggio { gate: 0.0, ..Default::default() }.validate().is_err());
+        assert!(Arpeggio { octaves: 0, ..Default::default() }.validate().is_err());
+        let arpeggio: Arpeggio = serde_json::from_str(r#"{"order": "up-down", "rate": "1/8t"}"#).unwrap();
+        assert_eq!(arpeggio.rate, ArpeggioRate::EighthTriplet);
+        assert_eq!(arpeggio.gate, 0.8);
+    }
+}
@@ -0,0 +1,192 @@
+//! Bass lines.
+//!
+//! A bass line follows the chords that were placed in the chord track, and is
+//! written to its own track, using the same random decisions as the rest of
+//! the song so that it stays reproducible.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::BassStyle, error::Error, meter::Meter, midi::TrackSettings, music::PlacedChord, rhythm::Rhythm, utils::MathMagician};
+
+/// The rhythm of `BassStyle::Syncopated`, which is a 3-3-2 pattern over two
+/// beats.
+const SYNCOPATED_RHYTHM: &str = "x-.x-.x.";
+
+/// The settings for a bass line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BassLine {
+    pub style: BassStyle,
+    /// The lowest root of the bass line. The roots are placed in the octave
+    /// above it, and the other notes can go up to an octave higher.
+    pub lowest_note: u8,
+    pub track: TrackSettings,
+}
+
+impl Default for BassLine {
+    fn default() -> Self {
+        BassLine { style: BassStyle::Root, lowest_note: 28, track: TrackSettings::new("Bass", 1, Some(33)) }
+    }
+}
+
+/// A note of a bass line. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BassNote {
+    pub start: f64,
+    pub length: f64,
+    pub pitch: u8,
+}
+
+impl BassLine {
+    /// Creates a bass line with the default range and track.
+    pub fn new(style: BassStyle) -> Self {
+        BassLine { style, ..Default::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        self.track.validate()?;
+        // the highest notes are almost two octaves above the lowest root, and
+        // a walking line can approach a root from a semitone below
+        if !(12..=104).contains(&self.lowest_note) {
+            return Err(Error::InvalidInput(format!("the lowest note of the bass line must be between 12 and 104, but it is {}", self.lowest_note)));
+        }
+        Ok(())
+    }
+
+    /// The root of the chord in the bass range.
+    fn root(&self, chord: &PlacedChord) -> u8 {
+        self.lowest_note + (chord.root - self.lowest_note as i16).rem_euclid(12) as u8
+    }
+
+    /// The fifth of the chord above `root`, which is a perfect fifth unless
+    /// the chord has a diminished or augmented fifth instead.
+    fn fifth(&self, chord: &PlacedChord, root: u8) -> u8 {
+        let intervals: Vec<i16> = chord.pitch_classes.iter().map(|pitch_class| (pitch_class - chord.root).rem_euclid(12)).collect();
+        let fifth = [7, 6, 8].into_iter().find(|interval| intervals.contains(interval)).unwrap_or(7);
+        root + fifth as u8
+    }
+
+    /// Writes the bass line for the chords.
+    pub fn notes(&self, chords: &[PlacedChord], meter: &Meter, math_magician: &mut MathMagician) -> Vec<BassNote> {
+        let beat = meter.beat_length();
+        let mut notes = Vec::new();
+        for (i, chord) in chords.iter().enumerate() {
+            let root = self.root(chord);
+            // the times of the steps of the chord, cut short at the end
+            let steps = |step: f64| {
+                let length = chord.length;
+                (0..).map(move |i| i as f64 * step).take_while(move |time| *time < length).map(move |time| (time, step.min(length - time)))
+            };
+            let mut push = |start: f64, length: f64, pitch: u8| notes.push(BassNote { start: chord.start + start, length, pitch });
+            match self.style {
+                BassStyle::Root => push(0.0, chord.length, root),
+                BassStyle::RootFifth => {
+                    let fifth = self.fifth(chord, root);
+                    for (j, (time, length)) in steps(beat).enumerate() {
+                        push(time, length, if j % 2 == 0 { root } else { fifth });
+                    }
+                },
+                BassStyle::Walking => {
+                    let beats: Vec<(f64, f64)> = steps(beat).collect();
+                    // the next chord is approached from a semitone above or
+                    // below, unless it starts later or has the same root
+                    let next_root = chords.get(i + 1)
+                        .filter(|next| (next.start - (chord.start + chord.length)).abs() < 1e-9)
+                        .map(|next| self.root(next))
+                        .filter(|next_root| *next_root != root);
+                    let tones: Vec<u8> = chord.pitch_classes
+                        .iter()
+                        .map(|pitch_class| root + (pitch_class - chord.root).rem_euclid(12) as u8)
+                        .collect();
+                    for (j, (time, length)) in beats.iter().enumerate() {
+                        let pitch = match (j, next_root) {
+                            (0, _) => root,
+                            (j, Some(next_root)) if j == beats.len() - 1 => match math_magician.big_decision(0, 1) {
+                                0 => next_root - 1,
+                                _ => next_root + 1
+                            },
+                            _ => tones[math_magician.big_decision(0, tones.len() as u16 - 1) as usize]
+                        };
+                        push(*time, *length, pitch);
+                    }
+                },
+                BassStyle::Octaves => {
+                    for (j, (time, length)) in steps(beat / 2.0).enumerate() {
+                        push(time, length, if j % 2 == 0 { root } else { root + 12 });
+                    }
+                },
+                BassStyle::Syncopated => {
+                    let rhythm: Rhythm = SYNCOPATED_RHYTHM.parse().expect("the syncopated rhythm is valid");
+                    let fifth = self.fifth(chord, root);
+                    for (j, hit) in rhythm.hits_in(chord.length).iter().enumerate() {
+                        push(hit.start, hit.length, if j % 3 == 2 { fifth } else { root });
+                    }
+                }
+            }
+        }
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chords() -> Vec<PlacedChord> {
+        // C major and G major for a bar each, and B diminished for half a bar
+        PlacedChord::one_after_another(&[(0, [0, 4, 7], 4.0), (7, [7, 11, 2], 4.0), (11, [11, 2, 5], 2.0)])
+    }
+
+    fn pitches(style: BassStyle) -> Vec<(f64, u8)> {
+        BassLine::new(style)
+            .notes(&chords(), &Meter::default(), &mut MathMagician::share_hash([0; 32]))
+            .iter()
+            .map(|note| (note.start, note.pitch))
+            .collect()
+    }
+
+    #[test]
+    fn roots_and_fifths() {
+        assert_eq!(pitches(BassStyle::Root), [(0.0, 36), (4.0, 31), (8.0, 35)]);
+        let root_fifth = pitches(BassStyle::RootFifth);
+        assert_eq!(root_fifth.len(), 10);
+        assert_eq!(root_fifth[..4], [(0.0, 36), (1.0, 43), (2.0, 36), (3.0, 43)]);
+        // the diminished fifth of B
+        assert_eq!(root_fifth[9], (9.0, 41));
+        // two notes per beat
+        let octaves = pitches(BassStyle::Octaves);
+        assert_eq!(octaves.len(), 20);
+        assert_eq!(octaves[..2], [(0.0, 36), (0.5, 48)]);
+    }
+
+    #[test]
+    fn walking_bass() {
+        let walking = pitches(BassStyle::Walking);
+        assert_eq!(walking.len(), 10);
+        assert_eq!(walking[0], (0.0, 36));
+        // a chromatic approach to G and then B
+        assert!([30, 32].contains(&walking[3].1), "{:?}", walking);
+        assert!([34, 36].contains(&walking[7].1), "{:?}", walking);
+        // the other beats are chord tones
+        assert!([36, 40, 43].contains(&walking[1].1));
+        assert!([31, 35, 38].contains(&walking[5].1));
+    }
+
+    #[test]
+    fn syncopated_bass() {
+        let syncopated = pitches(BassStyle::Syncopated);
+        assert_eq!(syncopated[..3], [(0.0, 36), (0.75, 36), (1.5, 43)]);
+        assert_eq!(syncopated.len(), 15);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(BassLine::default().validate().is_ok());
+        assert!(BassLine { lowest_note: 120, ..Default::default() }.validate().is_err());
+        assert!(BassLine { lowest_note: 0, ..Default::default() }.validate().is_err());
+        assert!(BassLine { lowest_note: 11, ..Default::default() }.validate().is_err());
+        assert!(BassLine { lowest_note: 12, ..Default::default() }.validate().is_ok());
+        let bass: BassLine = serde_json::from_str(r#"{"style": "walking"}"#).unwrap();
+        assert_eq!(bass, BassLine::new(BassStyle::Walking));
+    }
+}
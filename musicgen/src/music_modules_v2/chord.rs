@@ -4,7 +4,7 @@ use base64::{engine::general_purpose, Engine};
 use midly::Smf;
 use serde::{ser::SerializeStruct, Serialize};
 
-use crate::music_modules_v2::music::KEYS;
+use crate::music_modules_v2::{key::{Key, PitchClass}, roman::{HarmonicFunction, RomanNumeral}};
 
 use super::{chord_type::*, midi::MidiFile};
 
@@ -13,6 +13,8 @@ pub struct Chord {
     pub chord_type: ChordType,
     pub root: u8,
     pub key: i16,
+    /// The key that `key` was parsed from, used for spelling note names.
+    pub key_signature: Key,
     pub probability_2d: f32,
     pub probability_1d: f32,
     pub show_probability: bool,
@@ -25,6 +27,7 @@ impl Default for Chord {
             chord_type: ChordType::default(),
             root: 0,
             key: 0,
+            key_signature: Key::default(),
             probability_2d: 0f32,
             probability_1d: 0f32,
             show_probability: false,
@@ -57,7 +60,7 @@ impl Serialize for Chord {
         S: serde::Serializer 
     {
         // increment this by one when adding a new serialized field
-        let num_standard_fields = 4;
+        let num_standard_fields = 6;
         let mut state = if self.show_probability {
             let mut state = serializer.serialize_struct("Chord", num_standard_fields + 2)?;
             state.serialize_field("probability_2d", &format!("{:.1}%", &self.probability_2d * 100f32))?;
@@ -68,6 +71,8 @@ impl Serialize for Chord {
         };
         state.serialize_field("name", &self.get_name())?;
         state.serialize_field("notes", &self.get_note_names())?;
+        state.serialize_field("numeral", &self.get_roman_numeral().to_string())?;
+        state.serialize_field("function", &self.get_function())?;
         state.serialize_field("midi", &self.to_midi())?;
         state.serialize_field("note_vec", &self.get_notes_vec())?;
         state.end()
@@ -81,6 +86,7 @@ impl Chord {
             chord_type: chord_type.to_owned(),
             root: root_index,
             key: 0,
+            key_signature: Key::default(),
             probability_2d: 0f32,
             probability_1d: 0f32,
             show_probability: false,
@@ -110,24 +116,39 @@ impl Chord {
 
     /// Gets the name of this chord, given the current key.
     pub fn get_name(&self) -> String {
-        let letter = KEYS[(self.root as usize + self.key as usize) % 12];
+        let letter = self.key_signature.spell(PitchClass::new(self.root as i16 + self.key));
         format!("{} {}", letter, self.chord_type.name)
     }
 
     /// Gets the names of the notes of this chord.
     /// 
-    /// Returns a string like "F, A, C#, E"
+    /// Returns a string like "F, A, C#, E". The root is spelled for the key 
+    /// signature, and the other notes are spelled as intervals above the 
+    /// root where possible, so that an Eb chord contains a G rather than an 
+    /// F##.
     pub fn get_note_names(&self) -> String {
-        let notes = self.get_notes();
-        if notes.len() == 0 {
-            return String::new();
-        }
-        let mut result = String::with_capacity(4 * notes.len());
-        result.push_str(KEYS[(notes[0] as usize) % 12]);
-        for note in notes.iter().skip(1) {
-            result.push_str(&format!(", {}", KEYS[(*note as usize) % 12]))
-        }
-        result
+        let root = self.root as i16 + self.key;
+        let root_name = self.key_signature.spell(PitchClass::new(root));
+        self.get_notes()
+            .iter()
+            .map(|note| root_name
+                .chord_tone((*note - root).rem_euclid(12) as u8)
+                .unwrap_or_else(|| self.key_signature.spell(PitchClass::new(*note)))
+                .to_string()
+            )
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Names this chord as a Roman numeral in its key signature, such as 
+    /// "ii7" or "bVII".
+    pub fn get_roman_numeral(&self) -> RomanNumeral {
+        RomanNumeral::from_chord(self, &self.key_signature)
+    }
+
+    /// Gets the harmonic function of this chord in its key signature.
+    pub fn get_function(&self) -> HarmonicFunction {
+        self.get_roman_numeral().function()
     }
 
     pub fn to_midi(&self) -> String {
@@ -151,7 +172,8 @@ impl Chord {
         base64
     }
 
-    fn get_notes_vec(&self) -> Vec<i16> {
+    /// Gets the notes of this chord as MIDI note numbers, starting from C3.
+    pub fn get_notes_vec(&self) -> Vec<i16> {
         let mut result = self.get_notes();
         result.iter_mut().for_each(|note| *note = *note + 12 * 4);
         result
@@ -211,4 +233,39 @@ pub fn expand_chords(chord_list: &mut Vec<Chord>) -> (HashSet<Chord>, Vec<HashSe
         }
     }
     (chord_set, chord_table_sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spelling_depends_on_the_key() {
+        let major = ChordType::new("major", &[0, 4, 7], &[3], None);
+        let mut chord = Chord::new(3, &major);
+        chord.key_signature = "Cmin".parse().unwrap();
+        assert_eq!(chord.get_name(), "Eb major");
+        assert_eq!(chord.get_note_names(), "Eb, G, Bb");
+
+        chord.key_signature = "Emin".parse().unwrap();
+        assert_eq!(chord.get_name(), "D# major");
+        // F## would need a double sharp, so it falls back to the key signature
+        assert_eq!(chord.get_note_names(), "D#, G, A#");
+    }
+
+    #[test]
+    fn numerals_depend_on_the_key() {
+        let minor7 = ChordType::new("m7", &[0, 3, 7, 10], &[2], None);
+        let mut chord = Chord::new(2, &minor7);
+        chord.key_signature = "Cmaj".parse().unwrap();
+        assert_eq!(chord.get_roman_numeral().to_string(), "ii7");
+        assert_eq!(chord.get_function(), HarmonicFunction::Subdominant);
+
+        chord.key_signature = "Amin".parse().unwrap();
+        assert_eq!(chord.get_roman_numeral().to_string(), "iv7");
+
+        let json = serde_json::to_value(&chord).unwrap();
+        assert_eq!(json["numeral"], "iv7");
+        assert_eq!(json["function"], "subdominant");
+    }
 }
\ No newline at end of file
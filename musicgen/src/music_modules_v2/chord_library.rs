@@ -0,0 +1,224 @@
+//! User-defined chord types.
+//!
+//! A `ChordLibrary` is a list of chord types that can be loaded from JSON or
+//! TOML and merged with the built-in chord types, such as:
+//!
+//! ```toml
+//! [[chord_types]]
+//! name = "so what"
+//! intervals = [0, 5, 10, 15, 19]
+//! roots = [0, 2, 5, 7]
+//! tags = ["quartal"]
+//!
+//! [[chord_types]]
+//! name = "quartal"
+//! intervals = [0, 5, 10]
+//! optional_notes = [15]
+//! tags = ["quartal"]
+//! ```
+//!
+//! `roots` defaults to every root, and like the rest of the chord tables, the
+//! roots are relative to C minor.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{chord_type::{all_custom_chord_types_handpicked, ChordType}, error::Error};
+
+/// A list of chord types, where no two chord types share a name.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChordLibrary {
+    #[serde(default)]
+    pub chord_types: Vec<ChordType>,
+}
+
+impl From<Vec<ChordType>> for ChordLibrary {
+    fn from(chord_types: Vec<ChordType>) -> Self {
+        let mut library = ChordLibrary::default();
+        library.extend(chord_types);
+        library
+    }
+}
+
+impl ChordLibrary {
+    /// The hand-picked chord types used by `ChordTypeGroup::Custom`, tagged
+    /// with the names that the web app selects them by, such as `minor7`.
+    pub fn built_in() -> Self {
+        Self::from(all_custom_chord_types_handpicked())
+    }
+
+    /// Parses a library from JSON or TOML. JSON may also be a bare array of
+    /// chord types.
+    pub fn parse(document: &str) -> Result<Self, Error> {
+        match document.trim_start().chars().next() {
+            Some('{') | Some('[') if !document.trim_start().starts_with("[[") => Self::from_json(document),
+            _ => Self::from_toml(document)
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Library(ChordLibrary),
+            ChordTypes(Vec<ChordType>),
+        }
+        let library = match serde_json::from_str(json)? {
+            Repr::Library(library) => library,
+            Repr::ChordTypes(chord_types) => ChordLibrary { chord_types },
+        };
+        library.validated()
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let library: ChordLibrary = toml::from_str(toml)?;
+        library.validated()
+    }
+
+    /// Checks the chord types, and merges chord types that share a name.
+    fn validated(self) -> Result<Self, Error> {
+        for chord_type in self.chord_types.iter() {
+            let invalid = |reason| Err(Error::InvalidChordType { name: chord_type.name.clone(), reason });
+            if chord_type.name.trim().is_empty() {
+                return invalid("the name is empty");
+            }
+            if chord_type.note_intervals.is_empty() {
+                return invalid("there are no intervals");
+            }
+            if chord_type.roots.iter().any(|root| *root > 11) {
+                return invalid("the roots must be between 0 and 11");
+            }
+            // the notes are shifted up by 4 octaves when they are played
+            if chord_type.note_intervals.iter().chain(chord_type.optional_notes.iter()).any(|note| *note > 60) {
+                return invalid("the intervals must be at most 60 semitones");
+            }
+        }
+        Ok(Self::from(self.chord_types))
+    }
+
+    /// Adds chord types to the library, replacing any chord type with the same
+    /// name. The replaced chord type's tags are kept, so that it can still be
+    /// selected the same way.
+    pub fn extend<I: IntoIterator<Item = ChordType>>(&mut self, chord_types: I) {
+        for mut chord_type in chord_types {
+            match self.chord_types.iter_mut().find(|ct| ct.name == chord_type.name) {
+                Some(existing) => {
+                    for tag in existing.tags.drain(..) {
+                        if !chord_type.tags.contains(&tag) {
+                            chord_type.tags.push(tag);
+                        }
+                    }
+                    *existing = chord_type;
+                },
+                None => self.chord_types.push(chord_type)
+            }
+        }
+    }
+
+    /// Returns this library with the chord types of `other` added to it.
+    pub fn merged(mut self, other: &ChordLibrary) -> Self {
+        self.extend(other.chord_types.iter().cloned());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ChordType> {
+        self.chord_types.iter().find(|chord_type| chord_type.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chord_types.is_empty()
+    }
+
+    /// Returns the chord types whose name or tags are in `selection`, in
+    /// library order.
+    pub fn select(&self, selection: &HashSet<String>) -> Vec<ChordType> {
+        self.chord_types
+            .iter()
+            .filter(|chord_type| chord_type.is_selected_by(selection))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every tag in the library, in the order they first appear.
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = Vec::new();
+        for tag in self.chord_types.iter().flat_map(|ct| ct.tags.iter()) {
+            if !tags.contains(&tag.as_str()) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        [[chord_types]]
+        name = "so what"
+        intervals = [0, 5, 10, 15, 19]
+        roots = [0, 2, 5, 7]
+        tags = ["quartal"]
+
+        [[chord_types]]
+        name = "quartal"
+        intervals = [0, 5, 10]
+        optional_notes = [15]
+        tags = ["quartal"]
+    "#;
+
+    #[test]
+    fn loading_toml() {
+        let library = ChordLibrary::parse(TOML).unwrap();
+        assert_eq!(library.chord_types.len(), 2);
+        let so_what = library.get("so what").unwrap();
+        assert_eq!(so_what.note_intervals, [0, 5, 10, 15, 19]);
+        assert_eq!(so_what.roots, [0, 2, 5, 7]);
+        let quartal = library.get("quartal").unwrap();
+        assert_eq!(quartal.roots.len(), 12);
+        assert_eq!(quartal.optional_notes, [15]);
+        assert_eq!(library.tags(), ["quartal"]);
+    }
+
+    #[test]
+    fn loading_json() {
+        let json = r#"{"chord_types": [{"name": "sus2", "intervals": [0, 2, 7], "roots": [0]}]}"#;
+        let library = ChordLibrary::parse(json).unwrap();
+        assert_eq!(library.get("sus2").unwrap().roots, [0]);
+
+        let json = r#"[{"name": "sus4", "intervals": [0, 5, 7]}]"#;
+        let library = ChordLibrary::parse(json).unwrap();
+        assert_eq!(library.get("sus4").unwrap().tags.len(), 0);
+    }
+
+    #[test]
+    fn invalid_libraries() {
+        assert!(matches!(
+            ChordLibrary::parse(r#"[{"name": "bad", "intervals": [0, 4], "roots": [12]}]"#),
+            Err(Error::InvalidChordType { .. })
+        ));
+        assert!(matches!(
+            ChordLibrary::parse(r#"[{"name": "", "intervals": [0, 4]}]"#),
+            Err(Error::InvalidChordType { .. })
+        ));
+        assert!(ChordLibrary::parse(r#"[{"name": "no intervals"}]"#).is_err());
+        assert!(ChordLibrary::parse("[[chord_types]]\nname = 5").is_err());
+    }
+
+    #[test]
+    fn merging_replaces_chord_types_with_the_same_name() {
+        let custom = ChordLibrary::parse(r#"[{"name": "minor 7", "intervals": [0, 3, 7, 10], "roots": [0]}]"#).unwrap();
+        let library = ChordLibrary::built_in().merged(&custom);
+        assert_eq!(library.chord_types.len(), 17);
+        assert_eq!(library.chord_types[0].note_intervals, [0, 3, 7, 10]);
+        assert!(library.chord_types[0].is_selected_by(&["minor7".to_string()]));
+
+        // the built-in chord types are selected by their tags
+        let selection = HashSet::from(["minor7".to_string(), "major".to_string()]);
+        let selected = ChordLibrary::built_in().select(&selection);
+        assert_eq!(selected.iter().map(|ct| ct.name.as_str()).collect::<Vec<&str>>(), ["minor 7", "major"]);
+    }
+}
@@ -0,0 +1,495 @@
+//! Parsing chord symbols such as `Cm7`, `Bb13` or `Ebmaj7#11/G`.
+//!
+//! A chord symbol is made of:
+//!
+//! * a root, such as `C`, `F#` or `Bb`
+//! * an optional quality: `maj`/`M`/`Δ`, `m`/`min`, `dim`/`°`, `ø`,
+//!   `aug`/`+`, or `5` for a power chord
+//! * an optional extension: `6`, `6/9`, `7`, `9`, `11` or `13`
+//! * any number of modifiers, optionally in parentheses: `sus2`, `sus4`,
+//!   alterations (`b5`, `#5`, `b9`, `#9`, `#11`, `b13`), add-tones (`add9`,
+//!   `add#11`) and omissions (`no3`, `omit5`)
+//! * an optional bass note after a `/`
+
+use std::{fmt::Display, str::FromStr};
+
+use super::{chord::Chord, chord_type::ChordType, error::Error, key::{Key, Mode, NoteName}};
+
+/// A parsed chord symbol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChordSymbol {
+    pub root: NoteName,
+    /// Everything between the root and the bass note, as it was written.
+    pub quality: String,
+    /// The intervals above the root, not including the bass note.
+    pub intervals: Vec<u8>,
+    pub bass: Option<NoteName>,
+}
+
+/// The chord tones of a chord symbol, as semitones above the root.
+#[derive(Default)]
+struct ChordTones {
+    third: Option<u8>,
+    fifth: Option<u8>,
+    sixth: Option<u8>,
+    seventh: Option<u8>,
+    ninth: Option<u8>,
+    eleventh: Option<u8>,
+    thirteenth: Option<u8>,
+    extra: Vec<u8>,
+}
+
+impl ChordTones {
+    fn intervals(&self) -> Vec<u8> {
+        let mut intervals: Vec<u8> = [
+            Some(0),
+            self.third,
+            self.fifth,
+            self.sixth,
+            self.seventh,
+            self.ninth,
+            self.eleventh,
+            self.thirteenth
+        ]
+            .into_iter()
+            .flatten()
+            .chain(self.extra.iter().copied())
+            .collect();
+        intervals.sort();
+        intervals.dedup();
+        intervals
+    }
+}
+
+/// Reads a chord symbol from left to right.
+struct Parser<'a> {
+    symbol: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.symbol[self.position..]
+    }
+
+    fn error(&self, reason: &'static str) -> Error {
+        Error::InvalidChordSymbol { symbol: self.symbol.to_string(), position: self.position, reason }
+    }
+
+    /// Consumes the first of `options` that the rest of the symbol starts
+    /// with.
+    fn eat(&mut self, options: &[&str]) -> bool {
+        match options.iter().find(|option| self.rest().starts_with(**option)) {
+            Some(option) => {
+                self.position += option.len();
+                true
+            },
+            None => false
+        }
+    }
+
+    fn number(&mut self) -> Option<u8> {
+        let digits = self.rest().chars().take_while(|c| c.is_ascii_digit()).count();
+        let number = self.rest()[..digits].parse().ok()?;
+        self.position += digits;
+        Some(number)
+    }
+
+    /// Consumes an accidental, returning -1 for a flat and 1 for a sharp.
+    fn accidental(&mut self) -> Option<i8> {
+        if self.eat(&["b", "♭", "-"]) {
+            Some(-1)
+        } else if self.eat(&["#", "♯", "+"]) {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    fn note_name(&mut self) -> Result<NoteName, Error> {
+        if !self.rest().starts_with(|c: char| ('A'..='G').contains(&c)) {
+            return Err(self.error("expected a note from A to G"));
+        }
+        // a double sharp is not a valid accidental for a chord root
+        let max_accidentals = match self.rest()[1..].starts_with(['x', '𝄪', '𝄫']) {
+            true => 0,
+            false => 1
+        };
+        let (name, rest) = NoteName::parse_prefix(self.rest(), max_accidentals)
+            .ok_or_else(|| self.error("expected a note from A to G"))?;
+        self.position = self.symbol.len() - rest.len();
+        Ok(name)
+    }
+
+    /// Parses the quality and extension.
+    fn quality(&mut self, tones: &mut ChordTones) -> Result<(), Error> {
+        tones.third = Some(4);
+        tones.fifth = Some(7);
+        // the 7th that an extension implies
+        let mut seventh = 10;
+        if self.eat(&["maj", "Maj", "M", "Δ", "△"]) {
+            seventh = 11;
+            if self.rest().is_empty() || self.rest().starts_with('/') {
+                // `Cmaj` is a major triad, but `CΔ` is a major 7th chord
+                if self.symbol[..self.position].ends_with(['Δ', '△']) {
+                    tones.seventh = Some(11);
+                }
+                return Ok(());
+            }
+            if !self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+                tones.seventh = Some(11);
+            }
+        } else if self.eat(&["min", "mi", "m", "−"]) {
+            tones.third = Some(3);
+            // minor-major 7th chords, such as `CmMaj7` or `Cm(maj7)`
+            if self.eat(&["Maj", "maj", "M", "(maj", "(Maj", "Δ"]) {
+                seventh = 11;
+                if !self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+                    tones.seventh = Some(11);
+                }
+            }
+        } else if !self.rest().starts_with("omit") && self.eat(&["dim", "°", "o"]) {
+            tones.third = Some(3);
+            tones.fifth = Some(6);
+            seventh = 9;
+        } else if self.eat(&["ø"]) {
+            tones.third = Some(3);
+            tones.fifth = Some(6);
+            tones.seventh = Some(10);
+        } else if self.eat(&["aug", "+"]) {
+            tones.fifth = Some(8);
+        }
+
+        let start = self.position;
+        match self.number() {
+            None => (),
+            Some(5) if self.position - start == 1 && self.symbol[..start].len() == self.root_len() => {
+                tones.third = None;
+            },
+            Some(6) => {
+                tones.sixth = Some(9);
+                if self.eat(&["/9", "9"]) {
+                    tones.ninth = Some(14);
+                }
+            },
+            Some(69) => {
+                tones.sixth = Some(9);
+                tones.ninth = Some(14);
+            },
+            Some(7) => tones.seventh = Some(seventh),
+            Some(extension @ (9 | 11 | 13)) => {
+                tones.seventh = Some(seventh);
+                tones.ninth = Some(14);
+                // the 11th clashes with the major 3rd, so it is only implied
+                // by minor 13th chords
+                if extension == 11 || (extension == 13 && tones.third == Some(3)) {
+                    tones.eleventh = Some(17);
+                }
+                if extension == 13 {
+                    tones.thirteenth = Some(21);
+                }
+            },
+            Some(_) => {
+                self.position = start;
+                return Err(self.error("expected an extension of 6, 7, 9, 11 or 13"));
+            }
+        }
+        // closes `Cm(maj7)`
+        self.eat(&[")"]);
+        Ok(())
+    }
+
+    /// The length of the root in the symbol.
+    fn root_len(&self) -> usize {
+        match NoteName::parse_prefix(self.symbol, 1) {
+            Some((_, rest)) => self.symbol.len() - rest.len(),
+            None => 0
+        }
+    }
+
+    /// Parses the alterations, add-tones, omissions and suspensions.
+    fn modifiers(&mut self, tones: &mut ChordTones) -> Result<(), Error> {
+        while !self.rest().is_empty() && !self.rest().starts_with('/') {
+            if self.eat(&["(", ")", ",", " "]) {
+                continue;
+            }
+            let start = self.position;
+            if self.eat(&["sus"]) {
+                tones.third = None;
+                match self.number() {
+                    Some(2) => tones.extra.push(2),
+                    Some(4) | None => tones.extra.push(5),
+                    Some(_) => {
+                        self.position = start;
+                        return Err(self.error("expected `sus2` or `sus4`"));
+                    }
+                }
+            } else if self.eat(&["add"]) {
+                let accidental = self.accidental().unwrap_or(0);
+                let interval = match self.number() {
+                    Some(2) => 2,
+                    Some(4) => 5,
+                    Some(6) => 9,
+                    Some(9) => 14,
+                    Some(11) => 17,
+                    Some(13) => 21,
+                    _ => {
+                        self.position = start;
+                        return Err(self.error("expected `add2`, `add4`, `add6`, `add9`, `add11` or `add13`"));
+                    }
+                };
+                tones.extra.push((interval as i8 + accidental) as u8);
+            } else if self.eat(&["no", "omit"]) {
+                match self.number() {
+                    Some(3) => tones.third = None,
+                    Some(5) => tones.fifth = None,
+                    _ => {
+                        self.position = start;
+                        return Err(self.error("only the 3rd and the 5th can be left out"));
+                    }
+                }
+            } else if let Some(accidental) = self.accidental() {
+                match (accidental, self.number()) {
+                    (-1, Some(5)) => tones.fifth = Some(6),
+                    (1, Some(5)) => tones.fifth = Some(8),
+                    (-1, Some(9)) => tones.ninth = Some(13),
+                    (1, Some(9)) => tones.ninth = Some(15),
+                    (1, Some(11)) => tones.eleventh = Some(18),
+                    (-1, Some(13)) => tones.thirteenth = Some(20),
+                    _ => {
+                        self.position = start;
+                        return Err(self.error("expected an alteration such as `b5`, `#5`, `b9`, `#9`, `#11` or `b13`"));
+                    }
+                }
+                // an altered 9th, 11th or 13th implies a 7th
+                if tones.seventh.is_none() && tones.sixth.is_none() && matches!(tones.ninth, Some(13 | 15)) {
+                    tones.seventh = Some(10);
+                }
+            } else {
+                return Err(self.error("unexpected character"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChordSymbol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let symbol = s.trim();
+        let mut parser = Parser { symbol, position: 0 };
+        let root = parser.note_name()?;
+        let quality_start = parser.position;
+        let mut tones = ChordTones::default();
+        parser.quality(&mut tones)?;
+        parser.modifiers(&mut tones)?;
+        let quality = symbol[quality_start..parser.position].to_string();
+        let bass = match parser.eat(&["/"]) {
+            true => Some(parser.note_name()?),
+            false => None
+        };
+        if !parser.rest().is_empty() {
+            return Err(parser.error("expected the end of the chord symbol after the bass note"));
+        }
+        Ok(ChordSymbol { root, quality, intervals: tones.intervals(), bass })
+    }
+}
+
+impl Display for ChordSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.root, self.quality)?;
+        match self.bass {
+            Some(bass) => write!(f, "/{}", bass),
+            None => Ok(())
+        }
+    }
+}
+
+impl ChordSymbol {
+    /// The intervals of the chord above its root. With a bass note, the bass
+    /// note comes first and the rest of the chord is moved up an octave.
+    pub fn note_intervals(&self) -> Vec<u8> {
+        match self.bass {
+            Some(bass) => {
+                let bass_interval = (bass.pitch_class().value() + 12 - self.root.pitch_class().value()) % 12;
+                match bass_interval {
+                    0 => self.intervals.clone(),
+                    _ => std::iter::once(bass_interval)
+                        .chain(self.intervals.iter().map(|interval| interval + 12))
+                        .collect()
+                }
+            },
+            None => self.intervals.clone()
+        }
+    }
+
+    pub fn chord_type(&self) -> ChordType {
+        let mut name = match self.quality.as_str() {
+            "" => "major".to_string(),
+            quality => quality.to_string(),
+        };
+        if let Some(bass) = self.bass {
+            name.push_str(&format!("/{}", bass));
+        }
+        let root = self.root.pitch_class().value();
+        ChordType::new(&name, &self.note_intervals(), &[root], None)
+    }
+
+    /// Creates a chord in C, so that `root` is the pitch class of the root.
+    /// The notes are spelled in the key of the chord's root, so that `D#m`
+    /// uses sharps and `Ebm` uses flats.
+    pub fn to_chord(&self) -> Chord {
+        let mut chord = Chord::new(self.root.pitch_class().value(), &self.chord_type());
+        let mode = match self.intervals.contains(&3) && !self.intervals.contains(&4) {
+            true => Mode::Minor,
+            false => Mode::Major,
+        };
+        chord.key_signature = Key::from_note_name(self.root, mode);
+        chord
+    }
+}
+
+/// Parses a progression of chord symbols separated by spaces, `-`, `|` or
+/// `,`, such as `Cm7 - Fm9 - Bb13 - Ebmaj7`.
+pub fn parse_progression(progression: &str) -> Result<Vec<ChordSymbol>, Error> {
+    let symbols = progression
+        .split(|c: char| c.is_whitespace() || c == '|' || c == ',')
+        .flat_map(|part| split_dashes(part))
+        .filter(|symbol| !symbol.is_empty())
+        .map(|symbol| symbol.parse())
+        .collect::<Result<Vec<ChordSymbol>, Error>>()?;
+    match symbols.is_empty() {
+        true => Err(Error::InvalidChordSymbol { symbol: progression.to_string(), position: 0, reason: "there are no chords" }),
+        false => Ok(symbols)
+    }
+}
+
+/// Splits on dashes that separate chords, but not on the dashes in
+/// alterations such as `C7-9`.
+pub(crate) fn split_dashes(part: &str) -> Vec<&str> {
+    let mut symbols = Vec::new();
+    let mut start = 0;
+    for (i, _) in part.match_indices('-') {
+        if !part[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            symbols.push(&part[start..i]);
+            start = i + 1;
+        }
+    }
+    symbols.push(&part[start..]);
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals(symbol: &str) -> Vec<u8> {
+        symbol.parse::<ChordSymbol>().unwrap().note_intervals()
+    }
+
+    #[test]
+    fn qualities() {
+        assert_eq!(intervals("C"), [0, 4, 7]);
+        assert_eq!(intervals("Cm"), [0, 3, 7]);
+        assert_eq!(intervals("Cmin"), [0, 3, 7]);
+        assert_eq!(intervals("Cmaj"), [0, 4, 7]);
+        assert_eq!(intervals("Cdim"), [0, 3, 6]);
+        assert_eq!(intervals("Caug"), [0, 4, 8]);
+        assert_eq!(intervals("C+"), [0, 4, 8]);
+        assert_eq!(intervals("Csus2"), [0, 2, 7]);
+        assert_eq!(intervals("Csus4"), [0, 5, 7]);
+        assert_eq!(intervals("Csus"), [0, 5, 7]);
+        assert_eq!(intervals("C5"), [0, 7]);
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(intervals("C6"), [0, 4, 7, 9]);
+        assert_eq!(intervals("C6/9"), [0, 4, 7, 9, 14]);
+        assert_eq!(intervals("C7"), [0, 4, 7, 10]);
+        assert_eq!(intervals("Cmaj7"), [0, 4, 7, 11]);
+        assert_eq!(intervals("CM7"), [0, 4, 7, 11]);
+        assert_eq!(intervals("CΔ"), [0, 4, 7, 11]);
+        assert_eq!(intervals("Cm7"), [0, 3, 7, 10]);
+        assert_eq!(intervals("CmMaj7"), [0, 3, 7, 11]);
+        assert_eq!(intervals("Cm(maj7)"), [0, 3, 7, 11]);
+        assert_eq!(intervals("Cm7b5"), [0, 3, 6, 10]);
+        assert_eq!(intervals("Cø"), [0, 3, 6, 10]);
+        assert_eq!(intervals("Cdim7"), [0, 3, 6, 9]);
+        assert_eq!(intervals("Caug7"), [0, 4, 8, 10]);
+        assert_eq!(intervals("C9"), [0, 4, 7, 10, 14]);
+        assert_eq!(intervals("Cmaj9"), [0, 4, 7, 11, 14]);
+        assert_eq!(intervals("Cm9"), [0, 3, 7, 10, 14]);
+        assert_eq!(intervals("C11"), [0, 4, 7, 10, 14, 17]);
+        assert_eq!(intervals("C13"), [0, 4, 7, 10, 14, 21]);
+        assert_eq!(intervals("Cm13"), [0, 3, 7, 10, 14, 17, 21]);
+        assert_eq!(intervals("C7sus4"), [0, 5, 7, 10]);
+    }
+
+    #[test]
+    fn alterations_additions_and_omissions() {
+        assert_eq!(intervals("C7b9"), [0, 4, 7, 10, 13]);
+        assert_eq!(intervals("C7#9"), [0, 4, 7, 10, 15]);
+        assert_eq!(intervals("C7(b9, #11)"), [0, 4, 7, 10, 13, 18]);
+        assert_eq!(intervals("C7#5"), [0, 4, 8, 10]);
+        assert_eq!(intervals("C13b9"), [0, 4, 7, 10, 13, 21]);
+        assert_eq!(intervals("C7b13"), [0, 4, 7, 10, 20]);
+        assert_eq!(intervals("Cadd9"), [0, 4, 7, 14]);
+        assert_eq!(intervals("Cmadd9"), [0, 3, 7, 14]);
+        assert_eq!(intervals("Cadd#11"), [0, 4, 7, 18]);
+        assert_eq!(intervals("C7no3"), [0, 7, 10]);
+        assert_eq!(intervals("Cmaj7omit5"), [0, 4, 11]);
+    }
+
+    #[test]
+    fn slash_chords() {
+        let symbol: ChordSymbol = "Ebmaj7#11/G".parse().unwrap();
+        assert_eq!(symbol.root.to_string(), "Eb");
+        assert_eq!(symbol.quality, "maj7#11");
+        assert_eq!(symbol.bass.unwrap().to_string(), "G");
+        assert_eq!(symbol.intervals, [0, 4, 7, 11, 18]);
+        assert_eq!(symbol.note_intervals(), [4, 12, 16, 19, 23, 30]);
+        assert_eq!(symbol.to_string(), "Ebmaj7#11/G");
+
+        let chord = symbol.to_chord();
+        assert_eq!(chord.root, 3);
+        assert_eq!(chord.get_notes()[0] % 12, 7);
+        assert_eq!(chord.get_name(), "Eb maj7#11/G");
+        assert_eq!(chord.get_note_names(), "G, Eb, G, Bb, D, A");
+
+        assert_eq!(intervals("C/C"), [0, 4, 7]);
+    }
+
+    #[test]
+    fn spelling() {
+        let chord = "D#m7".parse::<ChordSymbol>().unwrap().to_chord();
+        assert_eq!(chord.get_note_names(), "D#, F#, A#, C#");
+        let chord = "Ebm7".parse::<ChordSymbol>().unwrap().to_chord();
+        assert_eq!(chord.get_note_names(), "Eb, Gb, Bb, Db");
+        let chord = "C".parse::<ChordSymbol>().unwrap().to_chord();
+        assert_eq!(chord.get_name(), "C major");
+    }
+
+    #[test]
+    fn errors() {
+        for symbol in ["", "H7", "c", "C8", "C7#7", "Cadd3", "Csus3", "C7/", "C/X", "C/Gb7", "Cno9", "Cx"] {
+            assert!(symbol.parse::<ChordSymbol>().is_err(), "`{}` should not parse", symbol);
+        }
+        match "Cm7$".parse::<ChordSymbol>() {
+            Err(Error::InvalidChordSymbol { position, .. }) => assert_eq!(position, 3),
+            other => panic!("expected an invalid chord symbol error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn progressions() {
+        let chords = parse_progression("Cm7 - Fm9 - Bb13 - Ebmaj7").unwrap();
+        let names: Vec<String> = chords.iter().map(|c| c.to_string()).collect();
+        assert_eq!(names, ["Cm7", "Fm9", "Bb13", "Ebmaj7"]);
+        assert_eq!(parse_progression("C|Am,F G7-9").unwrap().len(), 4);
+        assert_eq!(parse_progression("C-Am-F-G").unwrap().len(), 4);
+        assert!(parse_progression(" - ").is_err());
+        assert!(parse_progression("C - Q").is_err());
+    }
+}
@@ -1,15 +1,36 @@
 use std::hash::Hash;
 
+use serde::{Deserialize, Serialize};
+
 use crate::music_modules_v2::music::notes::*;
 
+/// The roots that are used when a chord type does not list any.
+const ALL_ROOTS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
 
-#[derive(Clone, Debug)]
+/// A chord type, such as a minor 7th chord, along with the roots that it may 
+/// be played on in C minor.
+/// 
+/// When deserializing, `roots` defaults to every root, and `optional_notes` 
+/// and `tags` default to being empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChordType {
+    #[serde(rename = "intervals", alias = "note_intervals")]
     pub note_intervals: Vec<u8>,
     pub name: String,
+    #[serde(default = "all_roots_vec")]
     pub roots: Vec<u8>,
-    pub optional_notes: Vec<u8>
+    #[serde(default)]
+    pub optional_notes: Vec<u8>,
+    /// Extra names that the chord type can be selected by in 
+    /// `GenerationConfig::chord_selection`.
+    #[serde(default)]
+    pub tags: Vec<String>
+}
+
+fn all_roots_vec() -> Vec<u8> {
+    ALL_ROOTS.to_vec()
 }
+
 impl ChordType {
     pub fn new(name: &str, intervals: &[u8], root_vec: &[u8], optional_notes_vec: Option<&[u8]>) -> Self {
         let opt_notes: Vec<u8>;
@@ -23,6 +44,7 @@ impl ChordType {
             name: name.to_string(),
             roots: root_vec.to_vec(),
             optional_notes: opt_notes,
+            tags: Vec::new(),
         }
     }
     pub fn all_roots(name: &str, notes: &[u8], optional_notes_vec: Option<&[u8]>) -> Self {
@@ -35,16 +57,25 @@ impl ChordType {
         ChordType {
             note_intervals: notes.to_vec(),
             name: name.to_string(),
-            roots: vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            roots: all_roots_vec(),
             optional_notes: opt_notes,
+            tags: Vec::new(),
         }
     }
     /// Sets this chord type to use all roots.
     pub fn use_all_roots(&mut self, should_use_all_roots: bool) {
         if should_use_all_roots {
-            self.roots = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+            self.roots = all_roots_vec();
         }
     }
+
+    /// Returns whether `selection` contains the name or one of the tags of 
+    /// this chord type.
+    pub fn is_selected_by<'a, I: IntoIterator<Item = &'a String>>(&self, selection: I) -> bool {
+        selection
+            .into_iter()
+            .any(|selected| selected.eq(&self.name) || self.tags.contains(selected))
+    }
 }
 
 impl Default for ChordType {
@@ -53,7 +84,8 @@ impl Default for ChordType {
             note_intervals: Vec::new(),
             roots: Vec::new(),
             name: String::new(),
-            optional_notes: Vec::new()
+            optional_notes: Vec::new(),
+            tags: Vec::new()
         }
     }
 }
@@ -103,8 +135,50 @@ pub fn default_chord_types() -> Vec<ChordType> {
     ]
 }
 
-#[cfg(not(target_arch="wasm32"))]
-#[allow(unused)]
+/// The chord types that the original chord table was built from.
+pub fn original_chord_types() -> Vec<ChordType> {
+    let minor7_og = ChordType::new("minor 7", &[0, 3, 6, 10], &[0, 2, 5, 7], None);
+    let augmented_og = ChordType::new("augmented", &[0, 4, 8], &[10], Some(&[12]));
+    let major7_og = ChordType::new("major 7", &[0, 4, 7, 11], &[3, 8], None);
+    let diminished_og = ChordType::new("diminished", &[0, 3, 6], &[3], None);
+    let major6_og = ChordType::new("major 6", &[0, 4, 7, 9], &[10], None);
+    let minor9 = ChordType::new("minor 9", &[0, 3, 7, 10, 14], &[G], None);
+
+    vec![minor7_og, minor9, augmented_og, major7_og, diminished_og, major6_og]
+}
+
+/// Major and minor triads.
+pub fn major_and_minor_chord_types() -> Vec<ChordType> {
+    let major = ChordType::new("major", &[0, 4, 7], &[DSHARP, GSHARP, ASHARP], None);
+    let minor = ChordType::new("minor", &[0, 3, 7], &[C, D, F, G], None);
+    vec![major, minor]
+}
+
+/// The names used to select each of the hand-picked chord types in 
+/// `GenerationConfig::chord_selection`, in the same order as 
+/// `all_custom_chord_types_handpicked`.
+pub const CUSTOM_CHORD_TYPE_IDS: [&str; 17] = [
+    "minor7",
+    "major7",
+    "diminished",
+    "augmented",
+    "major6",
+    "minor6",
+    "major9",
+    "major7sharp9",
+    "major7flat5sharp9",
+    "major9flat5",
+    "major7flat9",
+    "major",
+    "minor",
+    "minor9",
+    "major13",
+    "dominant9",
+    "add9"
+];
+
+/// The chord types that can be picked from in the custom chord type groups. 
+/// Each chord type is tagged with its name in `CUSTOM_CHORD_TYPE_IDS`.
 pub fn all_custom_chord_types_handpicked() -> Vec<ChordType> {
     let minor7 = ChordType::new("minor 7", &[0, 10, 15, 19], &[C, D, F, FSHARP, ASHARP], None);
     let major7 = ChordType::new("major 7", &[0, 11, 16, 19], &[DSHARP, GSHARP], None);
@@ -150,4 +224,48 @@ pub fn all_custom_chord_types_handpicked() -> Vec<ChordType> {
         dominant9,
         add9
     ]
-}
\ No newline at end of file
+    .into_iter()
+    .zip(CUSTOM_CHORD_TYPE_IDS)
+    .map(|(mut chord_type, id)| {
+        chord_type.tags.push(id.to_string());
+        chord_type
+    })
+    .collect()
+}
+
+/// The chord types that can be picked from in `ChordTypeGroup::CustomPruning`.
+/// They are the hand-picked chord types, except that major 13 does not have 
+/// optional notes, since pruning would turn them into extra chords.
+pub fn custom_pruning_chord_types() -> Vec<ChordType> {
+    let mut chord_types = all_custom_chord_types_handpicked();
+    for chord_type in chord_types.iter_mut().filter(|chord_type| chord_type.name == "major 13") {
+        chord_type.optional_notes.clear();
+    }
+    chord_types
+}
+
+/// The chord types that chords are recognized by when reading MIDI files: the
+/// hand-picked chord types, along with a few common chords that they do not
+/// cover. Chord types with the same pitch classes are only listed once.
+pub fn recognizable_chord_types() -> Vec<ChordType> {
+    let extra = [
+        ChordType::all_roots("dominant 7", &[0, 4, 7, 10], None),
+        ChordType::all_roots("half-diminished 7", &[0, 3, 6, 10], None),
+        ChordType::all_roots("diminished 7", &[0, 3, 6, 9], None),
+        ChordType::all_roots("sus2", &[0, 2, 7], None),
+        ChordType::all_roots("sus4", &[0, 5, 7], None),
+    ];
+    let mut pitch_class_sets: Vec<Vec<u8>> = Vec::new();
+    all_custom_chord_types_handpicked()
+        .into_iter()
+        .chain(extra)
+        .filter(|chord_type| {
+            let mut pitch_classes: Vec<u8> = chord_type.note_intervals.iter().map(|interval| interval % 12).collect();
+            pitch_classes.sort_unstable();
+            pitch_classes.dedup();
+            let is_new = !pitch_class_sets.contains(&pitch_classes);
+            pitch_class_sets.push(pitch_classes);
+            is_new
+        })
+        .collect()
+}
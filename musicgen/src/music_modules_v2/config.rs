@@ -0,0 +1,822 @@
+//! Typed configuration for generating music.
+//!
+//! Every option that used to be passed around as a `&str` is represented by an
+//! enum here. The string values match the ones used by the web app, so a
+//! `GenerationConfig` can be (de)serialized from the same JSON that the
+//! frontend produces, and each enum can be parsed with `FromStr`.
+
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{arpeggio::Arpeggio, bass::BassLine, chord_library::ChordLibrary, drums::Drums, error::Error, humanize::Humanizer, key::Key, key_detection::KeyCandidate, markov::MarkovModel, melody::Melody, meter::{Meter, TimeSignature}, midi::TrackSettings, patterns::grammar::{Pattern, PatternStep}, reharmonize::Reharmonizer, rhythm::Rhythm, strum::Strum, scale::PitchClassSet, voicing::VoiceLeading};
+
+/// Defines a fieldless enum with a fixed string value for each variant, along
+/// with `FromStr`, `Display` and serde implementations that use those strings.
+macro_rules! string_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $description:literal, {
+            $(
+                $(#[$variant_meta:meta])*
+                ($variant:ident, $value:literal)
+            ),*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                #[serde(rename = $value)]
+                $variant,
+            )*
+        }
+
+        impl $name {
+            /// Every variant, in declaration order.
+            pub const ALL: &'static [$name] = &[$($name::$variant),*];
+
+            /// The string value of every variant, in declaration order.
+            pub const VALUES: &'static [&'static str] = &[$($value),*];
+
+            /// Returns the string value of this variant.
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value,)*
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($value => Ok($name::$variant),)*
+                    _ => Err(Error::UnknownOption {
+                        option: $description,
+                        value: s.to_string(),
+                        expected: $name::VALUES,
+                    })
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+string_enum!(
+    /// How the picked chords are placed in the track.
+    GenerationMode, "generation mode", {
+        /// The original placement algorithm, where each note of the chord gets
+        /// random lengths.
+        (Melody, "melody"),
+        /// Block chords that are played for `duration` beats.
+        (Chords, "chords"),
+        /// Each note gets random lengths, and gets shifted by an octave at
+        /// random.
+        (MelodyV2, "melody v2"),
+        /// Each note gets random lengths and velocities.
+        (MelodyV3, "melody v3"),
+        /// The "fixed" version of `Melody`, where every note of the chord
+        /// shares the same random lengths.
+        (Intended, "intended"),
+        /// The notes of the chord are played one at a time, using 
+        /// `GenerationConfig::arpeggio`.
+        (Arpeggio, "arpeggio"),
+        /// The chord is strummed on every beat, or rolled once, using 
+        /// `GenerationConfig::strum`.
+        (Strum, "strum")
+    }
+);
+
+string_enum!(
+    /// How a bass line follows the chords.
+    BassStyle, "bass style", {
+        /// The root, held for the whole chord.
+        (Root, "root"),
+        /// The root and the fifth, alternating on every beat.
+        (RootFifth, "root-fifth"),
+        /// A note on every beat, starting with the root, then chord tones, 
+        /// and then a chromatic approach to the next root.
+        (Walking, "walking"),
+        /// The root and the octave above it, alternating on every half beat.
+        (Octaves, "octaves"),
+        /// A 3-3-2 rhythm of roots and fifths.
+        (Syncopated, "syncopated")
+    }
+);
+
+string_enum!(
+    /// The style of a drum track.
+    DrumGenre, "drum genre", {
+        /// Half-time kicks and claps under fast hi-hats.
+        (Trap, "trap"),
+        /// A kick on every beat, with open hi-hats in between.
+        (House, "house"),
+        /// A laid-back hip hop beat.
+        (LoFi, "lo-fi"),
+        /// A backbeat, with the snare on 2 and 4.
+        (Rock, "rock")
+    }
+);
+
+string_enum!(
+    /// The rise and fall of the phrases of a melody.
+    PhraseShape, "phrase shape", {
+        /// Rises to the middle of the phrase and falls back down.
+        (Arch, "arch"),
+        /// Rises from the start of the phrase to the end.
+        (Ascending, "ascending"),
+        /// A rising call, a rest, and a falling response.
+        (CallAndResponse, "call-and-response")
+    }
+);
+
+string_enum!(
+    /// The direction of a strum. A down strum goes from the lowest note to 
+    /// the highest, like a guitar.
+    StrumDirection, "strum direction", {
+        (Down, "down"),
+        (Up, "up"),
+        /// Down on the first beat, up on the second, and so on.
+        (Alternating, "alternating")
+    }
+);
+
+string_enum!(
+    /// The order that an arpeggio plays the notes of a chord in.
+    ArpeggioOrder, "arpeggio order", {
+        /// From the lowest note to the highest.
+        (Up, "up"),
+        /// From the highest note to the lowest.
+        (Down, "down"),
+        /// Up and then back down, without repeating the highest and lowest 
+        /// notes.
+        (UpDown, "up-down"),
+        /// A random note for every step.
+        (Random, "random"),
+        /// The order of the notes in the chord, or in its voicing.
+        (AsPlayed, "as played")
+    }
+);
+
+string_enum!(
+    /// How often an arpeggio plays a note.
+    ArpeggioRate, "arpeggio rate", {
+        (Quarter, "1/4"),
+        (Eighth, "1/8"),
+        (EighthTriplet, "1/8t"),
+        (Sixteenth, "1/16"),
+        (SixteenthTriplet, "1/16t")
+    }
+);
+
+string_enum!(
+    /// How a MIDI file is cut up before its chords are recognized.
+    Segmentation, "segmentation", {
+        /// One chord per bar, using the first time signature of the file.
+        (Bar, "bar"),
+        /// One chord per group of notes that start together.
+        (Onsets, "onsets")
+    }
+);
+
+string_enum!(
+    /// The notes that are swung by a `Humanizer`.
+    SwingUnit, "swing unit", {
+        (Eighth, "1/8"),
+        (Sixteenth, "1/16")
+    }
+);
+
+string_enum!(
+    /// How chords are picked from the chord table.
+    ChordPickingMethod, "chord picking method", {
+        /// Picks a random column of the 2D chord table, then a random chord in
+        /// that column. Chords with more notes are somewhat more likely to be
+        /// picked.
+        (Original, "original"),
+        /// Picks a random chord from the 1D chord list. Each chord has a
+        /// roughly equal probability of getting picked.
+        (OneDimensional, "1D"),
+        /// Picks each chord from the 1D chord list based on the chords before
+        /// it, using `GenerationConfig::markov_model`.
+        (Markov, "markov"),
+        /// Picks each chord from the 1D chord list, where chords that can be
+        /// reached with less movement from the previous chord are more likely
+        /// to be picked. Uses `GenerationConfig::voice_leading`, or the
+        /// default settings when it is `None`.
+        (VoiceLeading, "voice leading")
+    }
+);
+
+string_enum!(
+    /// The group of chord types that the chord table is built from.
+    ChordTypeGroup, "chord type group", {
+        (Default, "default"),
+        (MajorAndMinor, "major and minor"),
+        (Original, "original"),
+        /// Hand-picked roots for the chord types in `chord_selection`.
+        (Custom, "custom"),
+        /// All roots for the chord types in `chord_selection`; meant to be
+        /// used with a scale.
+        (CustomPruning, "custom_pruning")
+    }
+);
+
+string_enum!(
+    /// The scale used for pruning chords. The modes are rooted on the tonic
+    /// of the key, so `Dorian` is C Dorian in both C minor and C major rather
+    /// than a mode of either key. The other scales are relative to the chord
+    /// table, which is in C minor, or in the relative minor of major keys.
+    Scale, "scale", {
+        (Disabled, "disabled"),
+        (Natural, "natural"),
+        (Melodic, "melodic"),
+        (Harmonic, "harmonic"),
+        (Pentatonic, "pentatonic"),
+        (Romanian, "romanian"),
+        (Hungarian, "hungarian"),
+        /// Does not prune anything, but turns chords with optional notes into
+        /// new chords.
+        (AllNotes, "all_notes"),
+        (HalfWhole, "half_whole"),
+        (WholeHalf, "whole_half"),
+        (Ionian, "ionian"),
+        (Dorian, "dorian"),
+        (Phrygian, "phrygian"),
+        (Lydian, "lydian"),
+        (Mixolydian, "mixolydian"),
+        (Locrian, "locrian"),
+        (Blues, "blues"),
+        (MajorPentatonic, "major_pentatonic"),
+        /// The bebop dominant scale.
+        (Bebop, "bebop"),
+        (Altered, "altered"),
+        (WholeTone, "whole_tone"),
+        (PhrygianDominant, "phrygian_dominant"),
+        /// Uses the notes in `GenerationConfig::custom_scale`.
+        (Custom, "custom")
+    }
+);
+
+string_enum!(
+    /// How the chord table is arranged.
+    TableScheme, "table scheme", {
+        /// Each column contains every chord that contains the column's note.
+        (ContainsNote, "contains_note"),
+        /// Each column contains the chords whose highest note is the column's
+        /// note.
+        (HighestNote, "highest_note"),
+        /// Each column contains the chords whose lowest note is the column's
+        /// note.
+        (LowestNote, "lowest_note")
+    }
+);
+
+string_enum!(
+    /// A built-in rhythm for `GenerationConfig::rhythm`. Each one is a bar of
+    /// 4/4 on a 16th note grid.
+    Groove, "groove", {
+        /// The 3-3-2 stabs of the dembow: `x..x..x.x..x..x.`
+        (Reggaeton, "reggaeton"),
+        /// Short stabs on every beat: `x...x...x...x...`
+        (FourOnTheFloor, "four on the floor"),
+        /// A dotted quarter note and an eighth note: `x-----x-........`
+        (Charleston, "charleston"),
+        /// The bossa nova clave: `x..x..x...x..x..`
+        (Bossa, "bossa")
+    }
+);
+
+string_enum!(
+    /// A notation format that a `Score` can be written in.
+    NotationFormat, "notation format", {
+        (MusicXml, "musicxml"),
+        (LilyPond, "lilypond"),
+        (Abc, "abc")
+    }
+);
+
+/// All of the options for generating music.
+///
+/// Missing fields are filled in with their defaults when deserializing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationConfig {
+    /// A key such as `Cmin` or `D#maj`, or `random`.
+    pub key: String,
+    pub chord_type_group: ChordTypeGroup,
+    /// The names or tags of the chord types to use with 
+    /// `ChordTypeGroup::Custom` and `ChordTypeGroup::CustomPruning`.
+    pub chord_selection: HashSet<String>,
+    /// Extra chord types. They are added to every chord type group, except
+    /// for the custom groups, where they are only used when they are in 
+    /// `chord_selection`. A chord type with the same name as a built-in one 
+    /// replaces it.
+    pub chord_library: ChordLibrary,
+    pub scale: Scale,
+    /// The notes of `Scale::Custom`.
+    pub custom_scale: Option<PitchClassSet>,
+    pub table_scheme: TableScheme,
+    /// Sorts the chords after pruning so that the output does not depend on
+    /// `HashSet` ordering.
+    pub is_reproducible: bool,
+    /// Gives every chord type all 12 roots.
+    pub use_all_roots: bool,
+    pub generation_mode: GenerationMode,
+    pub chord_picking_method: ChordPickingMethod,
+    /// The model used by `ChordPickingMethod::Markov`, or `None` for the 
+    /// built-in model.
+    pub markov_model: Option<MarkovModel>,
+    /// Voices each chord smoothly after the previous one, in every generation
+    /// mode. `None` places every chord in root position.
+    pub voice_leading: Option<VoiceLeading>,
+    pub num_chords: usize,
+    /// Picks `num_chords` chords up front and reuses them for the pattern,
+    /// rather than picking a new chord for every bar.
+    pub should_use_same_chords: bool,
+    pub min_number_of_unique_chords: u32,
+    /// The order to play the chords in, using 1-based indices, along with 
+    /// their durations, rests, repeats and sections. Only used by
+    /// `GenerationMode::Chords`.
+    pub pattern: Pattern,
+    /// How often each chord is played, in beats. Only used by
+    /// `GenerationMode::Chords` when `should_use_same_chords` is set.
+    pub duration: u32,
+    /// The order, rate, gate and octaves of `GenerationMode::Arpeggio`.
+    pub arpeggio: Arpeggio,
+    /// The direction and timing of `GenerationMode::Strum`.
+    pub strum: Strum,
+    /// Writes a bass line that follows the chords to its own track.
+    pub bass: Option<BassLine>,
+    /// Writes a melody over the chords to its own track, using the notes of 
+    /// the scale.
+    pub melody: Option<Melody>,
+    /// Writes drums to their own track, on the same bars as the chords.
+    pub drums: Option<Drums>,
+    /// Picks each chord so that it contains the notes that a melody plays on
+    /// its strong beats, instead of picking the chords at random. The
+    /// chords are placed with the generation mode, for as long as the melody
+    /// lasts.
+    pub reharmonize: Option<Reharmonizer>,
+    /// Moves the notes of every track off the grid and changes their 
+    /// velocities, after they are placed.
+    pub humanize: Option<Humanizer>,
+    /// Places every chord with this rhythm, repeated until the chord ends, 
+    /// instead of the note lengths of the generation mode.
+    pub rhythm: Option<Rhythm>,
+    /// The name, channel and instrument of the chord track. By default, the 
+    /// track is unnamed and on channel 0.
+    pub chord_track: TrackSettings,
+    /// The title of the song, which is written as the name of the first track.
+    pub title: Option<String>,
+    /// The tempo in beats per minute.
+    pub bpm: f64,
+    pub time_signature: TimeSignature,
+    /// How many bars each chord lasts, such as `2.0` for two bars or `0.5` 
+    /// for half a bar.
+    pub bars_per_chord: f64,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            key: "random".to_string(),
+            chord_type_group: ChordTypeGroup::Default,
+            chord_selection: HashSet::new(),
+            chord_library: ChordLibrary::default(),
+            scale: Scale::Disabled,
+            custom_scale: None,
+            table_scheme: TableScheme::ContainsNote,
+            is_reproducible: false,
+            use_all_roots: false,
+            generation_mode: GenerationMode::Melody,
+            chord_picking_method: ChordPickingMethod::Original,
+            markov_model: None,
+            voice_leading: None,
+            num_chords: 20,
+            should_use_same_chords: true,
+            min_number_of_unique_chords: 0,
+            pattern: Pattern::default(),
+            duration: 4,
+            arpeggio: Arpeggio::default(),
+            strum: Strum::default(),
+            bass: None,
+            melody: None,
+            drums: None,
+            reharmonize: None,
+            humanize: None,
+            rhythm: None,
+            chord_track: TrackSettings::default(),
+            title: None,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+            bars_per_chord: 1.0,
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// Creates a builder, starting from the default config.
+    pub fn builder() -> GenerationConfigBuilder {
+        GenerationConfigBuilder::default()
+    }
+
+    /// Returns the chord numbers of the pattern if it applies to the 
+    /// generation mode, otherwise an empty list.
+    pub fn chord_order(&self) -> Vec<u8> {
+        self.pattern_steps().iter().filter_map(|step| step.chord).collect()
+    }
+
+    /// Returns the chords and rests of the pattern if it applies to the 
+    /// generation mode, otherwise an empty list.
+    pub fn pattern_steps(&self) -> Vec<PatternStep<'_>> {
+        match self.generation_mode {
+            GenerationMode::Chords => self.pattern.steps(),
+            _ => Vec::new()
+        }
+    }
+
+    /// The time signature and the length of each chord.
+    pub fn meter(&self) -> Result<Meter, Error> {
+        Meter::new(self.time_signature, self.bars_per_chord)
+    }
+
+    /// Checks the options that the types cannot guarantee.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.generation_mode == GenerationMode::Chords && !self.pattern.is_empty() {
+            self.pattern.validate()?;
+        }
+        self.chord_track.validate()?;
+        self.meter()?;
+        if let Some(bass) = self.bass.as_ref() {
+            bass.validate()?;
+        }
+        if let Some(melody) = self.melody.as_ref() {
+            melody.validate()?;
+        }
+        if let Some(drums) = self.drums.as_ref() {
+            drums.validate()?;
+        }
+        if let Some(reharmonizer) = self.reharmonize.as_ref() {
+            reharmonizer.validate()?;
+        }
+        if let Some(humanizer) = self.humanize.as_ref() {
+            humanizer.validate()?;
+        }
+        match self.generation_mode {
+            GenerationMode::Arpeggio => self.arpeggio.validate()?,
+            GenerationMode::Strum => self.strum.validate()?,
+            _ => {}
+        }
+        if self.duration == 0 {
+            return Err(Error::InvalidInput("the duration of a chord must be at least 1 beat".to_string()));
+        }
+        // the tempo is written as a 24-bit number of microseconds per beat
+        if !(4.0..=1000.0).contains(&self.bpm) {
+            return Err(Error::InvalidInput(format!("the tempo must be between 4 and 1000 BPM, but it is {}", self.bpm)));
+        }
+        if let Some(voice_leading) = self.voice_leading {
+            if voice_leading.lowest_note > voice_leading.highest_note || voice_leading.highest_note > 127 {
+                return Err(Error::InvalidInput(format!(
+                    "the voice leading range must be between MIDI notes 0 and 127, but it is {} to {}",
+                    voice_leading.lowest_note,
+                    voice_leading.highest_note
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the notes of the scale to prune the chords to in `key`, 
+    /// relative to the tonic of the chord table, or `None` when the chords 
+    /// should not be pruned.
+    pub fn scale_notes(&self, key: &Key) -> Result<Option<PitchClassSet>, Error> {
+        match (self.scale, self.custom_scale) {
+            (Scale::Custom, Some(notes)) => Ok(Some(notes)),
+            (Scale::Custom, None) => Err(Error::InvalidScale {
+                scale: Scale::Custom.to_string(),
+                reason: "the custom scale does not have any notes"
+            }),
+            (scale, _) => Ok(scale.pitch_classes().map(|notes| notes.transposed(scale.root(key))))
+        }
+    }
+
+    /// Sets the fields that are in `json`, which is a `GenerationConfig` as a 
+    /// JSON object such as `{"bpm": 90, "bass": {"style": "walking"}}`, and 
+    /// keeps the other fields.
+    pub fn with_json(self, json: &str) -> Result<Self, Error> {
+        let serde_json::Value::Object(fields) = serde_json::from_str(json)? else {
+            return Err(Error::InvalidInput("expected the config to be a JSON object".to_string()));
+        };
+        let mut config = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(config) = &mut config {
+            config.extend(fields);
+        }
+        Ok(serde_json::from_value(config)?)
+    }
+
+    /// Returns the number of chords to pick, which must be large enough for
+    /// every index in the pattern.
+    pub fn total_chords(&self) -> usize {
+        self.chord_order()
+            .iter()
+            .fold(self.num_chords, |max, idx| max.max(*idx as usize))
+    }
+}
+
+/// Builds a `GenerationConfig`.
+///
+/// ```
+/// use musicgen::music_modules_v2::config::*;
+///
+/// let config = GenerationConfig::builder()
+///     .key("F#min")
+///     .generation_mode(GenerationMode::Chords)
+///     .scale("natural".parse().unwrap())
+///     .num_chords(4)
+///     .build();
+/// assert_eq!(config.scale, Scale::Natural);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GenerationConfigBuilder {
+    config: GenerationConfig
+}
+
+macro_rules! builder_setters {
+    ($(($field:ident, $ty:ty)),*) => {
+        $(
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.config.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl GenerationConfigBuilder {
+    builder_setters!(
+        (chord_type_group, ChordTypeGroup),
+        (chord_selection, HashSet<String>),
+        (chord_library, ChordLibrary),
+        (scale, Scale),
+        (table_scheme, TableScheme),
+        (is_reproducible, bool),
+        (use_all_roots, bool),
+        (generation_mode, GenerationMode),
+        (chord_picking_method, ChordPickingMethod),
+        (num_chords, usize),
+        (should_use_same_chords, bool),
+        (min_number_of_unique_chords, u32),
+        (duration, u32),
+        (chord_track, TrackSettings),
+        (bpm, f64),
+        (time_signature, TimeSignature),
+        (bars_per_chord, f64),
+        (arpeggio, Arpeggio),
+        (strum, Strum)
+    );
+
+    /// Sets the pattern, which can be a list of chord numbers.
+    pub fn pattern(mut self, pattern: impl Into<Pattern>) -> Self {
+        self.config.pattern = pattern.into();
+        self
+    }
+
+    /// Parses a pattern such as `[verse] 1-2:2-_-(3-4)x2`.
+    pub fn pattern_str(self, pattern: &str) -> Result<Self, Error> {
+        Ok(self.pattern(pattern.parse::<Pattern>()?))
+    }
+
+    /// Prunes the chords to a custom scale.
+    pub fn custom_scale(mut self, notes: PitchClassSet) -> Self {
+        self.config.scale = Scale::Custom;
+        self.config.custom_scale = Some(notes);
+        self
+    }
+
+    /// Sets the scale from either the name of a scale, or a custom scale such
+    /// as `2-1-2-2-1-2-2` or `0, 2, 3, 5, 7, 8, 10`.
+    pub fn scale_str(self, scale: &str) -> Result<Self, Error> {
+        match scale.parse::<Scale>() {
+            Ok(scale) => Ok(self.scale(scale)),
+            // the names of the scales are lowercase and never start with a digit
+            Err(err) => match scale.trim_start().starts_with(|c: char| c.is_ascii_digit() || ('A'..='G').contains(&c)) {
+                true => Ok(self.custom_scale(scale.parse()?)),
+                false => Err(err)
+            }
+        }
+    }
+
+    /// Picks chords with a Markov chain that uses `model`.
+    pub fn markov_model(mut self, model: MarkovModel) -> Self {
+        self.config.chord_picking_method = ChordPickingMethod::Markov;
+        self.config.markov_model = Some(model);
+        self
+    }
+
+    /// Voices the chords with `voice_leading`.
+    pub fn voice_leading(mut self, voice_leading: VoiceLeading) -> Self {
+        self.config.voice_leading = Some(voice_leading);
+        self
+    }
+
+    /// Uses the key and scale of a detected key, which prunes the chord table 
+    /// to the notes of the scale.
+    pub fn detected_key(mut self, candidate: &KeyCandidate) -> Self {
+        self.config.key = candidate.key.to_string();
+        self.config.scale = candidate.scale;
+        self
+    }
+
+    /// Adds a bass line to its own track.
+    pub fn bass(mut self, bass: BassLine) -> Self {
+        self.config.bass = Some(bass);
+        self
+    }
+
+    /// Adds a melody to its own track.
+    pub fn melody(mut self, melody: Melody) -> Self {
+        self.config.melody = Some(melody);
+        self
+    }
+
+    /// Adds drums to their own track.
+    pub fn drums(mut self, drums: Drums) -> Self {
+        self.config.drums = Some(drums);
+        self
+    }
+
+    /// Picks the chords for the melody of `reharmonizer`.
+    pub fn reharmonize(mut self, reharmonizer: Reharmonizer) -> Self {
+        self.config.reharmonize = Some(reharmonizer);
+        self
+    }
+
+    /// Humanizes every track with `humanizer`.
+    pub fn humanize(mut self, humanizer: Humanizer) -> Self {
+        self.config.humanize = Some(humanizer);
+        self
+    }
+
+    /// Places every chord with `rhythm`, such as a `Groove`.
+    pub fn rhythm(mut self, rhythm: impl Into<Rhythm>) -> Self {
+        self.config.rhythm = Some(rhythm.into());
+        self
+    }
+
+    /// Parses the name of a groove or a rhythm such as `x..x..x.` or 
+    /// `q q e e h`.
+    pub fn rhythm_str(self, rhythm: &str) -> Result<Self, Error> {
+        Ok(self.rhythm(rhythm.parse::<Rhythm>()?))
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.config.title = Some(title.to_string());
+        self
+    }
+
+    pub fn key(mut self, key: &str) -> Self {
+        self.config.key = key.to_string();
+        self
+    }
+
+    pub fn build(self) -> GenerationConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_round_trips() {
+        for mode in GenerationMode::ALL {
+            assert_eq!(mode.as_str().parse::<GenerationMode>().unwrap(), *mode);
+        }
+        for scale in Scale::ALL {
+            assert_eq!(scale.to_string().parse::<Scale>().unwrap(), *scale);
+        }
+        assert_eq!("1D".parse::<ChordPickingMethod>().unwrap(), ChordPickingMethod::OneDimensional);
+        assert_eq!("custom_pruning".parse::<ChordTypeGroup>().unwrap(), ChordTypeGroup::CustomPruning);
+        assert_eq!("lowest_note".parse::<TableScheme>().unwrap(), TableScheme::LowestNote);
+    }
+
+    #[test]
+    fn unknown_values_are_rejected() {
+        let err = "melody_v3".parse::<GenerationMode>().unwrap_err();
+        assert!(err.to_string().contains("`melody_v3`"), "{}", err);
+        assert!(err.to_string().contains("`melody v3`"), "{}", err);
+        assert!("pentatonik".parse::<Scale>().is_err());
+        assert!("2D".parse::<ChordPickingMethod>().is_err());
+    }
+
+    #[test]
+    fn serde_uses_string_values() {
+        let config = GenerationConfig::builder()
+            .generation_mode(GenerationMode::MelodyV2)
+            .chord_picking_method(ChordPickingMethod::OneDimensional)
+            .build();
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["generation_mode"], "melody v2");
+        assert_eq!(json["chord_picking_method"], "1D");
+
+        let parsed: GenerationConfig = serde_json::from_str(r#"{"key": "Dmin", "scale": "harmonic"}"#).unwrap();
+        assert_eq!(parsed.key, "Dmin");
+        assert_eq!(parsed.scale, Scale::Harmonic);
+        assert_eq!(parsed.generation_mode, GenerationMode::Melody);
+
+        assert!(serde_json::from_str::<GenerationConfig>(r#"{"scale": "nope"}"#).is_err());
+
+        let parsed: GenerationConfig = serde_json::from_str(r#"{"scale": "custom", "custom_scale": "2-2-3-2-3"}"#).unwrap();
+        assert_eq!(parsed.scale_notes(&Key::default()).unwrap(), Scale::MajorPentatonic.pitch_classes());
+    }
+
+    #[test]
+    fn merging_json() {
+        let config = GenerationConfig::builder()
+            .key("Dmin")
+            .scale(Scale::Dorian)
+            .pattern_str("1-2-(3)x2")
+            .unwrap()
+            .build();
+        assert_eq!(config.clone().with_json("{}").unwrap(), config);
+        let merged = config.clone().with_json(r#"{"bpm": 90, "time_signature": "3/4", "bass": {"style": "walking"}, "rhythm": "bossa"}"#).unwrap();
+        assert_eq!(merged.key, "Dmin");
+        assert_eq!(merged.pattern, config.pattern);
+        assert_eq!(merged.bpm, 90.0);
+        assert_eq!(merged.time_signature.to_string(), "3/4");
+        assert!(merged.bass.is_some() && merged.rhythm.is_some());
+        assert!(config.clone().with_json("[1]").is_err());
+        assert!(config.with_json(r#"{"scale": "nope"}"#).is_err());
+    }
+
+    #[test]
+    fn custom_scales() {
+        let config = GenerationConfig::builder().scale_str("dorian").unwrap().build();
+        assert_eq!(config.scale, Scale::Dorian);
+        assert_eq!(config.scale_notes(&Key::default()).unwrap(), Scale::Dorian.pitch_classes());
+
+        let config = GenerationConfig::builder().scale_str("1-3-1-2-1-2-2").unwrap().build();
+        assert_eq!(config.scale, Scale::Custom);
+        assert_eq!(config.scale_notes(&Key::default()).unwrap(), Scale::PhrygianDominant.pitch_classes());
+
+        assert!(matches!(
+            GenerationConfig::builder().scale_str("dorain"),
+            Err(Error::UnknownOption { .. })
+        ));
+        assert!(matches!(
+            GenerationConfig::builder().scale_str("0, 13"),
+            Err(Error::InvalidScale { .. })
+        ));
+
+        let config = GenerationConfig::builder().scale(Scale::Custom).build();
+        assert!(config.scale_notes(&Key::default()).is_err());
+        assert_eq!(GenerationConfig::default().scale_notes(&Key::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn pattern_only_applies_to_chords() {
+        let config = GenerationConfig::builder()
+            .num_chords(2)
+            .pattern(vec![1, 2, 5])
+            .build();
+        assert!(config.chord_order().is_empty());
+        assert_eq!(config.total_chords(), 2);
+
+        let config = GenerationConfig { generation_mode: GenerationMode::Chords, ..config };
+        assert_eq!(config.chord_order(), &[1, 2, 5]);
+        assert_eq!(config.total_chords(), 5);
+        assert!(config.validate().is_ok());
+
+        let config = GenerationConfig { pattern: vec![1, 12, 0].into(), ..config };
+        match config.validate() {
+            Err(Error::InvalidPattern { pattern, position, .. }) => {
+                assert_eq!(pattern, "1-12-0");
+                assert_eq!(position, 5);
+            },
+            other => panic!("expected an invalid pattern error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn voice_leading_range() {
+        let config: GenerationConfig = serde_json::from_str(r#"{"voice_leading": {"max_leap": 4}}"#).unwrap();
+        assert_eq!(config.voice_leading, Some(VoiceLeading { max_leap: 4, ..Default::default() }));
+        assert!(config.validate().is_ok());
+
+        let config = GenerationConfig::builder()
+            .voice_leading(VoiceLeading { lowest_note: 70, highest_note: 60, ..Default::default() })
+            .build();
+        assert!(matches!(config.validate(), Err(Error::InvalidInput(_))));
+    }
+}
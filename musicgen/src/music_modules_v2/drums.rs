@@ -0,0 +1,269 @@
+//! Drum tracks.
+//!
+//! The drums play a one bar template of the genre on every bar of the song,
+//! on the General MIDI percussion channel. The hits in uppercase are always
+//! played, and the others are picked by the density and varied from bar to
+//! bar. A fill leads into each section of the pattern.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::DrumGenre, error::Error, meter::Meter, midi::TrackSettings, music::PlacedChord, rhythm::Rhythm, utils::MathMagician};
+
+/// The General MIDI percussion notes that the templates use.
+pub mod notes {
+    pub const KICK: u8 = 36;
+    pub const SNARE: u8 = 38;
+    pub const CLAP: u8 = 39;
+    pub const CLOSED_HI_HAT: u8 = 42;
+    pub const OPEN_HI_HAT: u8 = 46;
+    pub const CRASH: u8 = 49;
+    pub const HIGH_TOM: u8 = 50;
+    pub const MID_TOM: u8 = 47;
+    pub const LOW_TOM: u8 = 45;
+    pub const FLOOR_TOM: u8 = 43;
+}
+
+use notes::*;
+
+/// The drums of a fill, from high to low. A fill moves down through them.
+const FILL_DRUMS: [u8; 5] = [SNARE, HIGH_TOM, MID_TOM, LOW_TOM, FLOOR_TOM];
+
+/// The length of a fill in beats, which is played in 16th notes.
+const FILL_LENGTH: f64 = 1.0;
+
+/// The settings for a drum track.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Drums {
+    pub genre: DrumGenre,
+    /// The chance that each optional hit of the template is played, from 0
+    /// to 1.
+    pub density: f64,
+    /// The chance that an optional hit changes from one bar to the next,
+    /// from 0 to 1.
+    pub variation: f64,
+    /// Plays a fill at the end of each section, and at the end of the song.
+    pub fills: bool,
+    /// Also plays a fill every this many bars. `0` only plays fills at the
+    /// ends of sections.
+    pub fill_every: u32,
+    pub track: TrackSettings,
+}
+
+impl Default for Drums {
+    fn default() -> Self {
+        Drums {
+            genre: DrumGenre::Rock,
+            density: 0.5,
+            variation: 0.2,
+            fills: true,
+            fill_every: 8,
+            track: TrackSettings::new("Drums", 9, None),
+        }
+    }
+}
+
+/// A hit of a drum track. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrumNote {
+    pub start: f64,
+    pub length: f64,
+    pub pitch: u8,
+    pub velocity: u8,
+}
+
+impl DrumGenre {
+    /// The drums of the genre and their rhythms over a bar of 4/4, on a 16th
+    /// note grid.
+    pub fn template(&self) -> &'static [(u8, &'static str)] {
+        match self {
+            DrumGenre::Trap => &[
+                (KICK, "X......xX.x....x"),
+                (CLAP, "........X......."),
+                (CLOSED_HI_HAT, "XxXxXxXxXxXxXxXx"),
+                (OPEN_HI_HAT, "..............x."),
+            ],
+            DrumGenre::House => &[
+                (KICK, "X...X...X...X..."),
+                (CLAP, "....X.......X..."),
+                (CLOSED_HI_HAT, "x..xx..xx..xx..x"),
+                (OPEN_HI_HAT, "..X...X...X...X."),
+            ],
+            DrumGenre::LoFi => &[
+                (KICK, "X.....x...X..x.."),
+                (SNARE, "....X.......X..x"),
+                (CLOSED_HI_HAT, "X.x.X.x.X.x.X.x."),
+            ],
+            DrumGenre::Rock => &[
+                (KICK, "X.....x.X.x....."),
+                (SNARE, "....X.......X..x"),
+                (CLOSED_HI_HAT, "X.X.X.X.X.X.X.X."),
+            ],
+        }
+    }
+}
+
+/// Returns true with a chance of `probability`.
+fn chance(math_magician: &mut MathMagician, probability: f64) -> bool {
+    (math_magician.big_decision(0, 999) as f64) < probability * 1000.0
+}
+
+impl Drums {
+    /// Creates a drum track with the default settings.
+    pub fn new(genre: DrumGenre) -> Self {
+        Drums { genre, ..Default::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        self.track.validate()?;
+        if !(0.0..=1.0).contains(&self.density) {
+            return Err(Error::InvalidInput(format!("the density of the drums must be between 0 and 1, but it is {}", self.density)));
+        }
+        if !(0.0..=1.0).contains(&self.variation) {
+            return Err(Error::InvalidInput(format!("the variation of the drums must be between 0 and 1, but it is {}", self.variation)));
+        }
+        Ok(())
+    }
+
+    /// Writes the drums for every bar of the chords. `section_starts` are the
+    /// start times of the sections of the pattern, in beats.
+    pub fn notes(&self, chords: &[PlacedChord], section_starts: &[f64], meter: &Meter, math_magician: &mut MathMagician) -> Vec<DrumNote> {
+        let song_end = chords.iter().map(|chord| chord.start + chord.length).fold(0.0, f64::max);
+        let bar = meter.bar_length();
+        let rhythms: Vec<(u8, Rhythm)> = self.genre
+            .template()
+            .iter()
+            .map(|(drum, grid)| (*drum, grid.parse().expect("the drum templates are valid rhythms")))
+            .collect();
+        // which optional hits are played, which changes a little on every bar
+        let mut is_played: Vec<Vec<bool>> = rhythms
+            .iter()
+            .map(|(_, rhythm)| rhythm.hits().iter().map(|_| chance(math_magician, self.density)).collect())
+            .collect();
+
+        // the ends of the sections and of the song, where the fills go
+        let mut fill_ends: Vec<f64> = Vec::new();
+        if self.fills {
+            fill_ends.extend(section_starts.iter().copied().filter(|start| *start > 0.0 && *start < song_end));
+            fill_ends.push(song_end);
+            if self.fill_every > 0 {
+                let every = bar * self.fill_every as f64;
+                fill_ends.extend((1..).map(|i| i as f64 * every).take_while(|end| *end < song_end - 1e-9));
+            }
+            fill_ends.sort_by(f64::total_cmp);
+            fill_ends.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        }
+        let is_in_fill = |time: f64| fill_ends.iter().any(|end| time >= end - FILL_LENGTH - 1e-9 && time < end - 1e-9);
+
+        let mut notes = Vec::new();
+        let mut bar_start = 0.0;
+        while bar_start < song_end - 1e-9 {
+            let length = bar.min(song_end - bar_start);
+            for ((drum, rhythm), is_played) in rhythms.iter().zip(is_played.iter_mut()) {
+                for (is_played, hit) in is_played.iter_mut().zip(rhythm.hits_in(length)) {
+                    if chance(math_magician, self.variation) {
+                        *is_played = !*is_played;
+                    }
+                    let start = bar_start + hit.start;
+                    if (hit.accent || *is_played) && !is_in_fill(start) {
+                        notes.push(DrumNote { start, length: hit.length, pitch: *drum, velocity: if hit.accent { 100 } else { 70 } });
+                    }
+                }
+            }
+            bar_start += bar;
+        }
+
+        for end in fill_ends.iter() {
+            let mut drum = math_magician.big_decision(0, 1) as usize;
+            for i in 0..4 {
+                let start = end - FILL_LENGTH + i as f64 * FILL_LENGTH / 4.0;
+                if start >= 0.0 {
+                    notes.push(DrumNote { start, length: FILL_LENGTH / 4.0, pitch: FILL_DRUMS[drum], velocity: 80 + 8 * i });
+                }
+                drum = (drum + math_magician.big_decision(0, 1) as usize).min(FILL_DRUMS.len() - 1);
+            }
+            // a crash on the first beat after the fill
+            if *end < song_end - 1e-9 {
+                notes.push(DrumNote { start: *end, length: 1.0, pitch: CRASH, velocity: 110 });
+            }
+        }
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Eight bars of chords.
+    fn chords() -> Vec<PlacedChord> {
+        PlacedChord::one_after_another(&[(0, [0, 4, 7], 4.0); 8])
+    }
+
+    fn drums(drums: &Drums, section_starts: &[f64]) -> Vec<DrumNote> {
+        drums.notes(&chords(), section_starts, &Meter::default(), &mut MathMagician::share_hash([0; 32]))
+    }
+
+    fn starts(notes: &[DrumNote], pitch: u8) -> Vec<f64> {
+        notes.iter().filter(|note| note.pitch == pitch).map(|note| note.start).collect()
+    }
+
+    #[test]
+    fn templates() {
+        let notes = drums(&Drums { fills: false, ..Drums::new(DrumGenre::House) }, &[]);
+        // four on the floor, with claps on 2 and 4
+        assert_eq!(starts(&notes, KICK), (0..32).map(|beat| beat as f64).collect::<Vec<f64>>());
+        assert_eq!(starts(&notes, CLAP).len(), 16);
+        assert!(notes.iter().all(|note| note.start < 32.0));
+
+        for genre in DrumGenre::ALL {
+            for (_, grid) in genre.template() {
+                assert_eq!(grid.parse::<Rhythm>().unwrap().length(), 4.0);
+            }
+        }
+    }
+
+    #[test]
+    fn density_and_variation() {
+        let count = |density, variation| drums(&Drums { density, variation, fills: false, ..Drums::new(DrumGenre::Trap) }, &[]).len();
+        // only the uppercase hits
+        assert_eq!(count(0.0, 0.0), 8 * 11);
+        assert_eq!(count(1.0, 0.0), 8 * 23);
+        assert!(count(0.0, 0.5) > 8 * 11);
+
+        // without variation, every bar is the same
+        let notes = drums(&Drums { fills: false, variation: 0.0, ..Default::default() }, &[]);
+        let bar = |i: f64| -> Vec<(f64, u8)> {
+            notes.iter().filter(|note| (4.0 * i..4.0 * i + 4.0).contains(&note.start)).map(|note| (note.start - 4.0 * i, note.pitch)).collect()
+        };
+        assert_eq!(bar(0.0), bar(5.0));
+    }
+
+    #[test]
+    fn fills_at_section_ends() {
+        let notes = drums(&Drums { fill_every: 0, ..Default::default() }, &[0.0, 12.0]);
+        // a crash after the fill into the second section
+        assert_eq!(starts(&notes, CRASH), [12.0]);
+        let fill: Vec<&DrumNote> = notes.iter().filter(|note| (11.0..12.0).contains(&note.start)).collect();
+        assert_eq!(fill.len(), 4);
+        assert!(fill.iter().all(|note| FILL_DRUMS.contains(&note.pitch)));
+        // the fill goes down the drums
+        assert!(fill.windows(2).all(|pair| FILL_DRUMS.iter().position(|drum| *drum == pair[0].pitch) <= FILL_DRUMS.iter().position(|drum| *drum == pair[1].pitch)));
+        // and at the end of the song
+        assert_eq!(notes.iter().filter(|note| note.start >= 31.0).count(), 4);
+
+        let notes = drums(&Drums { fill_every: 4, ..Default::default() }, &[]);
+        assert_eq!(starts(&notes, CRASH), [16.0]);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Drums::default().validate().is_ok());
+        assert!(Drums { density: 1.5, ..Default::default() }.validate().is_err());
+        assert!(Drums { variation: -0.5, ..Default::default() }.validate().is_err());
+        let drums: Drums = serde_json::from_str(r#"{"genre": "lo-fi", "density": 0.8}"#).unwrap();
+        assert_eq!(drums, Drums { density: 0.8, ..Drums::new(DrumGenre::LoFi) });
+        assert_eq!(drums.track.channel, 9);
+    }
+}
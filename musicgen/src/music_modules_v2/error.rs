@@ -0,0 +1,182 @@
+//! The error type for everything that can go wrong while making music.
+
+use std::fmt::Display;
+
+use wasm_bindgen::{JsError, JsValue};
+
+use super::config::{ChordTypeGroup, Scale};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A key or note name could not be parsed.
+    InvalidKey(String),
+    /// A string option, such as a scale or a generation mode, did not match
+    /// any of the accepted values.
+    UnknownOption {
+        option: &'static str,
+        value: String,
+        expected: &'static [&'static str],
+    },
+    /// There were no chords left to pick from after building and pruning the
+    /// chord table.
+    EmptyChordTable {
+        chord_type_group: ChordTypeGroup,
+        scale: Scale,
+    },
+    /// A custom scale could not be parsed.
+    InvalidScale {
+        scale: String,
+        reason: &'static str,
+    },
+    /// A chord type in a chord library is not usable.
+    InvalidChordType {
+        name: String,
+        reason: &'static str,
+    },
+    /// A chord library could not be parsed as TOML.
+    TomlError(toml::de::Error),
+    /// A chord symbol could not be parsed. `position` is the index of the
+    /// offending character.
+    InvalidChordSymbol {
+        symbol: String,
+        position: usize,
+        reason: &'static str,
+    },
+    /// A Roman numeral could not be parsed or realized in a key.
+    InvalidRomanNumeral {
+        numeral: String,
+        reason: &'static str,
+    },
+    /// A time signature could not be parsed.
+    InvalidTimeSignature {
+        time_signature: String,
+        reason: &'static str,
+    },
+    /// A pattern could not be parsed. `position` is the index of the
+    /// offending character.
+    InvalidPattern {
+        pattern: String,
+        position: usize,
+        reason: &'static str,
+    },
+    /// A rhythm could not be parsed. `position` is the index of the offending
+    /// character.
+    InvalidRhythm {
+        rhythm: String,
+        position: usize,
+        reason: &'static str,
+    },
+    /// The MIDI file could not be written.
+    MidiWriteError(String),
+    /// A MIDI file could not be read.
+    MidlyError(midly::Error),
+    SerdeError(serde_json::Error),
+    /// The input was not in the expected format.
+    InvalidInput(String),
+}
+
+impl From<midly::Error> for Error {
+    fn from(value: midly::Error) -> Self {
+        Self::MidlyError(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::SerdeError(value)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(value: toml::de::Error) -> Self {
+        Self::TomlError(value)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidKey(key) => write!(
+                f,
+                "invalid key `{}`; expected a note such as `C`, `F#` or `Bb`, optionally followed by a mode such as `maj`, `minor` or `m`",
+                key
+            ),
+            Self::UnknownOption { option, value, expected } => {
+                let expected = expected
+                    .iter()
+                    .map(|v| format!("`{}`", v))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "unknown {} `{}`; expected one of: {}", option, value, expected)
+            },
+            Self::EmptyChordTable { chord_type_group, scale } => match scale {
+                Scale::Disabled => write!(
+                    f,
+                    "the `{}` chord type group does not contain any chords; select at least one chord type",
+                    chord_type_group
+                ),
+                _ => write!(
+                    f,
+                    "no chords from the `{}` chord type group fit in the `{}` scale; select more chord types or a different scale",
+                    chord_type_group,
+                    scale
+                ),
+            },
+            Self::InvalidScale { scale, reason } => write!(f, "invalid scale `{}`: {}", scale, reason),
+            Self::InvalidChordType { name, reason } => write!(f, "invalid chord type `{}`: {}", name, reason),
+            Self::TomlError(v) => write!(f, "failed to read the chord library: {}", v),
+            Self::InvalidChordSymbol { symbol, position, reason } => write!(
+                f,
+                "invalid chord symbol `{}` at position {}: {}",
+                symbol,
+                position,
+                reason
+            ),
+            Self::InvalidRomanNumeral { numeral, reason } => write!(f, "invalid Roman numeral `{}`: {}", numeral, reason),
+            Self::InvalidTimeSignature { time_signature, reason } => write!(f, "invalid time signature `{}`: {}", time_signature, reason),
+            Self::InvalidPattern { pattern, position, reason } => write!(
+                f,
+                "invalid pattern `{}` at position {}: {}",
+                pattern,
+                position,
+                reason
+            ),
+            Self::InvalidRhythm { rhythm, position, reason } => write!(
+                f,
+                "invalid rhythm `{}` at position {}: {}",
+                rhythm,
+                position,
+                reason
+            ),
+            Self::MidiWriteError(v) => write!(f, "failed to write the MIDI file: {}", v),
+            Self::MidlyError(v) => write!(f, "failed to read the MIDI file: {}", v),
+            Self::SerdeError(v) => v.fmt(f),
+            Self::InvalidInput(v) => write!(f, "invalid input: {}", v),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for JsValue {
+    fn from(value: Error) -> Self {
+        JsError::from(value).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_contain_the_details() {
+        let err = Error::InvalidPattern { pattern: "1-a".into(), position: 2, reason: "expected a digit or `-`" };
+        assert_eq!(err.to_string(), "invalid pattern `1-a` at position 2: expected a digit or `-`");
+
+        let err = Error::UnknownOption { option: "scale", value: "lydian".into(), expected: &["natural", "melodic"] };
+        assert_eq!(err.to_string(), "unknown scale `lydian`; expected one of: `natural`, `melodic`");
+
+        let err = Error::EmptyChordTable { chord_type_group: ChordTypeGroup::Custom, scale: Scale::Disabled };
+        assert!(err.to_string().contains("select at least one chord type"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Humanization.
+//!
+//! The placement algorithms put every note exactly on the grid, and most of
+//! them play every note equally loud. A `Humanizer` moves the notes of a
+//! finished track a little, swings the off-beats, accents the beats of each
+//! bar, and plays the top note of each chord louder. It uses the random
+//! decisions of the song, so a humanized song is still reproducible.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::SwingUnit, error::Error, meter::Meter, utils::{beats, MathMagician}};
+
+/// The settings for `MidiFile::humanize`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Humanizer {
+    /// The most that a note can move, in ticks, where a quarter note is 96
+    /// ticks.
+    pub timing_jitter: u32,
+    /// How much louder or softer the notes on each beat of a bar are played,
+    /// repeated for bars with more beats. Notes between the beats are not
+    /// accented.
+    pub accents: Vec<i8>,
+    /// The most that a velocity can change at random.
+    pub velocity_variation: u8,
+    /// Where the second note of each pair of `swing_unit` notes is played, as
+    /// a percentage of the pair. `50` is straight, and `66` is a triplet
+    /// swing.
+    pub swing: u8,
+    pub swing_unit: SwingUnit,
+    /// How much louder the highest note of a chord is played. Drum tracks on
+    /// channel 10 are left as they are.
+    pub top_note_boost: u8,
+}
+
+impl Default for Humanizer {
+    fn default() -> Self {
+        Humanizer {
+            timing_jitter: 3,
+            accents: vec![10, -6, 4, -6],
+            velocity_variation: 6,
+            swing: 50,
+            swing_unit: SwingUnit::Eighth,
+            top_note_boost: 8,
+        }
+    }
+}
+
+impl SwingUnit {
+    /// The length of the notes that are swung, in beats.
+    pub fn length(&self) -> f64 {
+        match self {
+            SwingUnit::Eighth => 0.5,
+            SwingUnit::Sixteenth => 0.25
+        }
+    }
+}
+
+impl Humanizer {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(50..=75).contains(&self.swing) {
+            return Err(Error::InvalidInput(format!("the swing must be between 50 and 75 percent, but it is {}", self.swing)));
+        }
+        // half of an eighth note
+        if self.timing_jitter > 24 {
+            return Err(Error::InvalidInput(format!("the timing jitter must be at most 24 ticks, but it is {}", self.timing_jitter)));
+        }
+        if self.velocity_variation > 64 || self.top_note_boost > 64 {
+            return Err(Error::InvalidInput(format!(
+                "the velocity variation and top note boost must be at most 64, but they are {} and {}",
+                self.velocity_variation,
+                self.top_note_boost
+            )));
+        }
+        Ok(())
+    }
+
+    /// Moves a time that falls on the second note of a swung pair, in ticks.
+    pub fn swing_time(&self, time: u32) -> u32 {
+        let unit = beats(self.swing_unit.length());
+        let pair = 2 * unit;
+        match time % pair == unit {
+            true => time - unit + pair * self.swing as u32 / 100,
+            false => time
+        }
+    }
+
+    /// The accent of a note that starts at `time` ticks.
+    pub fn accent(&self, time: u32, meter: &Meter) -> i16 {
+        let beat = beats(meter.beat_length());
+        let position = time % beats(meter.bar_length());
+        match (position % beat, self.accents.is_empty()) {
+            (0, false) => self.accents[(position / beat) as usize % self.accents.len()] as i16,
+            _ => 0
+        }
+    }
+
+    /// Returns the new velocity of a note that starts at `time` ticks.
+    pub fn velocity(&self, velocity: u8, time: u32, is_top_note: bool, meter: &Meter, math_magician: &mut MathMagician) -> u8 {
+        let variation = self.velocity_variation as i16;
+        let random = math_magician.big_decision(0, 2 * variation as u16) as i16 - variation;
+        let boost = if is_top_note { self.top_note_boost as i16 } else { 0 };
+        (velocity as i16 + self.accent(time, meter) + random + boost).clamp(1, 127) as u8
+    }
+
+    /// Returns a random amount of ticks to move a note by.
+    pub fn jitter(&self, math_magician: &mut MathMagician) -> i64 {
+        math_magician.big_decision(0, 2 * self.timing_jitter as u16) as i64 - self.timing_jitter as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swing() {
+        let humanizer = Humanizer { swing: 66, ..Default::default() };
+        assert_eq!(humanizer.swing_time(0), 0);
+        assert_eq!(humanizer.swing_time(48), 63);
+        assert_eq!(humanizer.swing_time(96 + 48), 96 + 63);
+        // only the second note of the pair moves
+        assert_eq!(humanizer.swing_time(24), 24);
+        let humanizer = Humanizer { swing: 60, swing_unit: SwingUnit::Sixteenth, ..Default::default() };
+        assert_eq!(humanizer.swing_time(24), 28);
+        assert_eq!(Humanizer::default().swing_time(48), 48);
+    }
+
+    #[test]
+    fn accents() {
+        let humanizer = Humanizer::default();
+        let meter = Meter::default();
+        assert_eq!(humanizer.accent(0, &meter), 10);
+        assert_eq!(humanizer.accent(96, &meter), -6);
+        assert_eq!(humanizer.accent(4 * 96 + 2 * 96, &meter), 4);
+        assert_eq!(humanizer.accent(48, &meter), 0);
+        // the accents repeat for bars with more beats
+        let meter = Meter::new("6/8".parse().unwrap(), 1.0).unwrap();
+        assert_eq!(humanizer.accent(4 * 48, &meter), 10);
+        assert_eq!(humanizer.accent(6 * 48, &meter), 10);
+
+        let mut math_magician = MathMagician::share_hash([0; 32]);
+        let quiet = Humanizer { velocity_variation: 0, accents: vec![], ..Default::default() };
+        assert_eq!(quiet.velocity(80, 0, true, &meter, &mut math_magician), 88);
+        assert_eq!(quiet.velocity(125, 0, true, &meter, &mut math_magician), 127);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Humanizer::default().validate().is_ok());
+        assert!(Humanizer { swing: 80, ..Default::default() }.validate().is_err());
+        assert!(Humanizer { timing_jitter: 100, ..Default::default() }.validate().is_err());
+        let humanizer: Humanizer = serde_json::from_str(r#"{"swing": 66, "swing_unit": "1/16"}"#).unwrap();
+        assert_eq!(humanizer.swing_unit, SwingUnit::Sixteenth);
+    }
+}
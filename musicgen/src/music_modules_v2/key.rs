@@ -0,0 +1,418 @@
+//! Keys, pitch classes and note spelling.
+//!
+//! Every chord table is built in C minor and then transposed, so a `Key` is
+//! mostly used for two things: finding how far to transpose the chords (see
+//! `Key::offset`), and spelling note names correctly for the key signature.
+
+use std::{fmt::Display, str::FromStr};
+
+use super::error::Error;
+
+/// The letters of the note names, starting from C.
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+/// The pitch class of each letter without any accidentals.
+const NATURAL_PITCH_CLASSES: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// The position of each letter on the circle of fifths, relative to C.
+const LETTER_FIFTHS: [i8; 7] = [0, 2, 4, -1, 1, 3, 5];
+
+/// The number of letter steps between the root of a chord and each interval
+/// above it, for spelling chord tones. A tritone is spelled as a flat 5th and
+/// 8 semitones as a sharp 5th.
+const INTERVAL_LETTER_STEPS: [u8; 12] = [0, 1, 1, 2, 2, 3, 4, 4, 4, 5, 6, 6];
+
+/// A note without an octave, where C is 0 and B is 11.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PitchClass(u8);
+
+impl PitchClass {
+    /// Creates a pitch class from any number of semitones above C.
+    pub fn new(semitones: i16) -> Self {
+        Self(semitones.rem_euclid(12) as u8)
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+impl FromStr for PitchClass {
+    type Err = Error;
+
+    /// Parses a note name such as `C`, `Eb`, `f#` or `Bbb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match NoteName::parse_prefix(s.trim(), 2) {
+            Some((name, "")) => Ok(name.pitch_class()),
+            _ => Err(Error::InvalidKey(s.to_string()))
+        }
+    }
+}
+
+/// A spelled note name, such as `Eb` or `D#`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NoteName {
+    /// The index of the letter, where 0 is C and 6 is B.
+    letter: u8,
+    /// The number of sharps, or flats when negative.
+    accidental: i8,
+}
+
+impl NoteName {
+    /// Parses a note name from the start of `s`, returning the rest of `s`.
+    ///
+    /// The letter may be uppercase or lowercase, and may be followed by up to
+    /// `max_accidentals` accidentals: `#`, `♯`, `b`, `♭`, or `x` for a double 
+    /// sharp.
+    pub(crate) fn parse_prefix(s: &str, max_accidentals: usize) -> Option<(NoteName, &str)> {
+        let mut chars = s.chars();
+        let letter = chars.next()?.to_ascii_uppercase();
+        let letter = LETTERS.iter().position(|l| *l == letter)? as u8;
+        let mut accidental = 0i8;
+        let mut rest = chars.as_str();
+        for _ in 0..max_accidentals {
+            let mut chars = rest.chars();
+            match chars.next() {
+                Some('#') | Some('♯') if accidental >= 0 => accidental += 1,
+                Some('b') | Some('♭') if accidental <= 0 => accidental -= 1,
+                Some('x') | Some('𝄪') if accidental == 0 => accidental += 2,
+                Some('𝄫') if accidental == 0 => accidental -= 2,
+                _ => break
+            }
+            rest = chars.as_str();
+        }
+        Some((NoteName { letter, accidental }, rest))
+    }
+
+    /// Spells `pitch_class` with the given letter, if it can be done with at
+    /// most a double sharp or double flat.
+    fn with_letter(letter: u8, pitch_class: PitchClass) -> Option<NoteName> {
+        let letter = letter % 7;
+        let difference = pitch_class.value() as i8 - NATURAL_PITCH_CLASSES[letter as usize] as i8;
+        // normalize to -6..=5
+        let accidental = (difference + 6).rem_euclid(12) - 6;
+        match accidental.abs() <= 2 {
+            true => Some(NoteName { letter, accidental }),
+            false => None
+        }
+    }
+
+    pub fn pitch_class(&self) -> PitchClass {
+        PitchClass::new(NATURAL_PITCH_CLASSES[self.letter as usize] as i16 + self.accidental as i16)
+    }
+
+    /// The letter of the note, from `C` to `B`.
+    pub fn letter(&self) -> char {
+        LETTERS[self.letter as usize]
+    }
+
+    /// The number of sharps, or flats when negative.
+    pub fn accidental(&self) -> i8 {
+        self.accidental
+    }
+
+    /// Spells the note that is `semitones` above this one as a chord tone,
+    /// such as a major 3rd or a flat 5th. Returns `None` when that would need
+    /// more than one accidental.
+    pub fn chord_tone(&self, semitones: u8) -> Option<NoteName> {
+        let pitch_class = PitchClass::new(self.pitch_class().value() as i16 + semitones as i16);
+        let letter = self.letter + INTERVAL_LETTER_STEPS[semitones as usize % 12];
+        NoteName::with_letter(letter, pitch_class).filter(|name| name.accidental.abs() <= 1)
+    }
+
+    /// The position of this note on the circle of fifths, relative to C.
+    fn fifths(&self) -> i8 {
+        LETTER_FIFTHS[self.letter as usize] + 7 * self.accidental
+    }
+}
+
+impl Display for NoteName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let accidental = match self.accidental {
+            -2 => "bb",
+            -1 => "b",
+            1 => "#",
+            2 => "##",
+            _ => ""
+        };
+        write!(f, "{}{}", LETTERS[self.letter as usize], accidental)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    #[default]
+    Minor,
+}
+
+impl Mode {
+    /// The intervals of the scale degrees above the tonic.
+    pub fn intervals(&self) -> [u8; 7] {
+        match self {
+            Mode::Major => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => [0, 2, 3, 5, 7, 8, 10],
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    /// Parses `maj`, `major`, `ionian`, `min`, `minor`, `m` or `aeolian`,
+    /// ignoring case apart from `m`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "m" {
+            return Ok(Mode::Minor);
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "maj" | "major" | "ionian" => Ok(Mode::Major),
+            "min" | "minor" | "aeolian" => Ok(Mode::Minor),
+            _ => Err(Error::InvalidKey(s.to_string()))
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        })
+    }
+}
+
+/// A major or minor key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub tonic: PitchClass,
+    pub mode: Mode,
+    /// How the tonic is spelled, which decides between sharps and flats.
+    tonic_name: NoteName,
+}
+
+impl Default for Key {
+    /// C minor, which every chord table is built in.
+    fn default() -> Self {
+        Key::new(PitchClass::new(0), Mode::Minor)
+    }
+}
+
+impl Key {
+    /// Creates a key, spelling the tonic with the fewest accidentals in the
+    /// key signature. F# major and D# minor are preferred over Gb major and
+    /// Eb minor.
+    pub fn new(tonic: PitchClass, mode: Mode) -> Self {
+        let tonic_name = (0..7)
+            .filter_map(|letter| NoteName::with_letter(letter, tonic))
+            .min_by_key(|name| {
+                let fifths = Key::fifths_of(name, mode);
+                (fifths.abs(), -fifths)
+            })
+            .expect("Every pitch class can be spelled");
+        Key { tonic, mode, tonic_name }
+    }
+
+    /// Creates a key with a spelled tonic. Keys with more than 7 sharps or
+    /// flats, such as D# major, are respelled with their enharmonic
+    /// equivalent.
+    pub fn from_note_name(tonic_name: NoteName, mode: Mode) -> Self {
+        match Key::fifths_of(&tonic_name, mode).abs() > 7 {
+            true => Key::new(tonic_name.pitch_class(), mode),
+            false => Key { tonic: tonic_name.pitch_class(), mode, tonic_name }
+        }
+    }
+
+    /// Creates the key with `fifths` sharps in its key signature, or flats 
+    /// when negative. Returns `None` for more than 7 sharps or flats.
+    pub fn from_fifths(fifths: i8, mode: Mode) -> Option<Self> {
+        if fifths.abs() > 7 {
+            return None;
+        }
+        (0..7)
+            .flat_map(|letter| (-1..=1).map(move |accidental| NoteName { letter, accidental }))
+            .find(|name| Key::fifths_of(name, mode) == fifths)
+            .map(|tonic_name| Key { tonic: tonic_name.pitch_class(), mode, tonic_name })
+    }
+
+    fn fifths_of(tonic_name: &NoteName, mode: Mode) -> i8 {
+        match mode {
+            Mode::Major => tonic_name.fifths(),
+            Mode::Minor => tonic_name.fifths() - 3,
+        }
+    }
+
+    pub fn tonic_name(&self) -> NoteName {
+        self.tonic_name
+    }
+
+    /// The number of sharps in the key signature, or flats when negative.
+    pub fn fifths(&self) -> i8 {
+        Key::fifths_of(&self.tonic_name, self.mode)
+    }
+
+    /// The number of semitones to transpose a chord table built in C minor so
+    /// that it is in this key. Major keys use their relative minor.
+    pub fn offset(&self) -> i16 {
+        match self.mode {
+            Mode::Minor => self.tonic.value() as i16,
+            Mode::Major => (self.tonic.value() as i16 + 9) % 12,
+        }
+    }
+
+    /// Spells the given scale degree, where 1 is the tonic, raised or lowered
+    /// by `accidental` semitones. Returns `None` for degrees outside of 1 to 7
+    /// and for notes that would need more than a double sharp or flat.
+    pub fn degree(&self, degree: u8, accidental: i8) -> Option<NoteName> {
+        if !(1..=7).contains(&degree) {
+            return None;
+        }
+        let interval = self.mode.intervals()[degree as usize - 1] as i16 + accidental as i16;
+        let pitch_class = PitchClass::new(self.tonic.value() as i16 + interval);
+        NoteName::with_letter(self.tonic_name.letter + degree - 1, pitch_class)
+    }
+
+    /// Spells a pitch class in this key.
+    ///
+    /// Notes in the scale use the key signature, as do the raised 6th and 7th
+    /// degrees of minor keys. Any other note is spelled with flats in flat
+    /// keys and sharps otherwise.
+    pub fn spell(&self, pitch_class: PitchClass) -> NoteName {
+        let interval = PitchClass::new(pitch_class.value() as i16 - self.tonic.value() as i16).value();
+        let intervals = self.mode.intervals();
+        let degree = match intervals.iter().position(|i| *i == interval) {
+            Some(degree) => Some(degree),
+            None if self.mode == Mode::Minor && interval == 9 => Some(5),
+            None if self.mode == Mode::Minor && interval == 11 => Some(6),
+            None => None
+        };
+        if let Some(name) = degree.and_then(|d| NoteName::with_letter(self.tonic_name.letter + d as u8, pitch_class)) {
+            return name;
+        }
+        // chromatic notes are one semitone away from a natural note
+        let natural = NATURAL_PITCH_CLASSES.iter().position(|n| *n == pitch_class.value());
+        match (natural, self.fifths() < 0) {
+            (Some(letter), _) => NoteName { letter: letter as u8, accidental: 0 },
+            (None, true) => NoteName::with_letter(
+                NATURAL_PITCH_CLASSES.iter().position(|n| *n == pitch_class.value() + 1).unwrap() as u8,
+                pitch_class
+            ).unwrap(),
+            (None, false) => NoteName::with_letter(
+                NATURAL_PITCH_CLASSES.iter().position(|n| *n == pitch_class.value() - 1).unwrap() as u8,
+                pitch_class
+            ).unwrap(),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    /// Parses a key such as `Cmin`, `D#maj`, `Bbmaj`, `Eb minor`, `c#m` or
+    /// `F## major`. A tonic without a mode is minor, like the rest of this
+    /// crate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidKey(s.to_string());
+        let (tonic_name, rest) = NoteName::parse_prefix(s.trim(), 2).ok_or_else(invalid)?;
+        let rest = rest.trim_start_matches([' ', '-', '_']);
+        let mode = match rest {
+            "" => Mode::Minor,
+            _ => rest.parse().map_err(|_| invalid())?
+        };
+        Ok(Key::from_note_name(tonic_name, mode))
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.tonic_name, self.mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parsing_keys() {
+        let data = [
+            ("Cmin", 0, Mode::Minor),
+            ("D#maj", 3, Mode::Major),
+            ("Bbmaj", 10, Mode::Major),
+            ("Eb minor", 3, Mode::Minor),
+            ("c#m", 1, Mode::Minor),
+            ("gb Major", 6, Mode::Major),
+            ("F##min", 7, Mode::Minor),
+            ("Cbb-maj", 10, Mode::Major),
+            ("Ex_minor", 6, Mode::Minor),
+            ("B♭ major", 10, Mode::Major),
+            ("bbm", 10, Mode::Minor),
+            ("a", 9, Mode::Minor),
+        ];
+        for (input, tonic, mode) in data {
+            let k = key(input);
+            assert_eq!((k.tonic.value(), k.mode), (tonic, mode), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        for input in ["", "H", "Cmajor7", "#maj", "C###", "Cb b", "X#min", "Cmm", "CM"] {
+            assert!(input.parse::<Key>().is_err(), "`{}` should not parse", input);
+        }
+    }
+
+    #[test]
+    fn offsets_use_the_relative_minor() {
+        assert_eq!(key("Cmin").offset(), 0);
+        assert_eq!(key("Cmaj").offset(), 9);
+        assert_eq!(key("Ebmaj").offset(), 0);
+        assert_eq!(key("Amin").offset(), 9);
+    }
+
+    #[test]
+    fn key_signatures() {
+        assert_eq!(key("Cmin").fifths(), -3);
+        assert_eq!(key("Amin").fifths(), 0);
+        assert_eq!(key("D#min").fifths(), 6);
+        assert_eq!(key("Ebmin").fifths(), -6);
+        // respelled as Eb major
+        assert_eq!(key("D#maj").fifths(), -3);
+        assert_eq!(key("D#maj").tonic_name().to_string(), "Eb");
+        assert_eq!(Key::new(PitchClass::new(6), Mode::Major).tonic_name().to_string(), "F#");
+        assert_eq!(Key::new(PitchClass::new(10), Mode::Minor).tonic_name().to_string(), "Bb");
+
+        assert_eq!(Key::from_fifths(-6, Mode::Major), Some(key("Gbmaj")));
+        assert_eq!(Key::from_fifths(3, Mode::Minor), Some(key("F#min")));
+        assert_eq!(Key::from_fifths(0, Mode::Minor), Some(key("Amin")));
+        assert_eq!(Key::from_fifths(8, Mode::Major), None);
+    }
+
+    #[test]
+    fn spelling() {
+        let spell = |k: &str, pc: i16| key(k).spell(PitchClass::new(pc)).to_string();
+        assert_eq!(spell("Cmin", 3), "Eb");
+        assert_eq!(spell("Cmin", 11), "B");
+        assert_eq!(spell("Cmin", 6), "Gb");
+        assert_eq!(spell("Emin", 3), "D#");
+        assert_eq!(spell("Dmin", 1), "C#");
+        assert_eq!(spell("Ebmin", 11), "Cb");
+        assert_eq!(spell("F#maj", 5), "E#");
+        assert_eq!(spell("Amin", 8), "G#");
+    }
+
+    #[test]
+    fn chord_tones() {
+        let e = "E".parse::<PitchClass>().unwrap();
+        let e = key("Cmin").spell(e);
+        assert_eq!(e.chord_tone(4).unwrap().to_string(), "G#");
+        let eb = key("Cmin").spell(PitchClass::new(3));
+        assert_eq!(eb.chord_tone(3).unwrap().to_string(), "Gb");
+        // a flat 5th above Eb would be Bbb
+        assert!(eb.chord_tone(6).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Finding the key and scale of some notes.
+//!
+//! The notes are counted by pitch class, and the counts are correlated with a
+//! profile of every scale on every tonic, like the Krumhansl-Schmuckler
+//! algorithm. Major and natural minor keys use the Krumhansl-Kessler
+//! profiles, and the other scales of the crate use a profile that is built
+//! from their notes.
+
+use std::fmt::Display;
+
+use super::{analysis::MidiClip, config::Scale, key::{Key, Mode, PitchClass}, pruning::get_good_notes_set};
+
+/// How strongly each pitch class above the tonic belongs to a major key, from
+/// Krumhansl and Kessler (1982).
+const MAJOR_PROFILE: [f64; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+
+/// How strongly each pitch class above the tonic belongs to a minor key, from
+/// Krumhansl and Kessler (1982).
+const MINOR_PROFILE: [f64; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// A key and scale that some notes could be in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyCandidate {
+    /// The key to generate music in. Major keys use `Scale::Natural`, which is
+    /// rooted on their relative minor, and every other scale is rooted on the
+    /// tonic of a minor key.
+    pub key: Key,
+    pub scale: Scale,
+    /// The correlation between the notes and the profile of the scale, from
+    /// -1 to 1.
+    pub correlation: f64,
+}
+
+impl KeyCandidate {
+    /// The tonic of the scale.
+    pub fn tonic(&self) -> PitchClass {
+        self.key.tonic
+    }
+}
+
+/// Names the key, such as `Eb major`, `C minor` or `D dorian`.
+impl Display for KeyCandidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.scale {
+            Scale::Natural => write!(f, "{}", self.key),
+            scale => write!(f, "{} {}", self.key.tonic_name(), scale)
+        }
+    }
+}
+
+/// The profile of a scale that does not have a Krumhansl-Kessler profile,
+/// which weighs the tonic, fifth and third of the scale like the major and
+/// minor profiles do.
+fn scale_profile(scale: Scale) -> Option<[f64; 12]> {
+    let (good_notes_set, _) = get_good_notes_set(scale.pitch_classes()?);
+    let mut profile = [0.0; 12];
+    for (interval, weight) in profile.iter_mut().enumerate() {
+        let interval = interval as i16;
+        *weight = match (interval, good_notes_set.contains(&interval)) {
+            (0, _) => 6.35,
+            (7, true) => 5.0,
+            (3 | 4, true) => 4.4,
+            (_, true) => 3.6,
+            (_, false) => 2.4
+        };
+    }
+    Some(profile)
+}
+
+/// The Pearson correlation between two lists, or `None` when either of them
+/// is constant.
+fn correlation(a: &[f64; 12], b: &[f64; 12]) -> Option<f64> {
+    let mean = |values: &[f64; 12]| values.iter().sum::<f64>() / 12.0;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let covariance: f64 = a.iter().zip(b).map(|(a, b)| (a - mean_a) * (b - mean_b)).sum();
+    let variance = |values: &[f64; 12], mean: f64| values.iter().map(|value| (value - mean).powi(2)).sum::<f64>();
+    let denominator = (variance(a, mean_a) * variance(b, mean_b)).sqrt();
+    (denominator > 1e-12).then(|| covariance / denominator)
+}
+
+/// Ranks every key and scale by how well it fits `notes`, which are pairs of
+/// MIDI notes and their weights, such as how long they sound. The best key
+/// comes first. Returns an empty list when the notes do not favor any pitch
+/// class, such as when there are none.
+pub fn detect_key(notes: &[(u8, f64)]) -> Vec<KeyCandidate> {
+    let mut weights = [0.0; 12];
+    for (pitch, weight) in notes {
+        weights[(pitch % 12) as usize] += weight;
+    }
+
+    // Ionian is the same as a major key, and the other scales without a
+    // fixed set of notes cannot be detected
+    let profiles = [(Scale::Natural, Mode::Major, MAJOR_PROFILE), (Scale::Natural, Mode::Minor, MINOR_PROFILE)]
+        .into_iter()
+        .chain(Scale::ALL
+            .iter()
+            .filter(|scale| !matches!(scale, Scale::Natural | Scale::Ionian | Scale::AllNotes))
+            .filter_map(|scale| Some((*scale, Mode::Minor, scale_profile(*scale)?)))
+        );
+    let mut candidates: Vec<KeyCandidate> = Vec::new();
+    for (scale, mode, profile) in profiles {
+        for tonic in 0..12 {
+            let rotated: [f64; 12] = std::array::from_fn(|pitch_class| profile[(pitch_class + 12 - tonic) % 12]);
+            if let Some(correlation) = correlation(&weights, &rotated) {
+                candidates.push(KeyCandidate { key: Key::new(PitchClass::new(tonic as i16), mode), scale, correlation });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.correlation.total_cmp(&a.correlation));
+    candidates
+}
+
+impl MidiClip {
+    /// Ranks every key and scale by how well it fits the notes of the clip,
+    /// weighing each note by its length. See `detect_key`.
+    pub fn detect_key(&self) -> Vec<KeyCandidate> {
+        let notes: Vec<(u8, f64)> = self.notes.iter().map(|note| (note.pitch, note.length)).collect();
+        detect_key(&notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_modules_v2::config::GenerationConfig;
+
+    fn detect(notes: &[u8]) -> Vec<KeyCandidate> {
+        let notes: Vec<(u8, f64)> = notes.iter().map(|note| (*note, 1.0)).collect();
+        detect_key(&notes)
+    }
+
+    #[test]
+    fn major_and_minor_keys() {
+        // a scale and an arpeggio of Eb major
+        let candidates = detect(&[63, 65, 67, 68, 70, 72, 74, 75, 63, 67, 70, 75, 63]);
+        assert_eq!(candidates[0].to_string(), "Eb major");
+        assert_eq!(candidates[0].tonic(), PitchClass::new(3));
+
+        // the harmonic minor scale of A, with a leading tone
+        let candidates = detect(&[57, 59, 60, 62, 64, 65, 68, 69, 57, 60, 64, 69, 64, 68, 69]);
+        assert!(candidates[..3].iter().any(|candidate| candidate.to_string() == "A harmonic"), "{:?}", &candidates[..3]);
+        assert!(candidates[..3].iter().all(|candidate| candidate.tonic() == PitchClass::new(9)));
+
+        assert!(detect(&[]).is_empty());
+        assert!(detect(&(60..72).collect::<Vec<u8>>()).is_empty());
+    }
+
+    #[test]
+    fn exotic_scales() {
+        // the blues scale on G
+        let candidates = detect(&[55, 58, 60, 61, 62, 65, 67, 55, 62, 67, 58, 55]);
+        assert!(candidates[..3].iter().any(|candidate| candidate.scale == Scale::Blues), "{:?}", &candidates[..3]);
+        assert_eq!(candidates[0].tonic(), PitchClass::new(7));
+    }
+
+    #[test]
+    fn pruning_to_the_detected_key() {
+        // D major
+        let candidate = detect(&[62, 64, 66, 67, 69, 71, 73, 74, 62, 66, 69, 62])[0];
+        assert_eq!(candidate.to_string(), "D major");
+        let config = GenerationConfig::builder().detected_key(&candidate).build();
+        assert_eq!(config.key, "D major");
+        // the natural minor scale of B, which has the notes of D major
+        let notes = config.scale_notes(&candidate.key).unwrap().unwrap();
+        let offset = candidate.key.offset();
+        let mut pitch_classes: Vec<i16> = notes.iter().map(|note| (note as i16 + offset) % 12).collect();
+        pitch_classes.sort_unstable();
+        assert_eq!(pitch_classes, [1, 2, 4, 6, 7, 9, 11]);
+    }
+}
@@ -0,0 +1,209 @@
+//! LilyPond export.
+//!
+//! Writes a `Score` as LilyPond source, with the chords as `\chordmode`
+//! symbols above a staff for each part. Pitches are absolute, so every note
+//! can be read without the notes before it.
+
+use super::{key::{Mode, NoteName, PitchClass}, music::PlacedChord, score::{note_values, quantize, Clef, Score, ScoreEvent}};
+
+/// The LilyPond name of a note, such as `c`, `fis` or `bes`.
+fn note_name(name: NoteName) -> String {
+    let accidental = match name.accidental() {
+        accidental if accidental < 0 => "es".repeat(-accidental as usize),
+        accidental => "is".repeat(accidental as usize)
+    };
+    format!("{}{}", name.letter().to_ascii_lowercase(), accidental)
+}
+
+/// The LilyPond duration of a note value from `note_values`, such as `4` or
+/// `2.`.
+fn note_value(kind: &str, dotted: bool) -> String {
+    let value = match kind {
+        "whole" => "1",
+        "half" => "2",
+        "quarter" => "4",
+        "eighth" => "8",
+        _ => "16"
+    };
+    format!("{}{}", value, if dotted { "." } else { "" })
+}
+
+/// The LilyPond duration of a length in divisions, which is a single note
+/// value when there is one, or a scaled sixteenth note otherwise.
+fn duration(divisions: u32) -> String {
+    match note_values(divisions).as_slice() {
+        [(_, kind, dotted)] => note_value(kind, *dotted),
+        _ => format!("16*{}", divisions)
+    }
+}
+
+/// Escapes the characters of `text` that are special in a LilyPond string.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The `\chordmode` modifiers of a chord, as the steps of its notes above
+/// the root, such as `1.3-.5.7`.
+fn chord_steps(chord: &PlacedChord) -> String {
+    let mut intervals: Vec<i16> = chord.pitch_classes.iter().map(|pitch_class| (pitch_class - chord.root).rem_euclid(12)).collect();
+    intervals.sort_unstable();
+    intervals.dedup();
+    let has_fifth = intervals.contains(&7);
+    let steps: Vec<&str> = intervals
+        .iter()
+        .map(|interval| match interval {
+            0 => "1",
+            1 => "2-",
+            2 => "2",
+            3 => "3-",
+            4 => "3",
+            5 => "4",
+            6 if has_fifth => "4+",
+            6 => "5-",
+            7 => "5",
+            8 if has_fifth => "6-",
+            8 => "5+",
+            9 => "6",
+            10 => "7",
+            _ => "7+"
+        })
+        .collect();
+    steps.join(".")
+}
+
+impl Score {
+    /// Writes the score as LilyPond source.
+    pub fn to_lilypond(&self) -> String {
+        let mut out = String::from("\\version \"2.24.0\"\n\n");
+        if let Some(title) = self.title.as_ref() {
+            out.push_str(&format!("\\header {{\n  title = \"{}\"\n}}\n\n", escape(title)));
+        }
+        out.push_str("<<\n");
+        self.write_chord_names(&mut out);
+        for index in 0..self.parts.len() {
+            self.write_staff(&mut out, index);
+        }
+        out.push_str(">>\n");
+        out
+    }
+
+    /// Writes the chord symbols, with spacer rests between the chords.
+    fn write_chord_names(&self, out: &mut String) {
+        if self.chords.is_empty() {
+            return;
+        }
+        out.push_str("  \\new ChordNames \\chordmode {\n");
+        let mut time = 0;
+        for (i, chord) in self.chords.iter().enumerate() {
+            let start = quantize(chord.start).max(time);
+            let mut end = quantize(chord.start + chord.length);
+            if let Some(next) = self.chords.get(i + 1) {
+                end = end.min(quantize(next.start));
+            }
+            if end <= start {
+                continue;
+            }
+            if start > time {
+                out.push_str(&format!("    s{}\n", duration(start - time)));
+            }
+            let root = match NoteName::parse_prefix(&chord.name, 2) {
+                Some((root, _)) if root.pitch_class() == PitchClass::new(chord.root) => root,
+                _ => self.key.spell(PitchClass::new(chord.root))
+            };
+            out.push_str(&format!("    {}{}:{}\n", note_name(root), duration(end - start), chord_steps(chord)));
+            time = end;
+        }
+        out.push_str("  }\n");
+    }
+
+    /// Writes the part at `index` as a staff, with a line for each measure.
+    fn write_staff(&self, out: &mut String, index: usize) {
+        let part = &self.parts[index];
+        let clef = match part.clef() {
+            Clef::Percussion => "percussion",
+            Clef::Bass => "bass",
+            Clef::Treble => "treble"
+        };
+        let key = self.key_signature_tonic();
+        let mode = match key.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor"
+        };
+        out.push_str(&format!("  \\new Staff \\with {{ instrumentName = \"{}\" }} {{\n", escape(&self.part_name(index))));
+        out.push_str(&format!("    \\clef {}\n    \\key {} \\{}\n", clef, note_name(key.tonic_name()), mode));
+        out.push_str(&format!("    \\time {}/{}\n", self.time_signature.numerator(), self.time_signature.denominator()));
+        if index == 0 {
+            out.push_str(&format!("    \\tempo 4 = {}\n", self.bpm.round()));
+        }
+        for events in self.measures(index) {
+            let events: Vec<String> = events.iter().map(|event| self.lilypond_event(event)).collect();
+            out.push_str(&format!("    {} |\n", events.join(" ")));
+        }
+        out.push_str("  }\n");
+    }
+
+    /// A note, chord or rest, such as `c'4~`, `<c' e' g'>2.` or `r8`.
+    fn lilypond_event(&self, event: &ScoreEvent) -> String {
+        let value = note_value(event.kind, event.dotted);
+        let notes: Vec<String> = event.notes
+            .iter()
+            .map(|note| {
+                let (name, octave) = self.spell(note.pitch);
+                let octave = match octave - 3 {
+                    marks if marks < 0 => ",".repeat(-marks as usize),
+                    marks => "'".repeat(marks as usize)
+                };
+                (note_name(name) + &octave, note.tie_start)
+            })
+            .map(|(pitch, tie_start)| match (event.notes.len(), tie_start) {
+                (1, true) => format!("{}{}~", pitch, value),
+                (1, false) => format!("{}{}", pitch, value),
+                (_, true) => format!("{}~", pitch),
+                (_, false) => pitch
+            })
+            .collect();
+        match notes.len() {
+            0 => format!("r{}", value),
+            1 => notes[0].clone(),
+            _ => format!("<{}>{}", notes.join(" "), value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_modules_v2::{midi::TrackSettings, score::{ScoreNote, ScorePart}};
+
+    #[test]
+    fn chord_names_and_staves() {
+        let note = |pitch: u8, start: f64, length: f64| ScoreNote { pitch, start, length };
+        let score = Score {
+            title: Some("A \"Song\"".to_string()),
+            bpm: 90.4,
+            time_signature: "3/4".parse().unwrap(),
+            key_signature: (-3, true),
+            key: "Cmin".parse().unwrap(),
+            chords: vec![
+                PlacedChord { name: "Eb major".to_string(), root: 3, pitch_classes: vec![3, 7, 10], start: 0.0, length: 3.0 },
+                PlacedChord { name: "B diminished".to_string(), root: 11, pitch_classes: vec![11, 2, 5], start: 4.0, length: 2.0 },
+            ],
+            parts: vec![
+                ScorePart {
+                    settings: TrackSettings::default(),
+                    notes: vec![note(51, 0.0, 3.0), note(55, 0.0, 4.0), note(59, 4.0, 2.0)],
+                },
+                ScorePart { settings: TrackSettings::new("Lead", 1, None), notes: vec![note(75, 1.0, 0.5)] },
+            ],
+        };
+        let lilypond = score.to_lilypond();
+        assert!(lilypond.starts_with("\\version \"2.24.0\"\n\n\\header {\n  title = \"A \\\"Song\\\"\"\n}"), "{}", lilypond);
+        assert!(lilypond.contains("    ees2.:1.3.5\n    s4\n    b2:1.3-.5-\n"), "{}", lilypond);
+        assert!(lilypond.contains("\\new Staff \\with { instrumentName = \"Chords\" } {\n    \\clef bass\n    \\key c \\minor\n    \\time 3/4\n    \\tempo 4 = 90\n"), "{}", lilypond);
+        // the G is tied over the barline, and the B natural is spelled in C minor
+        assert!(lilypond.contains("    <ees g~>2. |\n    g4 b2 |\n"), "{}", lilypond);
+        assert!(lilypond.contains("instrumentName = \"Lead\" } {\n    \\clef treble\n    \\key c \\minor\n    \\time 3/4\n    r4 ees''8 r4. |\n    r2. |\n"), "{}", lilypond);
+        assert_eq!(duration(5), "16*5");
+        assert!(lilypond.ends_with(">>\n"));
+    }
+}
@@ -0,0 +1,383 @@
+//! Picking chords with a Markov chain.
+//!
+//! Chords are named by their Roman numeral in the key, without any sevenths or
+//! other extensions, so `ii7` and `ii9` are both `ii`. The model counts which
+//! numeral follows each run of up to `order` numerals, as well as which
+//! harmonic function follows each function. When picking the next chord, the
+//! longest run that the model has seen is used, and the functions and a
+//! little bit of randomness are mixed in so that every chord in the chord
+//! table can still be picked.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    analysis::recognize_midi_chords,
+    chord::Chord,
+    chord_symbol::parse_progression,
+    config::Segmentation,
+    error::Error,
+    key::{Key, Mode, PitchClass},
+    roman::{self, HarmonicFunction, RomanNumeral},
+};
+
+/// How much of the weight of a chord comes from the numeral transitions.
+const NUMERAL_WEIGHT: f64 = 0.85;
+/// How much of the weight of a chord comes from the function transitions.
+const FUNCTION_WEIGHT: f64 = 0.1;
+/// How much of the weight of a chord is the same for every chord.
+const SMOOTHING_WEIGHT: f64 = 0.05;
+
+/// The progressions that the built-in model is trained on.
+const BUILT_IN_PROGRESSIONS: [&str; 36] = [
+    "I - V - vi - IV",
+    "I - vi - IV - V",
+    "vi - IV - I - V",
+    "I - IV - V - I",
+    "I - IV - vi - V",
+    "ii - V - I",
+    "ii7 - V7 - Imaj7",
+    "I - vi - ii - V",
+    "iii - vi - ii - V - I",
+    "I - V - vi - iii - IV - I - IV - V",
+    "IV - V - iii - vi",
+    "I - bVII - IV - I",
+    "I - IV - I - V",
+    "I - ii - IV - V",
+    "IV - I - V - vi",
+    "I - iii - IV - V",
+    "vi - ii - V - I",
+    "I - V/V - V - I",
+    "IV - V - I",
+    "I - bVI - bVII - I",
+    "I - vii° - I",
+    "Imaj7 - vi7 - ii7 - V7",
+    "i - VI - III - VII",
+    "i - iv - v - i",
+    "i - VII - VI - V",
+    "i - iv - VII - III",
+    "i - VI - VII - i",
+    "ii° - V - i",
+    "iiø7 - V7 - i",
+    "i - iv - V - i",
+    "i - III - VII - VI",
+    "VI - VII - i",
+    "i - VII - VI - VII",
+    "i - v - VI - iv",
+    "i - iv - i - V",
+    "i - VI - iv - V",
+];
+
+/// A chord as the Markov chain sees it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MarkovState {
+    /// The Roman numeral without extensions, such as `ii` or `bVII`.
+    pub numeral: String,
+    pub function: HarmonicFunction,
+}
+
+impl MarkovState {
+    /// Names the chord with the given root and notes in `key`.
+    pub fn new(root: PitchClass, notes: &[i16], key: &Key) -> Self {
+        let mut numeral = RomanNumeral::from_notes(root, notes, key);
+        numeral.suffix.clear();
+        MarkovState { numeral: numeral.to_string(), function: numeral.function() }
+    }
+
+    pub fn of_chord(chord: &Chord, key: &Key) -> Self {
+        Self::new(PitchClass::new(chord.root as i16 + chord.key), &chord.get_notes(), key)
+    }
+}
+
+/// Counts of the next numeral or function after each context.
+type Transitions = BTreeMap<String, BTreeMap<String, u32>>;
+
+/// A Markov model of chord progressions.
+///
+/// The model can be (de)serialized, so that a trained model can be saved and
+/// passed back in with `GenerationConfig::markov_model`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkovModel {
+    /// The most numerals that are looked back at when picking the next one.
+    pub order: usize,
+    /// The numerals that follow each context, where a context is up to
+    /// `order` numerals separated by spaces. The empty context holds the
+    /// numerals that progressions start with.
+    pub transitions: Transitions,
+    /// The functions that follow each function.
+    pub function_transitions: Transitions,
+}
+
+impl Default for MarkovModel {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+impl MarkovModel {
+    /// Creates an empty model. An order of 0 is treated as 1.
+    pub fn new(order: usize) -> Self {
+        MarkovModel { order: order.max(1), transitions: BTreeMap::new(), function_transitions: BTreeMap::new() }
+    }
+
+    /// A second-order model trained on common pop, jazz and classical
+    /// progressions in major and minor keys.
+    pub fn built_in() -> Self {
+        let mut model = Self::new(2);
+        for progression in BUILT_IN_PROGRESSIONS {
+            model.train_roman_numerals(progression).expect("the built-in progressions are valid");
+        }
+        model
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// Counts the transitions between a progression of chords in `key`.
+    /// Repeated chords are only counted once.
+    pub fn train_chords(&mut self, chords: &[Chord], key: &Key) {
+        let mut states: Vec<MarkovState> = Vec::with_capacity(chords.len());
+        for state in chords.iter().map(|chord| MarkovState::of_chord(chord, key)) {
+            if states.last() != Some(&state) {
+                states.push(state);
+            }
+        }
+        self.train_states(&states);
+    }
+
+    fn train_states(&mut self, states: &[MarkovState]) {
+        let numerals: Vec<&str> = states.iter().map(|state| state.numeral.as_str()).collect();
+        for (i, state) in states.iter().enumerate() {
+            let count = |transitions: &mut Transitions, context: String, next: &str| {
+                *transitions.entry(context).or_default().entry(next.to_string()).or_default() += 1;
+            };
+            if i == 0 {
+                count(&mut self.transitions, String::new(), &state.numeral);
+                continue;
+            }
+            for n in 1..=self.order.min(i) {
+                count(&mut self.transitions, numerals[i - n..i].join(" "), &state.numeral);
+            }
+            count(&mut self.function_transitions, states[i - 1].function.to_string(), &state.function.to_string());
+        }
+    }
+
+    /// Trains on Roman numerals such as `ii7-V7-Imaj7`. The progression is in
+    /// a minor key when its first tonic chord is lowercase.
+    pub fn train_roman_numerals(&mut self, progression: &str) -> Result<(), Error> {
+        let numerals = roman::parse_progression(progression)?;
+        let is_minor = numerals
+            .iter()
+            .find(|numeral| numeral.degree == 1 && numeral.accidental == 0 && numeral.secondary.is_none())
+            .is_some_and(|tonic| !tonic.is_major);
+        let key = Key::new(PitchClass::new(0), if is_minor { Mode::Minor } else { Mode::Major });
+        let chords = numerals
+            .iter()
+            .map(|numeral| numeral.to_chord(&key))
+            .collect::<Result<Vec<Chord>, Error>>()?;
+        self.train_chords(&chords, &key);
+        Ok(())
+    }
+
+    /// Trains on chord symbols such as `Am - F - C - G`. Without a key, the
+    /// first chord is taken to be the tonic.
+    pub fn train_chord_symbols(&mut self, progression: &str, key: Option<Key>) -> Result<(), Error> {
+        let chords: Vec<Chord> = parse_progression(progression)?
+            .iter()
+            .map(|symbol| symbol.to_chord())
+            .collect();
+        let key = key.unwrap_or_else(|| key_of_first_chord(&chords));
+        self.train_chords(&chords, &key);
+        Ok(())
+    }
+
+    /// Trains on either Roman numerals or chord symbols.
+    pub fn train_progression(&mut self, progression: &str, key: Option<Key>) -> Result<(), Error> {
+        match roman::parse_progression(progression) {
+            Ok(_) => self.train_roman_numerals(progression),
+            Err(_) => self.train_chord_symbols(progression, key)
+        }
+    }
+
+    /// Trains on the chords that are recognized between the note onsets of a
+    /// MIDI file. Without a key, the first chord is taken to be the tonic.
+    pub fn train_midi(&mut self, midi: &[u8], key: Option<Key>) -> Result<(), Error> {
+        let chords: Vec<Chord> = recognize_midi_chords(midi, Segmentation::Onsets)?
+            .into_iter()
+            .map(|recognized| recognized.chord)
+            .collect();
+        if chords.is_empty() {
+            return Err(Error::InvalidInput("the MIDI file does not contain any chords".to_string()));
+        }
+        let key = key.unwrap_or_else(|| key_of_first_chord(&chords));
+        self.train_chords(&chords, &key);
+        Ok(())
+    }
+
+    /// Returns the weight of each candidate given the states of the previous
+    /// chords. Every weight is positive.
+    pub fn weights(&self, history: &[MarkovState], candidates: &[MarkovState]) -> Vec<f64> {
+        let mut numerals: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut functions: BTreeMap<String, usize> = BTreeMap::new();
+        for candidate in candidates {
+            *numerals.entry(&candidate.numeral).or_default() += 1;
+        }
+        for numeral in numerals.keys() {
+            let function = candidates.iter().find(|c| c.numeral == *numeral).expect("the numeral is a candidate").function;
+            *functions.entry(function.to_string()).or_default() += 1;
+        }
+
+        // the probability of each numeral after the longest context that has
+        // been followed by any of the candidates
+        let numeral_probabilities = (0..=self.order.min(history.len()))
+            .rev()
+            .filter(|n| *n > 0 || history.is_empty())
+            .find_map(|n| {
+                let context = history[history.len() - n..]
+                    .iter()
+                    .map(|state| state.numeral.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                probabilities(self.transitions.get(&context)?, |numeral| numerals.contains_key(numeral))
+            });
+        let function_probabilities = history.last().and_then(|previous| {
+            probabilities(
+                self.function_transitions.get(&previous.function.to_string())?,
+                |function| functions.contains_key(function)
+            )
+        });
+
+        candidates
+            .iter()
+            .map(|candidate| {
+                let function = candidate.function.to_string();
+                let numeral_weight = numeral_probabilities
+                    .as_ref()
+                    .and_then(|p| p.get(&candidate.numeral))
+                    .map_or(0.0, |p| NUMERAL_WEIGHT * p);
+                let function_weight = function_probabilities
+                    .as_ref()
+                    .and_then(|p| p.get(&function))
+                    .map_or(0.0, |p| FUNCTION_WEIGHT * p / functions[&function] as f64);
+                let weight = numeral_weight + function_weight + SMOOTHING_WEIGHT / numerals.len() as f64;
+                // chords that share a numeral share its weight
+                weight / numerals[candidate.numeral.as_str()] as f64
+            })
+            .collect()
+    }
+}
+
+/// Normalizes the counts of the accepted keys, or returns `None` when none of
+/// them were counted.
+fn probabilities(counts: &BTreeMap<String, u32>, accept: impl Fn(&str) -> bool) -> Option<BTreeMap<String, f64>> {
+    let total: u32 = counts.iter().filter(|(key, _)| accept(key)).map(|(_, count)| count).sum();
+    if total == 0 {
+        return None;
+    }
+    Some(counts
+        .iter()
+        .filter(|(key, _)| accept(key))
+        .map(|(key, count)| (key.clone(), *count as f64 / total as f64))
+        .collect())
+}
+
+/// The key whose tonic is the root of the first chord, which is minor when
+/// the chord has a minor third.
+fn key_of_first_chord(chords: &[Chord]) -> Key {
+    match chords.first() {
+        Some(chord) => {
+            let root = chord.root as i16 + chord.key;
+            let notes = chord.get_notes();
+            let has = |interval: i16| notes.iter().any(|note| (note - root).rem_euclid(12) == interval);
+            let mode = if has(3) && !has(4) { Mode::Minor } else { Mode::Major };
+            Key::new(PitchClass::new(root), mode)
+        },
+        None => Key::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(numeral: &str) -> MarkovState {
+        let numeral: RomanNumeral = numeral.parse().unwrap();
+        MarkovState { numeral: numeral.to_string(), function: numeral.function() }
+    }
+
+    #[test]
+    fn training_counts_transitions() {
+        let mut model = MarkovModel::new(2);
+        model.train_roman_numerals("ii7 - V7 - Imaj7 - Imaj7 - vi").unwrap();
+        assert_eq!(model.transitions[""]["ii"], 1);
+        assert_eq!(model.transitions["ii"]["V"], 1);
+        assert_eq!(model.transitions["ii V"]["I"], 1);
+        // repeated chords are only counted once
+        assert!(!model.transitions["I"].contains_key("I"));
+        assert_eq!(model.transitions["V I"]["vi"], 1);
+        assert_eq!(model.function_transitions["dominant"]["tonic"], 1);
+
+        let mut model = MarkovModel::new(1);
+        model.train_roman_numerals("i - VI - III - VII").unwrap();
+        assert_eq!(model.transitions["VI"]["III"], 1);
+        assert!(!model.transitions.contains_key("i VI"));
+    }
+
+    #[test]
+    fn training_on_chord_symbols() {
+        let mut model = MarkovModel::new(1);
+        model.train_progression("Am - F - C - G", None).unwrap();
+        assert_eq!(model.transitions[""]["i"], 1);
+        assert_eq!(model.transitions["VI"]["III"], 1);
+
+        let mut model = MarkovModel::new(1);
+        model.train_progression("Am - F - C - G", Some("Cmaj".parse().unwrap())).unwrap();
+        assert_eq!(model.transitions["vi"]["IV"], 1);
+        assert!(model.train_progression("Am - Q", None).is_err());
+    }
+
+    #[test]
+    fn training_on_midi() {
+        let midi = crate::generate_midi_from_chord_symbols("C - F/C - G7 - C").unwrap();
+        let mut model = MarkovModel::new(2);
+        model.train_midi(&midi, None).unwrap();
+        assert_eq!(model.transitions["I"]["IV"], 1);
+        assert_eq!(model.transitions["I IV"]["V"], 1);
+        assert_eq!(model.transitions["IV V"]["I"], 1);
+
+        let no_chords = crate::write_chord_progression(&[vec![60], vec![62]]).unwrap();
+        assert!(model.train_midi(&no_chords, None).is_err());
+        assert!(model.train_midi(b"not midi", None).is_err());
+    }
+
+    #[test]
+    fn weights_follow_the_model() {
+        let model = MarkovModel::built_in();
+        let candidates: Vec<MarkovState> = ["I", "ii", "IV", "V", "vi", "iii"].into_iter().map(state).collect();
+        let weights = model.weights(&[state("ii"), state("V")], &candidates);
+        assert!(weights.iter().all(|weight| *weight > 0.0));
+        let most_likely = weights.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(candidates[most_likely].numeral, "I");
+
+        // chords that share a numeral split its weight
+        let weights = model.weights(&[], &[state("I"), state("I"), state("V")]);
+        assert_eq!(weights[0], weights[1]);
+
+        // the model backs off to shorter contexts and to functions
+        let weights = model.weights(&[state("bII")], &candidates);
+        assert!(weights.iter().all(|weight| *weight > 0.0));
+    }
+
+    #[test]
+    fn serde() {
+        let mut model = MarkovModel::new(1);
+        model.train_roman_numerals("I - IV - V").unwrap();
+        let json = serde_json::to_string(&model).unwrap();
+        assert_eq!(serde_json::from_str::<MarkovModel>(&json).unwrap(), model);
+        let model: MarkovModel = serde_json::from_str(r#"{"transitions": {"": {"I": 1}}}"#).unwrap();
+        assert_eq!(model.order, 2);
+    }
+}
@@ -0,0 +1,259 @@
+//! Top-line melodies.
+//!
+//! A melody is a single voice that walks the notes of the scale over the
+//! placed chords. It prefers steps to leaps, lands on chord tones on the
+//! strong beats, and follows the shape of a phrase that lasts a few chords.
+
+use std::{collections::HashSet, f64::consts::PI};
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::PhraseShape, error::Error, meter::Meter, midi::TrackSettings, music::PlacedChord, utils::MathMagician};
+
+/// The number of chords in a phrase.
+const PHRASE_CHORDS: usize = 4;
+
+/// The settings for a melody.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Melody {
+    pub shape: PhraseShape,
+    /// The lowest and highest notes of the melody, which must be at least an
+    /// octave apart.
+    pub lowest_note: u8,
+    pub highest_note: u8,
+    /// The chance that a beat is left silent, from 0 to 1. The first and
+    /// last notes of a phrase are always played.
+    pub rest_probability: f64,
+    pub track: TrackSettings,
+}
+
+impl Default for Melody {
+    fn default() -> Self {
+        Melody {
+            shape: PhraseShape::Arch,
+            lowest_note: 60,
+            highest_note: 81,
+            rest_probability: 0.15,
+            track: TrackSettings::new("Melody", 2, Some(73)),
+        }
+    }
+}
+
+/// A note of a melody. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MelodyNote {
+    pub start: f64,
+    pub length: f64,
+    pub pitch: u8,
+}
+
+impl PhraseShape {
+    /// The height that the melody aims for at `position` in the phrase, from
+    /// 0 at the bottom of the range to 1 at the top. `position` goes from 0
+    /// to 1.
+    pub fn contour(&self, position: f64) -> f64 {
+        match self {
+            PhraseShape::Arch => 0.2 + 0.6 * (PI * position).sin(),
+            PhraseShape::Ascending => 0.15 + 0.7 * position,
+            // the call rises, and the answer comes back down from where the
+            // call stopped
+            PhraseShape::CallAndResponse => match position < 0.5 {
+                true => 0.3 + 0.8 * position,
+                false => 0.7 - 0.8 * (position - 0.5)
+            }
+        }
+    }
+}
+
+impl Melody {
+    /// Creates a melody with the default range and track.
+    pub fn new(shape: PhraseShape) -> Self {
+        Melody { shape, ..Default::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        self.track.validate()?;
+        if self.highest_note > 127 || self.lowest_note as u16 + 12 > self.highest_note as u16 {
+            return Err(Error::InvalidInput(format!(
+                "the range of a melody must be at least an octave between MIDI notes 0 and 127, but it is {} to {}",
+                self.lowest_note,
+                self.highest_note
+            )));
+        }
+        if !(0.0..1.0).contains(&self.rest_probability) {
+            return Err(Error::InvalidInput(format!("the rest probability of a melody must be at least 0 and less than 1, but it is {}", self.rest_probability)));
+        }
+        Ok(())
+    }
+
+    /// Picks the next note from the notes of `pitch_classes` in range. Steps
+    /// are preferred over leaps, and notes near `target` over notes far from
+    /// it.
+    fn pick(&self, pitch_classes: &HashSet<i16>, previous: u8, target: f64, math_magician: &mut MathMagician) -> Option<u8> {
+        let candidates: Vec<u8> = (self.lowest_note..=self.highest_note)
+            .filter(|note| pitch_classes.contains(&(*note as i16 % 12)))
+            .collect();
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|note| {
+                let motion = match note.abs_diff(previous) {
+                    0 => 0.6,
+                    1..=2 => 4.0,
+                    3..=4 => 1.5,
+                    5..=7 => 0.4,
+                    _ => 0.05
+                };
+                motion * (-(*note as f64 - target).abs() / 4.0).exp()
+            })
+            .collect();
+        match candidates.is_empty() {
+            true => None,
+            false => Some(candidates[math_magician.weighted_decision(&weights)])
+        }
+    }
+
+    /// Writes a melody over the chords, using the notes of `scale`, which are
+    /// pitch classes in the key of the song.
+    pub fn notes(&self, chords: &[PlacedChord], scale: &HashSet<i16>, meter: &Meter, math_magician: &mut MathMagician) -> Vec<MelodyNote> {
+        let beat = meter.beat_length();
+        let bar = meter.bar_length();
+        // the middle of bars with an even number of beats is also strong
+        let half_bar = match meter.beats_per_bar() % 2 {
+            0 => bar / 2.0,
+            _ => bar
+        };
+        let is_strong = |time: f64| (time / half_bar - (time / half_bar).round()).abs() < 1e-9;
+        let span = (self.highest_note - self.lowest_note) as f64;
+
+        let mut notes = Vec::new();
+        let mut previous = None;
+        for phrase in chords.chunks(PHRASE_CHORDS) {
+            for (i, chord) in phrase.iter().enumerate() {
+                let is_last_chord = i == phrase.len() - 1;
+                let chord_tones: HashSet<i16> = chord.pitch_classes.iter().copied().collect();
+                // the last chord of a phrase only has notes in its first half,
+                // and the last of them is held until the end
+                let end = match is_last_chord {
+                    true => (chord.length / 2.0).max(beat.min(chord.length)),
+                    false => chord.length
+                };
+                let mut steps: Vec<(f64, f64)> = Vec::new();
+                let mut time = 0.0;
+                while time < end - 1e-9 {
+                    let length = beat.min(end - time);
+                    // some beats are split into two notes
+                    match length == beat && math_magician.big_decision(0, 3) == 0 {
+                        true => steps.extend([(time, beat / 2.0), (time + beat / 2.0, beat / 2.0)]),
+                        false => steps.push((time, length))
+                    }
+                    time += length;
+                }
+                if is_last_chord {
+                    if let Some(last) = steps.last_mut() {
+                        last.1 = chord.length - last.0;
+                    }
+                }
+
+                for (j, (start, length)) in steps.iter().enumerate() {
+                    let is_first = i == 0 && j == 0;
+                    let is_last = is_last_chord && j == steps.len() - 1;
+                    if !is_first && !is_last && (math_magician.big_decision(0, 999) as f64) < self.rest_probability * 1000.0 {
+                        continue;
+                    }
+                    let position = (i as f64 + start / chord.length) / phrase.len() as f64;
+                    let target = self.lowest_note as f64 + span * self.shape.contour(position);
+                    // the call of a call and response ends with a rest
+                    let end_position = position + length / chord.length / phrase.len() as f64;
+                    if self.shape == PhraseShape::CallAndResponse && !is_last && position < 0.5 - 1e-9 && end_position > 0.5 - 1e-9 {
+                        continue;
+                    }
+                    let pitch_classes = match is_strong(*start) || is_last {
+                        true => &chord_tones,
+                        false => scale
+                    };
+                    let from = previous.unwrap_or(target.round() as u8);
+                    let pitch = self.pick(pitch_classes, from, target, math_magician)
+                        .or_else(|| self.pick(scale, from, target, math_magician));
+                    if let Some(pitch) = pitch {
+                        notes.push(MelodyNote { start: chord.start + start, length: *length, pitch });
+                        previous = Some(pitch);
+                    }
+                }
+            }
+        }
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chords() -> Vec<PlacedChord> {
+        // C major, A minor, F major and G major, one bar each, twice
+        let chords = [(0, [0, 4, 7], 4.0), (9, [9, 0, 4], 4.0), (5, [5, 9, 0], 4.0), (7, [7, 11, 2], 4.0)];
+        PlacedChord::one_after_another(&chords.repeat(2))
+    }
+
+    fn melody(melody: &Melody) -> Vec<MelodyNote> {
+        let scale: HashSet<i16> = [0, 2, 4, 5, 7, 9, 11].into_iter().collect();
+        melody.notes(&chords(), &scale, &Meter::default(), &mut MathMagician::share_hash([0; 32]))
+    }
+
+    #[test]
+    fn monophonic_in_range_and_in_the_scale() {
+        let chords = chords();
+        for shape in PhraseShape::ALL {
+            let notes = melody(&Melody::new(*shape));
+            assert!(notes.len() > 16, "{:?}", notes);
+            // the notes never overlap
+            assert!(notes.windows(2).all(|pair| pair[0].start + pair[0].length <= pair[1].start + 1e-9));
+            assert!(notes.iter().all(|note| (60..=81).contains(&note.pitch)));
+            assert!(notes.iter().all(|note| [0, 2, 4, 5, 7, 9, 11].contains(&(note.pitch % 12))));
+            // strong beats are chord tones
+            for note in notes.iter().filter(|note| note.start % 2.0 == 0.0) {
+                let chord = &chords[(note.start / 4.0) as usize];
+                assert!(chord.pitch_classes.contains(&(note.pitch as i16 % 12)), "{:?} over {:?}", note, chord);
+            }
+            // every phrase ends on a note that is held until the end
+            let last = notes.last().unwrap();
+            assert_eq!(last.start + last.length, 32.0);
+        }
+    }
+
+    #[test]
+    fn stepwise_motion_and_shapes() {
+        let notes = melody(&Melody { rest_probability: 0.0, ..Melody::new(PhraseShape::Ascending) });
+        let leaps = notes.windows(2).filter(|pair| pair[0].pitch.abs_diff(pair[1].pitch) > 4).count();
+        assert!(leaps * 4 < notes.len(), "{:?}", notes);
+        // the melody ends each phrase higher than it starts
+        let first = notes.iter().filter(|note| note.start < 4.0).map(|note| note.pitch as f64).sum::<f64>();
+        let first_count = notes.iter().filter(|note| note.start < 4.0).count() as f64;
+        let last = notes.iter().filter(|note| (12.0..16.0).contains(&note.start)).map(|note| note.pitch as f64).sum::<f64>();
+        let last_count = notes.iter().filter(|note| (12.0..16.0).contains(&note.start)).count() as f64;
+        assert!(first / first_count < last / last_count);
+
+        assert!(PhraseShape::Arch.contour(0.5) > PhraseShape::Arch.contour(0.0));
+        assert!(PhraseShape::CallAndResponse.contour(0.9) < PhraseShape::CallAndResponse.contour(0.5));
+    }
+
+    #[test]
+    fn rests() {
+        let notes = melody(&Melody { rest_probability: 0.9, ..Default::default() });
+        // the first and last notes of every phrase are played
+        assert!(notes.iter().any(|note| note.start == 0.0));
+        assert!(notes.iter().any(|note| note.start == 16.0));
+        assert!(notes.len() < 12, "{:?}", notes);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Melody::default().validate().is_ok());
+        assert!(Melody { lowest_note: 70, highest_note: 75, ..Default::default() }.validate().is_err());
+        assert!(Melody { highest_note: 130, ..Default::default() }.validate().is_err());
+        assert!(Melody { rest_probability: 1.0, ..Default::default() }.validate().is_err());
+        let melody: Melody = serde_json::from_str(r#"{"shape": "call-and-response"}"#).unwrap();
+        assert_eq!(melody, Melody::new(PhraseShape::CallAndResponse));
+    }
+}
@@ -0,0 +1,178 @@
+//! Time signatures and meters.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+
+/// A time signature such as `4/4` or `6/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeSignature {
+    numerator: u8,
+    denominator: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature { numerator: 4, denominator: 4 }
+    }
+}
+
+impl TimeSignature {
+    /// Creates a time signature. The numerator must be at least 1, and the
+    /// denominator must be a power of two from 1 to 64.
+    pub fn new(numerator: u8, denominator: u8) -> Result<Self, Error> {
+        let invalid = |reason| Error::InvalidTimeSignature { time_signature: format!("{}/{}", numerator, denominator), reason };
+        if numerator == 0 {
+            return Err(invalid("there must be at least one beat in a bar"));
+        }
+        if !denominator.is_power_of_two() || denominator > 64 {
+            return Err(invalid("the beat unit must be 1, 2, 4, 8, 16, 32 or 64"));
+        }
+        Ok(TimeSignature { numerator, denominator })
+    }
+
+    /// The number of beats in a bar.
+    pub fn numerator(&self) -> u8 {
+        self.numerator
+    }
+
+    /// The note value of a beat, where 4 is a quarter note.
+    pub fn denominator(&self) -> u8 {
+        self.denominator
+    }
+}
+
+/// How long bars and chords are. Lengths are measured in quarter notes, which
+/// are the beats of the MIDI file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Meter {
+    time_signature: TimeSignature,
+    bars_per_chord: f64,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter { time_signature: TimeSignature::default(), bars_per_chord: 1.0 }
+    }
+}
+
+impl Meter {
+    /// Creates a meter where each chord lasts `bars_per_chord` bars, such as 
+    /// `2.0` for two bars or `0.5` for half a bar.
+    pub fn new(time_signature: TimeSignature, bars_per_chord: f64) -> Result<Self, Error> {
+        if !(bars_per_chord > 0.0 && bars_per_chord <= 64.0) {
+            return Err(Error::InvalidInput(format!("a chord must last between 0 and 64 bars, but it lasts {}", bars_per_chord)));
+        }
+        Ok(Meter { time_signature, bars_per_chord })
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    /// The number of beats in a bar.
+    pub fn beats_per_bar(&self) -> u8 {
+        self.time_signature.numerator
+    }
+
+    /// The note value of a beat, where 4 is a quarter note and 8 is an eighth
+    /// note.
+    pub fn beat_unit(&self) -> u8 {
+        self.time_signature.denominator
+    }
+
+    /// The length of a beat in quarter notes, such as 0.5 for `6/8`.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / self.beat_unit() as f64
+    }
+
+    pub fn bars_per_chord(&self) -> f64 {
+        self.bars_per_chord
+    }
+
+    /// The length of a bar in quarter notes, such as 3 for `3/4` and `6/8`, or
+    /// 3.5 for `7/8`.
+    pub fn bar_length(&self) -> f64 {
+        self.beats_per_bar() as f64 * 4.0 / self.beat_unit() as f64
+    }
+
+    /// The length of each chord in quarter notes.
+    pub fn chord_length(&self) -> f64 {
+        self.bar_length() * self.bars_per_chord
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Error::InvalidTimeSignature { time_signature: s.to_string(), reason };
+        let (numerator, denominator) = s.split_once('/').ok_or_else(|| invalid("expected two numbers separated by `/`"))?;
+        match (numerator.trim().parse(), denominator.trim().parse()) {
+            (Ok(numerator), Ok(denominator)) => Self::new(numerator, denominator),
+            _ => Err(invalid("expected two numbers separated by `/`"))
+        }
+    }
+}
+
+impl TryFrom<String> for TimeSignature {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeSignature> for String {
+    fn from(value: TimeSignature) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_time_signatures() {
+        let waltz: TimeSignature = "3/4".parse().unwrap();
+        assert_eq!((waltz.numerator(), waltz.denominator()), (3, 4));
+        assert_eq!(" 6 / 8 ".parse::<TimeSignature>().unwrap().to_string(), "6/8");
+        for invalid in ["", "4", "4/", "0/4", "4/3", "4/128", "a/4", "4/4/4"] {
+            assert!(invalid.parse::<TimeSignature>().is_err(), "`{}` should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn serde() {
+        let time_signature: TimeSignature = serde_json::from_str(r#""7/8""#).unwrap();
+        assert_eq!(serde_json::to_string(&time_signature).unwrap(), r#""7/8""#);
+        assert!(serde_json::from_str::<TimeSignature>(r#""7/7""#).is_err());
+    }
+
+    #[test]
+    fn chord_lengths() {
+        let meter = |time_signature: &str, bars_per_chord| Meter::new(time_signature.parse().unwrap(), bars_per_chord).unwrap();
+        assert_eq!(Meter::default().chord_length(), 4.0);
+        assert_eq!(meter("3/4", 1.0).chord_length(), 3.0);
+        assert_eq!(meter("6/8", 1.0).chord_length(), 3.0);
+        assert_eq!(meter("6/8", 1.0).beats_per_bar(), 6);
+        assert_eq!(meter("6/8", 1.0).beat_length(), 0.5);
+        assert_eq!(meter("5/4", 1.0).chord_length(), 5.0);
+        assert_eq!(meter("7/8", 1.0).chord_length(), 3.5);
+        assert_eq!(meter("4/4", 2.0).chord_length(), 8.0);
+        assert_eq!(meter("3/4", 0.5).chord_length(), 1.5);
+        for bars_per_chord in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Meter::new(TimeSignature::default(), bars_per_chord).is_err());
+        }
+    }
+}
@@ -1,11 +1,14 @@
 // use midly::
 
-use std::{cmp, collections::HashMap};
+use std::{cmp, collections::{HashMap, VecDeque}};
 
-use midly::{TrackEvent, TrackEventKind, MidiMessage};
+use midly::{MetaMessage, TrackEvent, TrackEventKind, MidiMessage, Smf};
+use serde::{Deserialize, Serialize};
 
 use crate::console_log;
 
+use super::{error::Error, humanize::Humanizer, meter::{Meter, TimeSignature}, utils::MathMagician};
+
 use super::utils::beats;
 
 pub type Track<'a> = Vec<TrackEvent<'a>>;
@@ -18,7 +21,64 @@ pub type Track<'a> = Vec<TrackEvent<'a>>;
  */
 #[derive(Debug, Clone)]
 pub struct MidiFile {
-    notes: Vec<MidiNote>
+    notes: Vec<MidiNote>,
+    settings: TrackSettings,
+    song_info: Option<SongInfo>,
+    /// The start times and text of the markers, such as the sections of a 
+    /// pattern.
+    markers: Vec<(u32, String)>
+}
+
+/// The title, tempo, time signature and key signature of a song, which are 
+/// written at the start of the first track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongInfo {
+    /// Written as the name of the track, in place of the track's own name.
+    pub title: Option<String>,
+    pub bpm: f64,
+    pub time_signature: TimeSignature,
+    /// The number of sharps, or flats when negative, and whether the key is 
+    /// minor.
+    pub key_signature: Option<(i8, bool)>,
+}
+
+impl Default for SongInfo {
+    fn default() -> Self {
+        SongInfo { title: None, bpm: 120.0, time_signature: TimeSignature::default(), key_signature: None }
+    }
+}
+
+/// The name, channel and instrument of a track.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackSettings {
+    /// Written as a track name meta event when set.
+    pub name: Option<String>,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: u8,
+    /// The General MIDI program number, from 0 to 127. A program change is 
+    /// written at the start of the track when set.
+    pub program: Option<u8>,
+}
+
+impl TrackSettings {
+    pub fn new(name: &str, channel: u8, program: Option<u8>) -> Self {
+        TrackSettings { name: Some(name.to_string()), channel, program }
+    }
+
+    /// Checks that the channel and program are in range.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.channel > 15 {
+            return Err(Error::InvalidInput(format!("MIDI channels go from 0 to 15, but the channel is {}", self.channel)));
+        }
+        match self.program {
+            Some(program) if program > 127 => Err(Error::InvalidInput(format!(
+                "MIDI programs go from 0 to 127, but the program is {}",
+                program
+            ))),
+            _ => Ok(())
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -32,8 +92,51 @@ pub struct MidiNote {
 impl MidiFile {
     #[inline(always)]
     pub fn new() -> Self {
-        MidiFile{notes: Vec::new()}
+        MidiFile{notes: Vec::new(), settings: TrackSettings::default(), song_info: None, markers: Vec::new()}
+    }
+
+    /// Creates an empty track with a name, channel and instrument.
+    pub fn with_settings(settings: TrackSettings) -> Self {
+        MidiFile{notes: Vec::new(), settings, song_info: None, markers: Vec::new()}
+    }
+
+    /// Writes the title, tempo, time signature and key signature at the start 
+    /// of this track.
+    pub fn set_song_info(&mut self, song_info: SongInfo) {
+        self.song_info = Some(song_info);
+    }
+
+    pub fn settings(&self) -> &TrackSettings {
+        &self.settings
+    }
+
+    pub fn song_info(&self) -> Option<&SongInfo> {
+        self.song_info.as_ref()
+    }
+
+    /// Returns the pitch, start time and length of every note, where the 
+    /// times are in beats. The notes are sorted by their start times.
+    pub fn notes_in_beats(&self) -> Vec<(u8, f64, f64)> {
+        let beat = beats(1.0) as f64;
+        let mut notes: Vec<(u8, f64, f64)> = self.note_pairs()
+            .iter()
+            .map(|(on, off)| {
+                let (on, off) = (&self.notes[*on], &self.notes[*off]);
+                (on.pitch, on.start_time as f64 / beat, (off.start_time - on.start_time) as f64 / beat)
+            })
+            .collect();
+        notes.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        notes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+    /// Adds a marker, such as the name of a section, at `time` beats.
+    pub fn add_marker_beats(&mut self, time: f64, text: &str) {
+        self.markers.push((beats(time), text.to_string()));
     }
+
     #[inline(always)]
     pub fn add_note_beats(
         &mut self,
@@ -70,6 +173,53 @@ impl MidiFile {
         });
     }
 
+    /// Matches each note on with its note off, as indices into `notes`.
+    fn note_pairs(&self) -> Vec<(usize, usize)> {
+        let mut order: Vec<usize> = (0..self.notes.len()).collect();
+        // note offs come first, so that a note can start where another one 
+        // of the same pitch ends
+        order.sort_by_key(|i| (self.notes[*i].start_time, self.notes[*i].note_on));
+        let mut playing: HashMap<u8, VecDeque<usize>> = HashMap::new();
+        let mut pairs = Vec::new();
+        for i in order {
+            let note = &self.notes[i];
+            match note.note_on {
+                true => playing.entry(note.pitch).or_default().push_back(i),
+                false => if let Some(on) = playing.get_mut(&note.pitch).and_then(|notes| notes.pop_front()) {
+                    pairs.push((on, i));
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Moves the notes off the grid and changes their velocities. See 
+    /// `Humanizer` for the settings. The notes keep their lengths, unless 
+    /// they end on a swung note.
+    pub fn humanize(&mut self, humanizer: &Humanizer, meter: &Meter, math_magician: &mut MathMagician) {
+        let pairs = self.note_pairs();
+        // the highest note and the number of notes that start at each time
+        let mut chords: HashMap<u32, (u8, usize)> = HashMap::new();
+        for (on, _) in pairs.iter() {
+            let note = &self.notes[*on];
+            let chord = chords.entry(note.start_time).or_insert((note.pitch, 0));
+            *chord = (chord.0.max(note.pitch), chord.1 + 1);
+        }
+        let is_percussion = self.settings.channel == 9;
+
+        for (on, off) in pairs {
+            let (start, end, pitch) = (self.notes[on].start_time, self.notes[off].start_time, self.notes[on].pitch);
+            let is_top_note = !is_percussion && chords[&start].1 > 1 && chords[&start].0 == pitch;
+            let velocity = humanizer.velocity(self.notes[on].volume, start, is_top_note, meter, math_magician);
+            let jitter = humanizer.jitter(math_magician);
+            let start = (humanizer.swing_time(start) as i64 + jitter).max(0) as u32;
+            let end = ((humanizer.swing_time(end) as i64 + jitter).max(0) as u32).max(start + 1);
+            self.notes[on] = MidiNote { start_time: start, volume: velocity, ..self.notes[on] };
+            self.notes[off] = MidiNote { start_time: end, volume: velocity, ..self.notes[off] };
+        }
+    }
+
     /**
      * A little helper function to finish creating the Vec<TrackEvent>, aka the Track
      */
@@ -80,12 +230,55 @@ impl MidiFile {
         console_log!("Successfully sorted notes");
         let mut result: Track = Vec::new();
         let mut last_time = 0;
+        let channel = self.settings.channel;
+
+        let title = self.song_info.as_ref().and_then(|info| info.title.as_ref());
+        if let Some(name) = title.or(self.settings.name.as_ref()) {
+            result.push(TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())) });
+        }
+        if let Some(info) = self.song_info.as_ref() {
+            let microseconds_per_beat = (60_000_000.0 / info.bpm).round() as u32;
+            let time_signature = info.time_signature;
+            let meta = [
+                MetaMessage::Tempo(microseconds_per_beat.into()),
+                MetaMessage::TimeSignature(
+                    time_signature.numerator(),
+                    time_signature.denominator().trailing_zeros() as u8,
+                    // MIDI clocks per metronome click, and 32nd notes per 
+                    // quarter note
+                    24,
+                    8
+                ),
+            ];
+            result.extend(meta.into_iter().map(|message| TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(message) }));
+            if let Some((sharps, is_minor)) = info.key_signature {
+                result.push(TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::KeySignature(sharps, is_minor)) });
+            }
+        }
+        if let Some(program) = self.settings.program {
+            result.push(TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Midi { channel: channel.into(), message: MidiMessage::ProgramChange { program: program.into() } }
+            });
+        }
 
+        self.markers.sort_by_key(|(time, _)| *time);
+        let mut markers = self.markers.iter().peekable();
         for n in self.notes.iter() {
-            result.push_track_event(n.start_time, last_time, n.pitch, n.volume, n.note_on);
+            // markers come before the notes that start with them
+            while let Some((time, text)) = markers.next_if(|(time, _)| *time <= n.start_time) {
+                result.push(TrackEvent { delta: (time - last_time).into(), kind: TrackEventKind::Meta(MetaMessage::Marker(text.as_bytes())) });
+                last_time = *time;
+            }
+            result.push_track_event(channel, n.start_time, last_time, n.pitch, n.volume, n.note_on);
 
             last_time = n.start_time;
         }
+        for (time, text) in markers {
+            result.push(TrackEvent { delta: (time - last_time).into(), kind: TrackEventKind::Meta(MetaMessage::Marker(text.as_bytes())) });
+            last_time = *time;
+        }
+        result.push(TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::EndOfTrack) });
 
         return result;
     }
@@ -103,18 +296,19 @@ impl MidiFile {
         let mut last_time = 0;
 
         let mut on_notes: HashMap<u8, u8> = HashMap::new();
+        let channel = self.settings.channel;
         for n in self.notes.iter() {
             // if the note is supposed to be played, and it is already playing, end the note
             if n.note_on {
                 if on_notes.contains_key(&n.pitch) {
-                    result.push_track_event(n.start_time, last_time, n.pitch, *on_notes.get(&n.pitch).unwrap(), false);
+                    result.push_track_event(channel, n.start_time, last_time, n.pitch, *on_notes.get(&n.pitch).unwrap(), false);
                 }else{
                     on_notes.insert(n.pitch, n.volume);
                 }
-                result.push_track_event(n.start_time, last_time, n.pitch, n.volume, true);
+                result.push_track_event(channel, n.start_time, last_time, n.pitch, n.volume, true);
             }else{
                 if on_notes.contains_key(&n.pitch) {
-                    result.push_track_event(n.start_time, last_time, n.pitch, n.volume, false);
+                    result.push_track_event(channel, n.start_time, last_time, n.pitch, n.volume, false);
                     on_notes.remove(&n.pitch);
                 }
             }
@@ -126,23 +320,42 @@ impl MidiFile {
     }
 }
 
+/// Writes the tracks to the bytes of a Standard MIDI File. A single track is 
+/// written as Format 0, and multiple tracks are written as Format 1, where the 
+/// tracks play at the same time.
+pub fn write_smf(tracks: Vec<Track>) -> Result<Vec<u8>, Error> {
+    let format = match tracks.len() {
+        0 | 1 => midly::Format::SingleTrack,
+        _ => midly::Format::Parallel
+    };
+    let smf = Smf {
+        header: midly::Header { format, timing: midly::Timing::Metrical(96.into()) },
+        tracks
+    };
+
+    let mut output = Vec::new();
+    smf.write(&mut output).map_err(|e| Error::MidiWriteError(e.to_string()))?;
+
+    Ok(output)
+}
+
 trait TrackEventVecUtils {
     /**
      * A one-line way to add a track event to a vector
      */
-    fn push_track_event(&mut self, start_time: u32, last_time: u32, pitch: u8, velocity: u8, is_on: bool);
+    fn push_track_event(&mut self, channel: u8, start_time: u32, last_time: u32, pitch: u8, velocity: u8, is_on: bool);
 }
 
 impl TrackEventVecUtils for Vec<TrackEvent<'_>> {
     #[inline(always)]
-    fn push_track_event(&mut self, start_time: u32, last_time: u32, pitch: u8, velocity: u8, is_on: bool) {
+    fn push_track_event(&mut self, channel: u8, start_time: u32, last_time: u32, pitch: u8, velocity: u8, is_on: bool) {
         if start_time < last_time {
             console_log!("Start time: {} - last time: {}", start_time, last_time);
         }
         self.push(TrackEvent { 
             delta: (start_time - last_time).into(), 
             kind: TrackEventKind::Midi {
-                channel: 0.into(),
+                channel: channel.into(),
                 message: if is_on {
                     MidiMessage::NoteOn { key: pitch.into(), vel: velocity.into() }
                 }else{
@@ -176,4 +389,84 @@ impl PartialOrd for MidiNote {
         }
         start_comparison
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_have_names_channels_and_programs() {
+        let mut pad = MidiFile::with_settings(TrackSettings::new("pad", 0, Some(89)));
+        pad.add_note_beats(60, 0.0, 4.0, 80);
+        let mut bass = MidiFile::with_settings(TrackSettings::new("bass", 1, Some(33)));
+        bass.add_note_beats(36, 0.0, 4.0, 80);
+        let bytes = write_smf(vec![pad.finalize(), bass.finalize()]).unwrap();
+
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.header.format, midly::Format::Parallel);
+        assert_eq!(smf.tracks.len(), 2);
+        let bass = &smf.tracks[1];
+        assert_eq!(bass[0].kind, TrackEventKind::Meta(MetaMessage::TrackName(b"bass")));
+        assert_eq!(bass[1].kind, TrackEventKind::Midi { channel: 1.into(), message: MidiMessage::ProgramChange { program: 33.into() } });
+        assert!(matches!(bass[2].kind, TrackEventKind::Midi { channel, message: MidiMessage::NoteOn { .. } } if channel == 1));
+    }
+
+    #[test]
+    fn single_tracks_are_unchanged() {
+        let mut track = MidiFile::new();
+        track.add_note_beats(60, 0.0, 1.0, 80);
+        let bytes = write_smf(vec![track.finalize()]).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.header.format, midly::Format::SingleTrack);
+        assert_eq!(smf.tracks[0].len(), 3);
+        assert_eq!(smf.tracks[0][2].kind, TrackEventKind::Meta(MetaMessage::EndOfTrack));
+    }
+
+    #[test]
+    fn humanizing() {
+        let mut track = MidiFile::new();
+        for note in [60, 64, 67] {
+            track.add_note_beats(note, 0.0, 0.5, 80);
+        }
+        track.add_note_beats(72, 0.5, 0.5, 80);
+        track.add_note_beats(72, 1.0, 1.0, 80);
+        let humanizer = Humanizer { timing_jitter: 0, velocity_variation: 0, swing: 66, ..Default::default() };
+        track.humanize(&humanizer, &Meter::default(), &mut MathMagician::share_hash([0; 32]));
+
+        let mut notes: Vec<(u32, bool, u8, u8)> = track.notes.iter().map(|note| (note.start_time, note.note_on, note.pitch, note.volume)).collect();
+        notes.sort_unstable();
+        assert_eq!(notes, [
+            // the top note of the chord is louder, and the first beat is accented
+            (0, true, 60, 90),
+            (0, true, 64, 90),
+            (0, true, 67, 98),
+            // the notes that end on the off-beat end later
+            (63, false, 60, 90),
+            (63, false, 64, 90),
+            (63, false, 67, 98),
+            (63, true, 72, 80),
+            (96, false, 72, 80),
+            (96, true, 72, 74),
+            (192, false, 72, 74),
+        ]);
+
+        // the timing jitter is the same for the same seed
+        let jittered = |seed| {
+            let mut track = MidiFile::new();
+            (0..8).for_each(|i| track.add_note_beats(60 + i, i as f64, 1.0, 80));
+            track.humanize(&Humanizer::default(), &Meter::default(), &mut MathMagician::share_hash([seed; 32]));
+            track.notes.iter().map(|note| (note.start_time, note.volume)).collect::<Vec<(u32, u8)>>()
+        };
+        assert_eq!(jittered(1), jittered(1));
+        assert_ne!(jittered(1), jittered(2));
+        assert!(jittered(1).iter().step_by(2).enumerate().all(|(i, (time, _))| time.abs_diff(96 * i as u32) <= 3));
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert!(TrackSettings::new("drums", 16, None).validate().is_err());
+        assert!(TrackSettings::new("pad", 0, Some(128)).validate().is_err());
+        assert!(TrackSettings::default().validate().is_ok());
+    }
 }
\ No newline at end of file
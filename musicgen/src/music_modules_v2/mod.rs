@@ -1,9 +1,32 @@
 pub mod music;
+pub mod abc;
+pub mod analysis;
+pub mod arpeggio;
+pub mod bass;
 pub mod chord;
 pub mod chord_type;
+pub mod chord_library;
+pub mod chord_symbol;
+pub mod config;
+pub mod drums;
 pub mod utils;
 pub mod midi;
 pub mod error;
+pub mod humanize;
+pub mod key;
+pub mod key_detection;
+pub mod lilypond;
+pub mod markov;
+pub mod melody;
+pub mod meter;
+pub mod musicxml;
 pub mod pruning;
+pub mod reharmonize;
+pub mod rhythm;
+pub mod roman;
+pub mod scale;
+pub mod score;
+pub mod strum;
+pub mod voicing;
 pub use music::Music;
 pub mod patterns;
\ No newline at end of file
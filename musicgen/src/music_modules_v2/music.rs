@@ -6,13 +6,39 @@ use sha2::Sha256;
 
 use crate::{Error, console_log};
 
-use super::chord_type::default_chord_types;
+use super::chord_library::ChordLibrary;
+use super::chord_type::{custom_pruning_chord_types, default_chord_types, major_and_minor_chord_types, original_chord_types};
 
-use super::pruning::prune_chords;
-use super::utils::{get_max_note_length_index, parse_key};
-use super::{chord_type::ChordType, chord::Chord, utils::MathMagician, midi::MidiFile};
+use super::pruning::{get_good_notes_set, prune_chords};
+use super::key::{Key, Mode, PitchClass};
+use super::markov::{MarkovModel, MarkovState};
+use super::arpeggio::Arpeggio;
+use super::bass::BassLine;
+use super::drums::Drums;
+use super::melody::Melody;
+use super::meter::Meter;
+use super::score::{check_time_signature, Score, ScoreNote, ScorePart};
+use super::rhythm::Rhythm;
+use super::strum::Strum;
+use super::patterns::grammar::PatternStep;
+use super::reharmonize::Reharmonizer;
+use super::voicing::VoiceLeading;
+use super::utils::{get_max_note_length_index, parse_key_signature, sets::SetOpsCollection};
+use super::{chord_type::ChordType, chord::Chord, utils::MathMagician, midi::{MidiFile, SongInfo, Track, TrackSettings}};
+use super::config::{ChordPickingMethod, ChordTypeGroup, GenerationConfig, GenerationMode, Scale, TableScheme};
+use super::scale::PitchClassSet;
 
-const NOTE_LENGTHS: [f64; 8] = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0];
+/// The shortest note that the placement algorithms pick, in beats. Every note
+/// length is a multiple of it, except for notes that are cut short to fit in
+/// the chord.
+const NOTE_LENGTH_STEP: f64 = 0.5;
+
+/// How many semitones of movement make a chord `e` times less likely to be 
+/// picked by the voice leading chord picking method.
+const VOICE_LEADING_TEMPERATURE: f64 = 3.0;
+/// Picking the same chord again counts as moving this many semitones, so 
+/// that the progression keeps moving.
+const REPEATED_CHORD_MOVEMENT: u32 = 12;
 
 macro_rules! define_consts {
     ($(($const_name:ident, $value:literal)),*) => {
@@ -40,16 +66,72 @@ define_consts!(
     (B, 11)
 );
 
-use notes::*;
+
+/// A chord that was placed in the chord track, which the other parts, such as
+/// the bass line, follow.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedChord {
+    /// The name of the chord in the key of the song, such as `Eb major`.
+    pub name: String,
+    /// The pitch class of the root, in the key of the song.
+    pub root: i16,
+    /// The pitch classes of the notes, in the key of the song.
+    pub pitch_classes: Vec<i16>,
+    /// The start time in beats.
+    pub start: f64,
+    /// The length in beats.
+    pub length: f64,
+}
+
+#[cfg(test)]
+impl PlacedChord {
+    /// Places chords one after another from the start of the song, where 
+    /// each chord is its root, its pitch classes and its length in beats.
+    pub fn one_after_another<P: AsRef<[i16]>>(chords: &[(i16, P, f64)]) -> Vec<PlacedChord> {
+        let mut start = 0.0;
+        chords
+            .iter()
+            .map(|(root, pitch_classes, length)| {
+                let chord = PlacedChord { name: String::new(), root: *root, pitch_classes: pitch_classes.as_ref().to_vec(), start, length: *length };
+                start += length;
+                chord
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug)]
 pub struct Music {
     math_magician: MathMagician,
+    /// The chord track.
     midi_file: MidiFile,
+    /// The tracks that are written after the chord track.
+    tracks: Vec<MidiFile>,
     pub key: i16,
+    /// The key that `key` was chosen from, used for spelling note names.
+    pub key_signature: Key,
     _chord_types: Vec<ChordType>,
     pub chord_table: Vec<Vec<Chord>>,
-    pub chord_list: Vec<Chord>
+    pub chord_list: Vec<Chord>,
+    /// The length of the bars and chords.
+    meter: Meter,
+    /// The rhythm that replaces the note lengths of the generation mode.
+    rhythm: Option<Rhythm>,
+    arpeggio: Arpeggio,
+    strum: Strum,
+    /// The chords in the order that they were placed.
+    placed_chords: Vec<PlacedChord>,
+    /// The start times of the sections of the pattern, in beats.
+    section_starts: Vec<f64>,
+    markov_model: MarkovModel,
+    /// The chords picked so far by the Markov chain.
+    markov_history: Vec<MarkovState>,
+    voice_leading: Option<VoiceLeading>,
+    /// The notes of the last chord that was placed with voice leading.
+    previous_voicing: Option<Vec<u8>>,
+    /// The notes of the last chord that was picked, for the voice leading 
+    /// chord picking method.
+    picked_voicing: Option<Vec<u8>>
 }
 
 macro_rules! enforce_unique_chord {
@@ -79,96 +161,126 @@ macro_rules! enforce_unique_chord {
 
 /// This macro picks chords to play and places them in the track.
 /// 
-/// There are two ways that chords can be picked:
+/// There are four ways that chords can be picked:
 /// 
 /// * `original` - chords are randomly picked from a 2D array. The rows of the 
-/// array are notes, and the columns are lists of chords that contain the 
-/// row's note. Chords with more notes are somewhat more likely to be picked.
+///   array are notes, and the columns are lists of chords that contain the 
+///   row's note. Chords with more notes are somewhat more likely to be picked.
 /// * `1D` - chords are randomly picked from a 1D array. Each chord has a 
-/// roughly equal probability of getting picked.
+///   roughly equal probability of getting picked.
+/// * `markov` - chords are picked from a 1D array, where the probability of 
+///   each chord depends on the chords that were picked before it.
+/// * `voice leading` - chords are picked from a 1D array, where chords that 
+///   are closer to the previous chord are more likely to be picked.
 /// 
 /// This could have been written prettier by checking the user's input inside 
 /// the for-loops, but then every iteration of the for-loop would have at 
 /// least one extra comparison. And potentially more than one if more modes are 
 /// added later.
 macro_rules! pick_chord_placement_method {
-    (
-        $music_obj:expr, 
-        $user_selected_type:expr, 
-        $num_chords:expr, 
-        $should_use_same_chords:expr, 
-        $chord_picking_method:expr, 
-        $minimum_number_of_unique_chords:expr,
-        $order:expr,
-        $duration:expr,
-        $(($chord_placement_str:expr, $placement_method:ident)),*
-    ) => {
-        let mut previous_n_chords: VecDeque<Chord> = VecDeque::with_capacity($minimum_number_of_unique_chords as usize);
-        if $should_use_same_chords {
-            let mut chords = vec![Chord::default(); $num_chords];
-
-            if $chord_picking_method == "original" {
-                for chord in chords.iter_mut() {
-                    *chord = $music_obj.pick_chord();
-                    enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, *chord);
-                }
-            } else if $chord_picking_method == "1D" {
-                for chord in chords.iter_mut() {
-                    *chord = $music_obj.pick_chord_1d();
-                    enforce_unique_chord!($music_obj, pick_chord_1d, previous_n_chords, *chord);
+    ($music_obj:expr, $config:expr) => {
+        let placement_method = Music::placement_method($config);
+        let num_chords = $config.total_chords();
+        let mut previous_n_chords: VecDeque<Chord> = VecDeque::with_capacity($config.min_number_of_unique_chords as usize);
+        if $config.should_use_same_chords {
+            let mut chords = vec![Chord::default(); num_chords];
+
+            match $config.chord_picking_method {
+                ChordPickingMethod::Original => {
+                    for chord in chords.iter_mut() {
+                        *chord = $music_obj.pick_chord();
+                        enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, *chord);
+                    }
+                },
+                ChordPickingMethod::OneDimensional => {
+                    for chord in chords.iter_mut() {
+                        *chord = $music_obj.pick_chord_1d();
+                        enforce_unique_chord!($music_obj, pick_chord_1d, previous_n_chords, *chord);
+                    }
+                },
+                ChordPickingMethod::Markov => {
+                    for chord in chords.iter_mut() {
+                        *chord = $music_obj.pick_chord_markov();
+                        enforce_unique_chord!($music_obj, pick_chord_markov, previous_n_chords, *chord);
+                        $music_obj.remember_markov_state(chord);
+                    }
+                },
+                ChordPickingMethod::VoiceLeading => {
+                    for chord in chords.iter_mut() {
+                        *chord = $music_obj.pick_chord_voice_leading();
+                        enforce_unique_chord!($music_obj, pick_chord_voice_leading, previous_n_chords, *chord);
+                        $music_obj.remember_voicing(chord);
+                    }
                 }
             }
 
-            match $user_selected_type {
-                $(
-                    $chord_placement_str => {
-                        let order = match $order.len() == 0 {
-                            true => &(1..=$num_chords as u8).collect(),
-                            false => $order
-                        };
-                        for (i, chord_idx) in order.iter().enumerate() {
-                            assert!(*chord_idx > 0);
-                            $music_obj.$placement_method(&chords[*chord_idx as usize - 1], 4, (i as u32 * 4).into(), $duration);
-                        }
-                    },
-                )*
-                _ => { (); }
+            let mut steps = $config.pattern_steps();
+            if steps.is_empty() {
+                steps = (1..=num_chords as u8)
+                    .map(|chord| PatternStep { chord: Some(chord), bars: None, section: None })
+                    .collect();
             }
+            let meter = $music_obj.meter;
+            let mut time = 0.0;
+            let mut section = None;
+            for step in steps {
+                if step.section.is_some() && step.section != section {
+                    $music_obj.midi_file.add_marker_beats(time, step.section.unwrap_or_default());
+                    $music_obj.section_starts.push(time);
+                }
+                section = step.section;
+                // the durations were checked when the pattern was validated
+                $music_obj.meter = step.bars
+                    .and_then(|bars| Meter::new(meter.time_signature(), bars).ok())
+                    .unwrap_or(meter);
+                if let Some(chord_idx) = step.chord {
+                    placement_method($music_obj, &chords[chord_idx as usize - 1], 4, time, $config.duration as f64);
+                    $music_obj.remember_placement(&chords[chord_idx as usize - 1], time);
+                }
+                time += $music_obj.meter.chord_length();
+            }
+            $music_obj.meter = meter;
         } else {
-            match $user_selected_type {
-                $(
-                    $chord_placement_str => {
-                        if $chord_picking_method == "original" {
-                            for i in 0..$num_chords {
-                                let mut chord = $music_obj.pick_chord();
-                                enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, chord);
-                                $music_obj.$placement_method(&chord, 4, (i as u32 * 4).into(), 4);
-                            }
-                        } else if $chord_picking_method == "1D" {
-                            for i in 0..$num_chords {
-                                let mut chord = $music_obj.pick_chord_1d();
-                                enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, chord);
-                                $music_obj.$placement_method(&chord, 4, (i as u32 * 4).into(), 4);
-                            }
-                        }
+            match $config.chord_picking_method {
+                ChordPickingMethod::Original => {
+                    for i in 0..num_chords {
+                        let mut chord = $music_obj.pick_chord();
+                        enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, chord);
+                        placement_method($music_obj, &chord, 4, i as f64 * $music_obj.meter.chord_length(), $music_obj.meter.chord_length());
+                        $music_obj.remember_placement(&chord, i as f64 * $music_obj.meter.chord_length());
+                    }
+                },
+                ChordPickingMethod::OneDimensional => {
+                    for i in 0..num_chords {
+                        let mut chord = $music_obj.pick_chord_1d();
+                        enforce_unique_chord!($music_obj, pick_chord, previous_n_chords, chord);
+                        placement_method($music_obj, &chord, 4, i as f64 * $music_obj.meter.chord_length(), $music_obj.meter.chord_length());
+                        $music_obj.remember_placement(&chord, i as f64 * $music_obj.meter.chord_length());
                     }
-                )*
-                _ => { (); }
+                },
+                ChordPickingMethod::Markov => {
+                    for i in 0..num_chords {
+                        let mut chord = $music_obj.pick_chord_markov();
+                        enforce_unique_chord!($music_obj, pick_chord_markov, previous_n_chords, chord);
+                        $music_obj.remember_markov_state(&chord);
+                        placement_method($music_obj, &chord, 4, i as f64 * $music_obj.meter.chord_length(), $music_obj.meter.chord_length());
+                        $music_obj.remember_placement(&chord, i as f64 * $music_obj.meter.chord_length());
+                    }
+                },
+                ChordPickingMethod::VoiceLeading => {
+                    for i in 0..num_chords {
+                        let mut chord = $music_obj.pick_chord_voice_leading();
+                        enforce_unique_chord!($music_obj, pick_chord_voice_leading, previous_n_chords, chord);
+                        $music_obj.remember_voicing(&chord);
+                        placement_method($music_obj, &chord, 4, i as f64 * $music_obj.meter.chord_length(), $music_obj.meter.chord_length());
+                        $music_obj.remember_placement(&chord, i as f64 * $music_obj.meter.chord_length());
+                    }
+                }
             }
         }
     };
 }
 
-macro_rules! add_chord_types {
-    ($vec:expr, $selected_types:expr, $(($chord_type_str:expr, $chord_type_obj:expr)),*) => {
-        $(
-            if $selected_types.contains(&$chord_type_str.to_string()) {
-                $vec.push($chord_type_obj)
-            }
-        )*
-    };
-}
-
 pub const KEYS: [&str; 12] = [
     "C",
     "C#",
@@ -185,15 +297,14 @@ pub const KEYS: [&str; 12] = [
 ];
 
 impl Music {
+    /// Builds the chord table and chord list described by `config`, using 
+    /// `hash` to seed every random decision.
     pub fn smoke_hash(
         hash: sha2::digest::Output<Sha256>, 
-        chosen_key: &str, 
-        chord_selections: &HashSet<String>, 
-        chord_type_group: &str,
-        scale: &str,
-        is_reproducible: bool,
-        use_all_roots: bool,
+        config: &GenerationConfig,
     ) -> Result<Music, Error> {
+        let chosen_key = config.key.as_str();
+        let chord_selections = &config.chord_selection;
         let mut stash = [0u8; 32];
         stash.copy_from_slice(hash.as_ref());
         let mut math_magician = MathMagician::share_hash(stash);
@@ -201,135 +312,34 @@ impl Music {
         // initialize key with RNG first so that the output remains the same as 
         // when the key is randomly chosen
         let mut key = math_magician.pick_note();
+        let mut key_signature = Key::new(PitchClass::new(key), Mode::Minor);
         if chosen_key.ne("random") {
-            key = parse_key(chosen_key);
+            key_signature = parse_key_signature(chosen_key)?;
+            key = key_signature.offset();
         }
 
-        let mut chord_types = match chord_type_group {
-            "default" => {
-                default_chord_types()
+        let library = &config.chord_library;
+        let mut chord_types = match config.chord_type_group {
+            ChordTypeGroup::Default => {
+                ChordLibrary::from(default_chord_types()).merged(library).chord_types
             },
-            "major and minor" => {
-                let major = ChordType::new("major", &[0, 4, 7], &[DSHARP, GSHARP, ASHARP], None);
-                let minor = ChordType::new("minor", &[0, 3, 7], &[C, D, F, G], None);
-                vec![major, minor]
+            ChordTypeGroup::MajorAndMinor => {
+                ChordLibrary::from(major_and_minor_chord_types()).merged(library).chord_types
             },
-            "original" => {
-                let minor7_og = ChordType::new("minor 7", &[0, 3, 6, 10], &[0, 2, 5, 7], None);
-                let augmented_og = ChordType::new("augmented", &[0, 4, 8], &[10], Some(&[12]));
-                let major7_og = ChordType::new("major 7", &[0, 4, 7, 11], &[3, 8], None);
-                let diminished_og = ChordType::new("diminished", &[0, 3, 6], &[3], None);
-                let major6_og = ChordType::new("major 6", &[0, 4, 7, 9], &[10], None);
-                let minor9 = ChordType::new("minor 9", &[0, 3, 7, 10, 14], &[G], None);
-
-                vec![minor7_og, minor9, augmented_og, major7_og, diminished_og, major6_og]
+            ChordTypeGroup::Original => {
+                ChordLibrary::from(original_chord_types()).merged(library).chord_types
             }
-            "custom" => {
-                let minor7 = ChordType::new("minor 7", &[0, 10, 15, 19], &[C, D, F, FSHARP, ASHARP], None);
-                let major7 = ChordType::new("major 7", &[0, 11, 16, 19], &[DSHARP, GSHARP], None);
-                let diminished = ChordType::new("diminished", &[0, 3, 6], &[DSHARP, FSHARP], None);
-                let augmented = ChordType::new("augmented", &[0,4,8], &[D, FSHARP, ASHARP], Some(&[12]));
-                //let major6 = ChordType::new(&[0, 4, 7, 9], &[3, 10], None);
-                let major6 = ChordType::new("major 6", &[0, 9, 16, 19], &[DSHARP, GSHARP, ASHARP], Some(&[23]));
-
-                let minor6 = ChordType::new("minor 6", &[0, 9, 15, 19], &[C, D, F, G], None);
-                let major9 = ChordType::new("major 9", &[0, 4, 10, 14], &[C, F, G], None);
-                let major7sharp9 = ChordType::new("major 7#9", &[0, 4, 10, 15], &[C, D, G, A], None);
-                let major7flat5sharp9 = ChordType::new("major 7b5#9", &[0, 4, 10, 15, 18], &[C, A], None);
-                let major9flat5 = ChordType::new("major 9b5", &[0, 4, 10, 15, 17], &[C, A], None);
-                let major7flat9 = ChordType::new("major 7b9", &[0, 4, 10, 13], &[C, D], None);
-
-                // extra chord types
-                let major = ChordType::new("major", &[0, 4, 7], &[DSHARP, GSHARP, ASHARP], None);
-                let minor = ChordType::new("minor", &[0, 3, 7], &[C, D, F, G], None);
-
-                let minor9 = ChordType::new("minor 9", &[0, 3, 7, 10, 14], &[G], None);
-
-                let major13 = ChordType::new("major 13", &[0, 5, 10, 21, 26, 31], &[C], Some(&[0, 5]));
-                let dominant9 = ChordType::new("dominant 9", &[0, 4, 9, 14, 18], &[CSHARP], None);
-                
-                let add9 = ChordType::new("add 9", &[0, 4, 7, 14], &[DSHARP, ASHARP], None);
-
-                let mut chord_types: Vec<ChordType> = Vec::with_capacity(chord_selections.len());
-                
-                add_chord_types!(
-                    chord_types, 
-                    chord_selections,
-                    ("minor7", minor7),
-                    ("major7", major7),
-                    ("diminished", diminished),
-                    ("augmented", augmented),
-                    ("major6", major6),
-                    ("minor6", minor6),
-                    ("major9", major9),
-                    ("major7sharp9", major7sharp9),
-                    ("major7flat5sharp9", major7flat5sharp9),
-                    ("major9flat5", major9flat5),
-                    ("major7flat9", major7flat9),
-                    ("major", major),
-                    ("minor", minor),
-                    ("minor9", minor9),
-                    ("major13", major13),
-                    ("dominant9", dominant9),
-                    ("add9", add9)
-                );
-                chord_types
+            ChordTypeGroup::Custom => {
+                ChordLibrary::built_in().merged(library).select(chord_selections)
             },
-            "custom_pruning" => {
-                let minor7 = ChordType::all_roots("minor 7", &[0, 10, 15, 19], None);
-                let major7 = ChordType::all_roots("major 7", &[0, 11, 16, 19], None);
-                let diminished = ChordType::all_roots("diminished", &[0, 3, 6], None);
-                let augmented = ChordType::all_roots("augmented", &[0,4,8], Some(&[12]));
-                //let major6 = ChordType::new(&[0, 4, 7, 9], &[3, 10], None);
-                let major6 = ChordType::all_roots("major 6", &[0, 9, 16, 19], Some(&[23]));
-
-                let minor6 = ChordType::all_roots("minor 6", &[0, 9, 15, 19], None);
-                let major9 = ChordType::all_roots("major 9", &[0, 4, 10, 14], None);
-                let major7sharp9 = ChordType::all_roots("major 7#9", &[0, 4, 10, 15], None);
-                let major7flat5sharp9 = ChordType::all_roots("major 7b5#9", &[0, 4, 10, 15, 18], None);
-                let major9flat5 = ChordType::all_roots("major 9b5", &[0, 4, 10, 15, 17], None);
-                let major7flat9 = ChordType::all_roots("major 7b9", &[0, 4, 10, 13], None);
-
-                // extra chord types
-                let major = ChordType::all_roots("major", &[0, 4, 7], None);
-                let minor = ChordType::all_roots("minor", &[0, 3, 7], None);
-
-                let minor9 = ChordType::all_roots("minor 9", &[0, 3, 7, 10, 14], None);
-
-                let major13 = ChordType::all_roots("major 13", &[0, 5, 10, 21, 26, 31], None);
-                let dominant9 = ChordType::all_roots("dominant 9", &[0, 4, 9, 14, 18], None);
-                
-                let add9 = ChordType::all_roots("add 9", &[0, 4, 7, 14], None);
-
-                let mut chord_types: Vec<ChordType> = Vec::with_capacity(chord_selections.len());
-                
-                add_chord_types!(
-                    chord_types, 
-                    chord_selections,
-                    ("minor7", minor7),
-                    ("major7", major7),
-                    ("diminished", diminished),
-                    ("augmented", augmented),
-                    ("major6", major6),
-                    ("minor6", minor6),
-                    ("major9", major9),
-                    ("major7sharp9", major7sharp9),
-                    ("major7flat5sharp9", major7flat5sharp9),
-                    ("major9flat5", major9flat5),
-                    ("major7flat9", major7flat9),
-                    ("major", major),
-                    ("minor", minor),
-                    ("minor9", minor9),
-                    ("major13", major13),
-                    ("dominant9", dominant9),
-                    ("add9", add9)
-                );
+            ChordTypeGroup::CustomPruning => {
+                let mut chord_types = ChordLibrary::from(custom_pruning_chord_types()).merged(library).select(chord_selections);
+                chord_types.iter_mut().for_each(|chord_type| chord_type.use_all_roots(true));
                 chord_types
-            },
-            _ => vec![ChordType::default()]
+            }
         };
 
-        chord_types.iter_mut().for_each(|chord_type| chord_type.use_all_roots(use_all_roots));
+        chord_types.iter_mut().for_each(|chord_type| chord_type.use_all_roots(config.use_all_roots));
 
         let mut chord_table: Vec<Vec<Chord>> = (0..12).map(|_| Vec::new()).collect();
         let mut chord_list: Vec<Chord> = Vec::new();
@@ -344,15 +354,43 @@ impl Music {
             }
         }
 
-        prune_chords(&mut chord_table, &mut chord_list, scale, is_reproducible);
+        prune_chords(&mut chord_table, &mut chord_list, config.scale_notes(&key_signature)?, config.is_reproducible);
+
+        // training the built-in model is only worth it when it is used
+        let markov_model = match (config.chord_picking_method, &config.markov_model) {
+            (ChordPickingMethod::Markov, Some(model)) => model.clone(),
+            (ChordPickingMethod::Markov, None) => MarkovModel::built_in(),
+            _ => MarkovModel::default()
+        };
+
+        let mut midi_file = MidiFile::with_settings(config.chord_track.clone());
+        midi_file.set_song_info(SongInfo {
+            title: config.title.clone(),
+            bpm: config.bpm,
+            time_signature: config.time_signature,
+            key_signature: Some(config.scale.key_signature(&key_signature)),
+        });
 
         return Ok(Music {
             math_magician,
-            midi_file: MidiFile::new(),
+            midi_file,
+            tracks: Vec::new(),
             key,
+            key_signature,
             chord_table,
             _chord_types: chord_types,
-            chord_list
+            chord_list,
+            meter: config.meter()?,
+            rhythm: config.rhythm.clone(),
+            arpeggio: config.arpeggio,
+            strum: config.strum,
+            placed_chords: Vec::new(),
+            section_starts: Vec::new(),
+            markov_model,
+            markov_history: Vec::new(),
+            voice_leading: config.voice_leading,
+            previous_voicing: None,
+            picked_voicing: None
         })
     }
 
@@ -361,34 +399,96 @@ impl Music {
      */
     pub fn make_music(
         &mut self, 
-        num_chords: usize, 
-        generation_mode: &str, 
-        should_use_same_chords: bool, 
-        chord_picking_method: &str, 
-        minimum_number_of_unique_chords: u32,
-        pattern_to_use: &Vec<u8>,
-        duration: u32,
+        config: &GenerationConfig,
     ) -> Result<Vec<TrackEvent<'_>>, Error> {
+        self.place_chords(config)?;
+        return Ok(self.midi_file.finalize());
+    }
+
+    /// Makes the chord track along with every other track, leaving out 
+    /// tracks without any notes.
+    pub fn make_tracks(&mut self, config: &GenerationConfig) -> Result<Vec<Track<'_>>, Error> {
+        self.place_chords(config)?;
+        let mut tracks = vec![self.midi_file.finalize()];
+        tracks.extend(self.tracks.iter_mut().filter(|track| !track.is_empty()).map(|track| track.finalize()));
+        Ok(tracks)
+    }
+
+    /// Returns the placed chords and the tracks as a score, which can be 
+    /// written as MusicXML. The chord track is always the first part, and 
+    /// tracks without any notes are left out like they are by `make_tracks`. 
+    /// Time signatures whose bars are not a whole number of 16th notes long 
+    /// cannot be written.
+    pub fn score(&self) -> Result<Score, Error> {
+        let song_info = self.midi_file.song_info().cloned().unwrap_or_default();
+        check_time_signature(song_info.time_signature)?;
+        let parts = std::iter::once(&self.midi_file)
+            .chain(self.tracks.iter().filter(|track| !track.is_empty()))
+            .map(|track| ScorePart {
+                settings: track.settings().clone(),
+                notes: track
+                    .notes_in_beats()
+                    .iter()
+                    .map(|(pitch, start, length)| ScoreNote { pitch: *pitch, start: *start, length: *length })
+                    .collect(),
+            })
+            .collect();
+        Ok(Score {
+            title: song_info.title,
+            bpm: song_info.bpm,
+            time_signature: song_info.time_signature,
+            key_signature: song_info.key_signature.unwrap_or((self.key_signature.fifths(), self.key_signature.mode == Mode::Minor)),
+            key: self.key_signature,
+            chords: self.placed_chords.clone(),
+            parts,
+        })
+    }
+
+    /// Adds an empty track that is written after the chord track.
+    pub fn add_track(&mut self, settings: TrackSettings) -> &mut MidiFile {
+        self.tracks.push(MidiFile::with_settings(settings));
+        self.tracks.last_mut().expect("a track was just added")
+    }
+
+    /// Picks the chords and places them in the chord track.
+    fn place_chords(&mut self, config: &GenerationConfig) -> Result<(), Error> {
         console_log!("In make_music");
-        pick_chord_placement_method!(
-            self,
-            generation_mode, 
-            num_chords,
-            should_use_same_chords,
-            chord_picking_method,
-            minimum_number_of_unique_chords,
-            pattern_to_use,
-            duration,
-            ("melody", original_placement_algorithm),
-            ("chords", place_chord_regular),
-            ("melody v2", place_chord_bug_v2),
-            ("melody v3", place_chord_bug_v3),
-            ("intended", place_variable_len_fixed)
-        );
+        config.validate()?;
+        self.meter = config.meter()?;
+        self.rhythm = config.rhythm.clone();
+        self.arpeggio = config.arpeggio;
+        self.strum = config.strum;
+        if self.chord_list.is_empty() || self.chord_table.iter().all(|col| col.is_empty()) {
+            return Err(Error::EmptyChordTable { chord_type_group: config.chord_type_group, scale: config.scale });
+        }
+        match config.reharmonize.as_ref() {
+            Some(reharmonizer) => self.reharmonize(reharmonizer, config),
+            None => {
+                pick_chord_placement_method!(self, config);
+            }
+        }
+
+        if let Some(bass) = config.bass.as_ref() {
+            self.place_bass(bass);
+        }
+        if let Some(melody) = config.melody.as_ref() {
+            // without a scale, the melody uses the natural minor scale of the 
+            // chord table
+            let scale = config.scale_notes(&self.key_signature)?.or(Scale::Natural.pitch_classes()).expect("the natural minor scale has notes");
+            self.place_melody(melody, scale);
+        }
+        if let Some(drums) = config.drums.as_ref() {
+            self.place_drums(drums);
+        }
+        if let Some(humanizer) = config.humanize.as_ref() {
+            for track in std::iter::once(&mut self.midi_file).chain(self.tracks.iter_mut()) {
+                track.humanize(humanizer, &self.meter, &mut self.math_magician);
+            }
+        }
 
         console_log!("At end of make_music()");
 
-        return Ok(self.midi_file.finalize());
+        Ok(())
     }
 
     /// For benchmarking the finalize function
@@ -396,80 +496,85 @@ impl Music {
     #[allow(unused)]
     pub fn make_music_no_finalize(
         &mut self,
-        num_chords: usize,
-        generation_mode: &str,
-        should_use_same_chords: bool,
-        chord_picking_method: &str,
-        minimum_number_of_unique_chords: u32,
-        duration: u32,
+        config: &GenerationConfig,
     ) -> MidiFile {
         console_log!("In make_music_no_finalize");
-        pick_chord_placement_method!(
-            self,
-            generation_mode,
-            num_chords,
-            should_use_same_chords,
-            chord_picking_method,
-            minimum_number_of_unique_chords,
-            &Vec::<u8>::new(),
-            duration,
-            ("melody", original_placement_algorithm),
-            ("chords", place_chord_regular),
-            ("melody v2", place_chord_bug_v2),
-            ("melody v3", place_chord_bug_v3),
-            ("intended", place_variable_len_fixed)
-        );
+        self.meter = config.meter().unwrap_or_default();
+        pick_chord_placement_method!(self, config);
         return self.midi_file.clone();
     }
 
     /// Rotates the chords in the chord table.
     #[allow(unused)]
-    pub fn rotate_chords(&mut self, key: &str) {
-        let k = parse_key(key);
+    pub fn rotate_chords(&mut self, key: &str) -> Result<(), Error> {
+        let key_signature = parse_key_signature(key)?;
+        let k = key_signature.offset();
         self
             .chord_list
             .iter_mut()
-            .for_each(|chord| chord.key = k);
+            .for_each(|chord| {
+                chord.key = k;
+                chord.key_signature = key_signature;
+            });
         self
             .chord_table
             .iter_mut()
             .for_each(|chords| chords
                 .iter_mut()
-                .for_each(|chord| chord.key = k));
+                .for_each(|chord| {
+                    chord.key = k;
+                    chord.key_signature = key_signature;
+                }));
         self.chord_table.rotate_right(k as usize);
+        Ok(())
+    }
 
+    /// Arranges the chord table in the given key according to the table 
+    /// scheme.
+    pub fn arrange_chord_table(&mut self, key: &str, table_scheme: TableScheme) -> Result<(), Error> {
+        match table_scheme {
+            TableScheme::ContainsNote => self.rotate_chords(key),
+            TableScheme::HighestNote => self.rearrange_by_highest_note(key),
+            TableScheme::LowestNote => self.rearrange_by_lowest_note(key),
+        }
     }
 
     /// Rearranges the chord table so that each column's highest note is the 
     /// column's note.
     #[allow(unused)]
-    pub fn rearrange_by_highest_note(&mut self, key: &str) {
-        let k = parse_key(key);
+    pub fn rearrange_by_highest_note(&mut self, key: &str) -> Result<(), Error> {
+        let key_signature = parse_key_signature(key)?;
+        let k = key_signature.offset();
         let mut table: Vec<Vec<Chord>> = vec![Vec::with_capacity(32); 12];
         self
             .chord_list
             .iter_mut()
             .for_each(|chord| {
                 chord.key = k;
+                chord.key_signature = key_signature;
                 table[*chord.get_notes().last().expect("Should contain notes") as usize % 12].push(chord.to_owned());
             });
         self.chord_table = table;
+        Ok(())
     }
 
     /// Rearranges the chord table so that each column's lowest note is the 
     /// column's note.
     #[allow(unused)]
-    pub fn rearrange_by_lowest_note(&mut self, key: &str) {
-        let k = parse_key(key);
+    pub fn rearrange_by_lowest_note(&mut self, key: &str) -> Result<(), Error> {
+        let key_signature = parse_key_signature(key)?;
+        let k = key_signature.offset();
         let mut table: Vec<Vec<Chord>> = vec![Vec::with_capacity(32); 12];
         self
             .chord_list
             .iter_mut()
             .for_each(|chord| {
                 chord.key = k;
+                chord.key_signature = key_signature;
                 table[*chord.get_notes().first().expect("Should contain notes") as usize % 12].push(chord.to_owned());
             });
         self.chord_table = table;
+        Ok(())
     }
 
     /// Picks a random chord from the 2-dimensional list of chords.
@@ -489,6 +594,49 @@ impl Music {
         }
     }
 
+    /// Returns the chords of the chord table that are in every one of 
+    /// `columns`, which are the chords that contain every one of those notes 
+    /// with the `ContainsNote` table scheme.
+    pub fn chords_containing(&self, columns: &[usize]) -> HashSet<Chord> {
+        let Some((first, rest)) = columns.split_first() else {
+            return HashSet::new();
+        };
+        let mut intersected_chords: HashSet<Chord> = HashSet::from_iter(self.chord_table[first % 12].iter().cloned());
+        for column in rest {
+            intersected_chords = intersected_chords
+                .intersection(&HashSet::from_iter(self.chord_table[column % 12].iter().cloned()))
+                .to_set();
+        }
+        intersected_chords
+    }
+
+    /// Picks a chord that contains the notes of the first `strong` columns of 
+    /// the chord table. When there is none, fewer of the strong columns are 
+    /// used, and then each of the other columns on its own. Chords that 
+    /// contain more of `columns` are more likely to be picked.
+    fn pick_chord_containing(&mut self, columns: &[usize], strong: usize) -> Option<Chord> {
+        let strong = strong.clamp(1, columns.len().max(1));
+        let mut candidates: Vec<Chord> = (1..=strong)
+            .rev()
+            .map(|n| &columns[..n.min(columns.len())])
+            .chain((strong..columns.len()).map(|i| &columns[i..=i]))
+            .map(|columns| self.chords_containing(columns))
+            .find(|chords| !chords.is_empty())?
+            .into_iter()
+            .collect();
+        candidates.sort_unstable_by(|a, b| a.get_name().cmp(&b.get_name()).then(a.get_notes().cmp(&b.get_notes())));
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|chord| {
+                let notes: HashSet<usize> = chord.get_notes().iter().map(|note| note.rem_euclid(12) as usize).collect();
+                2f64.powi(columns.iter().filter(|column| notes.contains(column)).count() as i32)
+            })
+            .collect();
+        let chord_index = self.math_magician.weighted_decision(&weights);
+
+        Some(candidates.swap_remove(chord_index))
+    }
+
     /// Picks a random chord from the `chord_list` 1-dimensional list of chords.
     fn pick_chord_1d(&mut self) -> Chord {
         if self.chord_list.len() == 0 {
@@ -498,29 +646,212 @@ impl Music {
         
         self.chord_list[chord_index as usize].to_owned()
     }
+
+    /// Names a chord for the Markov chain, in the key that it is played in.
+    fn markov_state(&self, chord: &Chord) -> MarkovState {
+        let notes: Vec<i16> = chord.get_notes().iter().map(|note| note + self.key).collect();
+        MarkovState::new(PitchClass::new(chord.root as i16 + chord.key + self.key), &notes, &self.key_signature)
+    }
+
+    /// Picks a chord from the `chord_list` 1-dimensional list of chords, 
+    /// based on the chords in `markov_history`.
+    fn pick_chord_markov(&mut self) -> Chord {
+        if self.chord_list.is_empty() {
+            return Chord::default();
+        }
+        let candidates: Vec<MarkovState> = self.chord_list.iter().map(|chord| self.markov_state(chord)).collect();
+        let weights = self.markov_model.weights(&self.markov_history, &candidates);
+        let chord_index = self.math_magician.weighted_decision(&weights);
+
+        self.chord_list[chord_index].to_owned()
+    }
+
+    /// Picks a chord from the `chord_list` 1-dimensional list of chords, 
+    /// where chords that move less from `picked_voicing` are more likely to be 
+    /// picked.
+    fn pick_chord_voice_leading(&mut self) -> Chord {
+        let previous = match &self.picked_voicing {
+            Some(previous) => previous,
+            None => return self.pick_chord_1d()
+        };
+        let settings = self.voice_leading.unwrap_or_default();
+        let weights: Vec<f64> = self.chord_list
+            .iter()
+            .map(|chord| {
+                let notes: Vec<i16> = chord.get_notes().iter().map(|note| note + 48 + self.key).collect();
+                match settings.cost(previous, &notes) {
+                    Some(cost) => {
+                        let movement = match cost.movement {
+                            0 => REPEATED_CHORD_MOVEMENT,
+                            movement => movement
+                        };
+                        (-(movement as f64) / VOICE_LEADING_TEMPERATURE).exp()
+                    },
+                    // the chord does not fit in the range
+                    None => f64::MIN_POSITIVE
+                }
+            })
+            .collect();
+        let chord_index = self.math_magician.weighted_decision(&weights);
+
+        self.chord_list[chord_index].to_owned()
+    }
+
+    /// Remembers a chord that was placed at `start`, for the other parts.
+    fn remember_placement(&mut self, chord: &Chord, start: f64) {
+        // the chords of the chord table are in C minor until they are placed
+        let mut named = chord.clone();
+        named.key += self.key;
+        named.key_signature = self.key_signature;
+        self.placed_chords.push(PlacedChord {
+            name: named.get_name(),
+            root: (chord.root as i16 + chord.key + self.key).rem_euclid(12),
+            pitch_classes: chord.get_notes().iter().map(|note| (note + self.key).rem_euclid(12)).collect(),
+            start,
+            length: self.meter.chord_length(),
+        });
+    }
+
+    /// The placement method of the generation mode, or of the rhythm when 
+    /// there is one, since the rhythm replaces the note lengths of every 
+    /// generation mode.
+    fn placement_method(config: &GenerationConfig) -> fn(&mut Music, &Chord, i16, f64, f64) {
+        if config.rhythm.is_some() {
+            return Music::place_chord_rhythm;
+        }
+        match config.generation_mode {
+            GenerationMode::Melody => Music::original_placement_algorithm,
+            GenerationMode::Chords => Music::place_chord_regular,
+            GenerationMode::MelodyV2 => Music::place_chord_bug_v2,
+            GenerationMode::MelodyV3 => Music::place_chord_bug_v3,
+            GenerationMode::Intended => Music::place_variable_len_fixed,
+            GenerationMode::Arpeggio => Music::place_chord_arpeggio,
+            GenerationMode::Strum => Music::place_chord_strum
+        }
+    }
+
+    /// Picks a chord for every chord length of the melody, out of the chords 
+    /// that contain the notes on its strong beats, and places them with the 
+    /// generation mode. A chord length without any notes keeps the chord 
+    /// before it.
+    fn reharmonize(&mut self, reharmonizer: &Reharmonizer, config: &GenerationConfig) {
+        let placement_method = Music::placement_method(config);
+        let chord_length = self.meter.chord_length();
+        let mut previous: Option<Chord> = None;
+        let mut time = 0.0;
+        while time < reharmonizer.length() - 1e-9 {
+            let (pitch_classes, strong) = reharmonizer.pitch_classes(time, time + chord_length, &self.meter);
+            // the chord table is not in the key of the song
+            let columns: Vec<usize> = pitch_classes.iter().map(|pitch_class| (pitch_class - self.key).rem_euclid(12) as usize).collect();
+            let chord = self.pick_chord_containing(&columns, strong)
+                .or_else(|| previous.clone())
+                .unwrap_or_else(|| self.pick_chord());
+            placement_method(self, &chord, 4, time, chord_length);
+            self.remember_placement(&chord, time);
+            previous = Some(chord);
+            time += chord_length;
+        }
+        if reharmonizer.keep_melody {
+            let track = self.add_track(reharmonizer.melody_track.clone());
+            for note in reharmonizer.melody.iter() {
+                track.add_note_beats(note.pitch, note.start, note.length, note.velocity);
+            }
+        }
+    }
+
+    /// Returns the chords in the order that they were placed.
+    pub fn placed_chords(&self) -> &[PlacedChord] {
+        &self.placed_chords
+    }
+
+    /// Writes a bass line for the placed chords to its own track.
+    fn place_bass(&mut self, bass: &BassLine) {
+        let notes = bass.notes(&self.placed_chords, &self.meter, &mut self.math_magician);
+        let track = self.add_track(bass.track.clone());
+        for note in notes {
+            track.add_note_beats(note.pitch, note.start, note.length, 90);
+        }
+    }
+
+    /// Writes a melody over the placed chords to its own track.
+    fn place_melody(&mut self, melody: &Melody, scale: PitchClassSet) {
+        let (good_notes_set, _) = get_good_notes_set(scale);
+        let scale = good_notes_set.iter().map(|note| (note + self.key).rem_euclid(12)).collect();
+        let notes = melody.notes(&self.placed_chords, &scale, &self.meter, &mut self.math_magician);
+        let track = self.add_track(melody.track.clone());
+        for note in notes {
+            track.add_note_beats(note.pitch, note.start, note.length, 90);
+        }
+    }
+
+    /// Writes drums for the bars of the placed chords to their own track.
+    fn place_drums(&mut self, drums: &Drums) {
+        let notes = drums.notes(&self.placed_chords, &self.section_starts, &self.meter, &mut self.math_magician);
+        let track = self.add_track(drums.track.clone());
+        for note in notes {
+            track.add_note_beats(note.pitch, note.start, note.length, note.velocity);
+        }
+    }
+
+    /// Adds a chord picked by the Markov chain to `markov_history`.
+    fn remember_markov_state(&mut self, chord: &Chord) {
+        let state = self.markov_state(chord);
+        if self.markov_history.len() >= self.markov_model.order {
+            self.markov_history.remove(0);
+        }
+        self.markov_history.push(state);
+    }
+
+    /// Voices a chord picked by voice leading after `picked_voicing`.
+    fn remember_voicing(&mut self, chord: &Chord) {
+        let notes: Vec<i16> = chord.get_notes().iter().map(|note| note + 48 + self.key).collect();
+        self.picked_voicing = Some(self.voice_leading.unwrap_or_default().voice(self.picked_voicing.as_deref(), &notes));
+    }
+
+    /// Returns the MIDI notes to play for a chord. With voice leading, the 
+    /// chord is voiced smoothly after the last chord that was placed. 
+    /// Otherwise, the chord is placed in root position in `octave`.
+    fn voiced_notes(&mut self, chord: &Chord, octave: i16) -> Vec<u8> {
+        let notes: Vec<i16> = chord.get_notes().iter().map(|note| note + 12 * octave + self.key).collect();
+        match self.voice_leading {
+            Some(voice_leading) => {
+                let voicing = voice_leading.voice(self.previous_voicing.as_deref(), &notes);
+                self.previous_voicing = Some(voicing.clone());
+                voicing
+            },
+            None => notes.iter().map(|note| *note as u8).collect()
+        }
+    }
     
+    /// The length of the note at `index` in `note_lengths`, cut short so that 
+    /// a note starting `total_time` beats into the chord does not outlast it.
+    fn note_length(&self, index: u16, total_time: f64) -> f64 {
+        let note_length = NOTE_LENGTH_STEP * (index + 1) as f64;
+        note_length.min(self.meter.chord_length() - total_time)
+    }
+
     /// The original implementation of `def place(self, octave, initTime, isHighPos = True)
     /// 
     /// "melody" mode
-    pub fn original_placement_algorithm(&mut self, chord: &Chord, octave: i16, initial_time: u32, _length: u32) {
-        for note in chord.get_notes().iter() {
-            let note_to_play = (note + 12 * octave + self.key) as u8;
+    pub fn original_placement_algorithm(&mut self, chord: &Chord, octave: i16, initial_time: f64, _length: f64) {
+        for note_to_play in self.voiced_notes(chord, octave) {
             
-            // pick note lengths such that total_time reaches 4.0
+            // pick note lengths such that total_time reaches the length of the chord
             let mut total_time = 0.0;
-            while total_time < 4.0 {
-                // pick a random note length that is between [0.5, 4.0 - total_time]
+            while total_time < self.meter.chord_length() {
+                // pick a random note length that is between [0.5, chord_length - total_time]
                 let max_index: u16;
                 if total_time == 0.0 {
-                    max_index = 4; // this is technically a bug; it's supposed to be 7
+                    // this is technically a bug; it's supposed to be the longest note
+                    max_index = min(4, get_max_note_length_index(0.0, self.meter.chord_length()));
                 }else{
-                    max_index = get_max_note_length_index(total_time);
+                    max_index = get_max_note_length_index(total_time, self.meter.chord_length());
                 }
                 let chosen_index = self.math_magician.big_decision(0, max_index);
-                total_time += NOTE_LENGTHS[chosen_index as usize];
+                total_time += self.note_length(chosen_index, total_time);
                 self.midi_file.add_note_beats(
                     note_to_play, 
-                    initial_time as f64 + total_time,
+                    initial_time + total_time,
                     total_time, 
                     80
                 );
@@ -531,23 +862,22 @@ impl Music {
     /// Fixed version of original placement algorithm.
     /// 
     /// "intended" generation mode
-    fn place_variable_len_fixed(&mut self, chord: &Chord, octave: i16, initial_time: u32, _length: u32) {
-        let notes = chord.get_notes();
+    fn place_variable_len_fixed(&mut self, chord: &Chord, octave: i16, initial_time: f64, _length: f64) {
+        let notes = self.voiced_notes(chord, octave);
 
-        // pick note lengths such that total_time reaches 4.0
+        // pick note lengths such that total_time reaches the length of the chord
         let mut total_time = 0.0;
-        while total_time < 4.0 {
-            // pick a random note length that is between [0.5, 4.0 - total_time]
-            let max_index = get_max_note_length_index(total_time);
+        while total_time < self.meter.chord_length() {
+            // pick a random note length that is between [0.5, chord_length - total_time]
+            let max_index = get_max_note_length_index(total_time, self.meter.chord_length());
             let chosen_index = self.math_magician.big_decision(0, max_index);
-            let note_length = NOTE_LENGTHS[chosen_index as usize];
+            let note_length = self.note_length(chosen_index, total_time);
 
             // apply note length to all notes
-            for note in notes.iter() {
-                let note_to_play = (note + 12 * octave + self.key) as u8;
+            for note_to_play in notes.iter() {
                 self.midi_file.add_note_beats(
-                    note_to_play, 
-                    initial_time as f64 + total_time, 
+                    *note_to_play, 
+                    initial_time + total_time, 
                     note_length, 
                     80
                 );
@@ -560,23 +890,26 @@ impl Music {
     /// Places chords in a regular manner.
     /// 
     /// "chords" generation mode
-    pub fn place_chord_regular(&mut self, chord: &Chord, octave: i16, initial_time: u32, length: u32) {
-        let notes = chord.get_notes();
-        let note_length = length.max(4).max(1) as f64;
-        let mut time = initial_time as f64;
-        for _l in 0..(4u32 / length) {
-            let sub = 4.0 - note_length;
-            let len = if sub < note_length && sub != 0.0 { sub } else { note_length };
-            for note in notes.iter() {
-                let note_to_play = (note + 12 * octave + self.key) as u8;
-
-                self.midi_file.add_note_beats(note_to_play, time, len, 80);
+    pub fn place_chord_regular(&mut self, chord: &Chord, octave: i16, initial_time: f64, length: f64) {
+        let notes = self.voiced_notes(chord, octave);
+        // the chord is played every `length` beats, and the last one is cut 
+        // short so that it does not outlast the chord
+        let end_time = initial_time + self.meter.chord_length();
+        let mut time = initial_time;
+        while time < end_time {
+            let len = length.min(end_time - time);
+            for note_to_play in notes.iter() {
+                self.midi_file.add_note_beats(*note_to_play, time, len, 80);
             }
             let optional_notes = chord.get_optional_notes();
             // optionally play optional notes
             for note in optional_notes.iter() {
                 if self.math_magician.big_decision(0, 100) > 69 {
-                    let note_to_play = (note + 12 * octave + self.key as i16) as u8;
+                    let note_to_play = match self.voice_leading {
+                        // the lowest position above the bass of the voicing
+                        Some(_) => notes[0] + (note + self.key - notes[0] as i16).rem_euclid(12) as u8,
+                        None => (note + 12 * octave + self.key as i16) as u8
+                    };
                     self.midi_file.add_note_beats(note_to_play, time, len, 80);
                 }
             }
@@ -584,6 +917,46 @@ impl Music {
         }
     }
 
+    /// Plays every note of the chord on each hit of the rhythm, where 
+    /// accented hits are louder.
+    /// 
+    /// Used by every generation mode when there is a rhythm
+    fn place_chord_rhythm(&mut self, chord: &Chord, octave: i16, initial_time: f64, _length: f64) {
+        let notes = self.voiced_notes(chord, octave);
+        let hits = match self.rhythm.as_ref() {
+            Some(rhythm) => rhythm.hits_in(self.meter.chord_length()),
+            None => return
+        };
+        for hit in hits {
+            let velocity = if hit.accent { 100 } else { 80 };
+            for note_to_play in notes.iter() {
+                self.midi_file.add_note_beats(*note_to_play, initial_time + hit.start, hit.length, velocity);
+            }
+        }
+    }
+
+    /// Plays the notes of the chord one at a time.
+    /// 
+    /// "arpeggio" generation mode
+    fn place_chord_arpeggio(&mut self, chord: &Chord, octave: i16, initial_time: f64, _length: f64) {
+        let notes = self.voiced_notes(chord, octave);
+        let arpeggio = self.arpeggio;
+        for (start, length, note_to_play) in arpeggio.notes_in(&notes, self.meter.chord_length(), &mut self.math_magician) {
+            self.midi_file.add_note_beats(note_to_play, initial_time + start, length, 80);
+        }
+    }
+
+    /// Strums the chord on every beat, or rolls it once.
+    /// 
+    /// "strum" generation mode
+    fn place_chord_strum(&mut self, chord: &Chord, octave: i16, initial_time: f64, _length: f64) {
+        let notes = self.voiced_notes(chord, octave);
+        let strum = self.strum;
+        for note in strum.notes_in(&notes, &self.meter, &mut self.math_magician) {
+            self.midi_file.add_note_beats(note.pitch, initial_time + note.start, note.length, note.velocity);
+        }
+    }
+
     /// Another buggy chord placement algorithm.
     /// 
     /// "melody v2"
@@ -591,10 +964,15 @@ impl Music {
         &mut self, 
         chord: &Chord,
         _octave: i16, 
-        initial_time: u32,
-        _length: u32,
+        initial_time: f64,
+        _length: f64,
     ) {
         //let octave = self.math_magician.pick_note() % 2 + 4;
+        if self.voice_leading.is_some() {
+            // the voicing replaces the random octaves
+            let notes = self.voiced_notes(chord, 4);
+            return self.place_notes_with_random_lengths(&notes, initial_time);
+        }
         let mut note_index = 0;
         let notes = chord.get_notes();
         let octave: i16;
@@ -614,18 +992,18 @@ impl Music {
             let mut total_time = 0.0;
 
             loop {
-                if total_time >= 4.0 {
+                if total_time >= self.meter.chord_length() {
                     break;
                 }
 
-                let max_index = get_max_note_length_index(total_time);
+                let max_index = get_max_note_length_index(total_time, self.meter.chord_length());
 
                 let chosen_index = self.math_magician.big_decision(0, max_index as u16);
-                let duration = NOTE_LENGTHS[chosen_index as usize];
+                let duration = self.note_length(chosen_index, total_time);
 
                 self.midi_file.add_note_beats(
                     note_to_play + self.key as u8, 
-                    initial_time as f64 + total_time, 
+                    initial_time + total_time, 
                     duration,
                     self.math_magician.big_decision(70, 90) as u8
                 );
@@ -641,27 +1019,34 @@ impl Music {
         &mut self, 
         chord: &Chord, 
         octave: i16,
-        initial_time: u32,
-        _length: u32
+        initial_time: f64,
+        _length: f64
     ) { 
         //let notes = self.get_modified_notes(chord);
         
-        for note in chord.get_notes() {
+        let notes = self.voiced_notes(chord, octave);
+        self.place_notes_with_random_lengths(&notes, initial_time);
+    }
+
+    /// Plays each note with random lengths and velocities until the bar is 
+    /// full.
+    fn place_notes_with_random_lengths(&mut self, notes: &[u8], initial_time: f64) {
+        for note in notes.iter() {
             let mut total_time = 0.0;
 
             loop {
-                if total_time >= 4.0 {
+                if total_time >= self.meter.chord_length() {
                     break;
                 }
 
-                let max_index = get_max_note_length_index(total_time);
+                let max_index = get_max_note_length_index(total_time, self.meter.chord_length());
 
                 let chosen_index = self.math_magician.big_decision(0, max_index as u16);
-                let duration = NOTE_LENGTHS[chosen_index as usize];
+                let duration = self.note_length(chosen_index, total_time);
 
                 self.midi_file.add_note_beats(
-                    note as u8 + self.key as u8 + (octave * 12) as u8, 
-                    initial_time as f64 + total_time, 
+                    *note, 
+                    initial_time + total_time, 
                     duration,
                     self.math_magician.big_decision(70, 90) as u8
                 );
@@ -674,10 +1059,37 @@ impl Music {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::music_modules_v2::analysis::ImportedNote;
+    use crate::music_modules_v2::config::{ArpeggioRate, BassStyle, DrumGenre, PhraseShape};
+    use crate::music_modules_v2::utils::beats;
+    use crate::music_modules_v2::voicing::cost as voicing_cost;
+
+    /// The time, key and velocity of every note that starts in `track`.
+    fn note_ons(track: &[TrackEvent]) -> Vec<(u32, u8, u8)> {
+        let mut time = 0;
+        let mut note_ons = Vec::new();
+        for event in track.iter() {
+            time += event.delta.as_int();
+            if let midly::TrackEventKind::Midi { message: midly::MidiMessage::NoteOn { key, vel }, .. } = event.kind {
+                if vel > 0 {
+                    note_ons.push((time, key.as_int(), vel.as_int()));
+                }
+            }
+        }
+        note_ons
+    }
+
+    /// The time of the last event of `track`.
+    fn track_length(track: &[TrackEvent]) -> u32 {
+        track.iter().map(|event| event.delta.as_int()).sum()
+    }
 
     macro_rules! init_music {
         ($chosen_key:expr) => {
-            Music::smoke_hash(Default::default(), $chosen_key, &HashSet::new(), "default", "disabled", true, false).unwrap()
+            Music::smoke_hash(
+                Default::default(),
+                &GenerationConfig::builder().key($chosen_key).is_reproducible(true).build()
+            ).unwrap()
         };
     }
 
@@ -686,10 +1098,442 @@ mod tests {
         let m = init_music!("Cmin");
         assert_eq!(m.key, 0);
         let m = init_music!("Cmaj");
-        assert_eq!(m.key, 3);
+        assert_eq!(m.key, 9);
         let m = init_music!("Dmin");
         assert_eq!(m.key, 2);
         let m = init_music!("Dmaj");
-        assert_eq!(m.key, 5);
+        assert_eq!(m.key, 11);
+        let m = init_music!("Bb minor");
+        assert_eq!(m.key, 10);
+        assert_eq!(m.key_signature.to_string(), "Bb minor");
+    }
+
+    #[test]
+    fn empty_chord_table() {
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .chord_type_group(ChordTypeGroup::Custom)
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        assert!(matches!(m.make_music(&config), Err(Error::EmptyChordTable { .. })));
+    }
+
+    #[test]
+    fn chord_library() {
+        let library = ChordLibrary::parse(r#"
+            [[chord_types]]
+            name = "so what"
+            intervals = [0, 5, 10, 15, 19]
+            roots = [2]
+            tags = ["quartal"]
+        "#).unwrap();
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .chord_type_group(ChordTypeGroup::Custom)
+            .chord_selection(["quartal".to_string(), "minor".to_string()].into())
+            .chord_library(library.clone())
+            .build();
+        let m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let names: Vec<String> = m.chord_list.iter().map(|c| c.chord_type.name.clone()).collect();
+        assert!(names.contains(&"so what".to_string()));
+        assert!(names.contains(&"minor".to_string()));
+        assert!(!names.contains(&"major".to_string()));
+
+        // every library chord type is added to the other groups
+        let config = GenerationConfig { chord_type_group: ChordTypeGroup::Default, ..config };
+        let m = Music::smoke_hash(Default::default(), &config).unwrap();
+        assert!(m.chord_list.iter().any(|c| c.chord_type.name == "so what"));
+        assert_eq!(m.chord_list.len(), default_chord_types().iter().map(|ct| ct.roots.len()).sum::<usize>() + 1);
+    }
+
+    #[test]
+    fn markov_picking_follows_the_model() {
+        let mut model = MarkovModel::new(1);
+        model.train_roman_numerals("I - V - I - V - I - V").unwrap();
+        let config = GenerationConfig::builder()
+            .key("Gmaj")
+            .chord_type_group(ChordTypeGroup::MajorAndMinor)
+            .use_all_roots(true)
+            .markov_model(model)
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let mut numerals = Vec::new();
+        for _ in 0..50 {
+            let chord = m.pick_chord_markov();
+            m.remember_markov_state(&chord);
+            numerals.push(m.markov_state(&chord).numeral);
+        }
+        let follows_the_model = numerals
+            .windows(2)
+            .filter(|pair| matches!((pair[0].as_str(), pair[1].as_str()), ("I", "V") | ("V", "I")))
+            .count();
+        assert!(follows_the_model > 30, "{:?}", numerals);
+        // other chords can still be picked
+        assert!(numerals.iter().any(|numeral| numeral != "I" && numeral != "V"), "{:?}", numerals);
+    }
+
+    #[test]
+    fn extra_tracks() {
+        let config = GenerationConfig::builder().key("Cmin").num_chords(2).build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        m.add_track(TrackSettings::new("empty", 1, None));
+        m.add_track(TrackSettings::new("bass", 2, Some(33))).add_note_beats(36, 0.0, 8.0, 80);
+        let tracks = m.make_tracks(&config).unwrap();
+        // empty tracks are left out
+        assert_eq!(tracks.len(), 2);
+        // the name, program, note on, note off and end of track
+        assert_eq!(tracks[1].len(), 5);
+    }
+
+    #[test]
+    fn placement_follows_the_meter() {
+        for (time_signature, bars_per_chord) in [("3/4", 1.0), ("6/8", 1.0), ("5/4", 1.0), ("7/8", 1.0), ("4/4", 2.0), ("3/4", 0.5), ("7/8", 0.5)] {
+            for generation_mode in [GenerationMode::Chords, GenerationMode::Intended, GenerationMode::MelodyV2, GenerationMode::MelodyV3] {
+                let config = GenerationConfig::builder()
+                    .key("Gmaj")
+                    .generation_mode(generation_mode)
+                    .should_use_same_chords(false)
+                    .num_chords(6)
+                    .time_signature(time_signature.parse().unwrap())
+                    .bars_per_chord(bars_per_chord)
+                    .build();
+                let chord_length = config.meter().unwrap().chord_length();
+                let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+                let track = m.make_music(&config).unwrap();
+
+                let note_ons: Vec<u32> = note_ons(&track).iter().map(|(time, ..)| *time).collect();
+                let context = format!("{} with {} bars per chord in {} mode", time_signature, bars_per_chord, generation_mode);
+                // every chord starts on time, and the last one ends with the track
+                for i in 0..6 {
+                    assert!(note_ons.contains(&beats(i as f64 * chord_length)), "{}", context);
+                }
+                assert_eq!(track_length(&track), beats(6.0 * chord_length), "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn patterns_with_durations_rests_and_sections() {
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .generation_mode(GenerationMode::Chords)
+            .pattern_str("[intro] 1:2-_-2:0.5 [outro] (1)x2")
+            .unwrap()
+            .build();
+        assert_eq!(config.total_chords(), config.num_chords);
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let track = m.make_music(&config).unwrap();
+
+        let mut note_ons: Vec<u32> = note_ons(&track).iter().map(|(time, ..)| *time).collect();
+        note_ons.dedup();
+        let markers: Vec<(u32, &[u8])> = track
+            .iter()
+            .scan(0, |time, event| {
+                *time += event.delta.as_int();
+                Some((*time, event.kind))
+            })
+            .filter_map(|(time, kind)| match kind {
+                midly::TrackEventKind::Meta(midly::MetaMessage::Marker(text)) => Some((time, text)),
+                _ => None
+            })
+            .collect();
+        // two bars, where the chord is played every `duration` beats, a rest, 
+        // half a bar, and then two bars
+        assert_eq!(note_ons, [0, beats(4.0), beats(12.0), beats(14.0), beats(18.0)]);
+        assert_eq!(markers, [(0, "intro".as_bytes()), (beats(14.0), "outro".as_bytes())]);
+        assert_eq!(track_length(&track), beats(22.0));
+    }
+
+    #[test]
+    fn durations_shorter_than_a_chord() {
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .generation_mode(GenerationMode::Chords)
+            .num_chords(2)
+            .should_use_same_chords(true)
+            .duration(3)
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let track = m.make_music(&config).unwrap();
+
+        let mut note_ons: Vec<u32> = note_ons(&track).iter().map(|(time, ..)| *time).collect();
+        note_ons.dedup();
+        // each chord is played every 3 beats, and the second hit is cut short 
+        // so that it ends with the chord
+        assert_eq!(note_ons, [0, beats(3.0), beats(4.0), beats(7.0)]);
+        assert_eq!(track_length(&track), beats(8.0));
+    }
+
+    #[test]
+    fn rhythms_replace_the_note_lengths() {
+        for generation_mode in GenerationMode::ALL {
+            let config = GenerationConfig::builder()
+                .key("Fmin")
+                .generation_mode(*generation_mode)
+                .num_chords(2)
+                .time_signature("3/4".parse().unwrap())
+                .rhythm_str("x-.X ..x.")
+                .unwrap()
+                .build();
+            let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+            let track = m.make_music(&config).unwrap();
+
+            let mut note_ons: Vec<(u32, u8)> = note_ons(&track).iter().map(|(time, _, velocity)| (*time, *velocity)).collect();
+            note_ons.dedup();
+            // the rhythm is 2 beats long, so it starts again halfway through 
+            // each chord and is cut short
+            let expected: Vec<(u32, u8)> = [(0.0, 80), (0.75, 100), (1.5, 80), (2.0, 80), (2.75, 100)]
+                .iter()
+                .chain(&[(3.0, 80), (3.75, 100), (4.5, 80), (5.0, 80), (5.75, 100)])
+                .map(|(time, velocity)| (beats(*time), *velocity))
+                .collect();
+            assert_eq!(note_ons, expected, "{}", generation_mode);
+        }
+    }
+
+    #[test]
+    fn arpeggios() {
+        let config = GenerationConfig::builder()
+            .key("Dmin")
+            .generation_mode(GenerationMode::Arpeggio)
+            .arpeggio(Arpeggio { rate: ArpeggioRate::Eighth, gate: 1.0, ..Default::default() })
+            .time_signature("3/4".parse().unwrap())
+            .num_chords(2)
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let track = m.make_music(&config).unwrap();
+
+        let note_ons: Vec<(u32, u8)> = note_ons(&track).iter().map(|(time, key, _)| (*time, *key)).collect();
+        assert_eq!(note_ons.len(), 12);
+        for (i, (time, _)) in note_ons.iter().enumerate() {
+            assert_eq!(*time, beats(i as f64 * 0.5));
+        }
+        // each chord goes up, one note at a time
+        for chord in note_ons.chunks(6) {
+            let cycle_length = chord.iter().skip(1).position(|(_, key)| *key == chord[0].1).map_or(6, |i| i + 1);
+            assert!(chord[..cycle_length].windows(2).all(|pair| pair[0].1 < pair[1].1), "{:?}", chord);
+        }
+        assert!(matches!(
+            GenerationConfig { arpeggio: Arpeggio { octaves: 9, ..Default::default() }, ..config }.validate(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn strums() {
+        let config = GenerationConfig::builder()
+            .key("Amin")
+            .generation_mode(GenerationMode::Strum)
+            .num_chords(4)
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let track = m.make_music(&config).unwrap();
+
+        let note_ons: Vec<u32> = note_ons(&track).iter().map(|(time, ..)| *time).collect();
+        // a strum on every beat, where the notes do not start together
+        for beat in 0..16 {
+            assert!(note_ons.contains(&beats(beat as f64)));
+            let strum: Vec<u32> = note_ons.iter().copied().filter(|time| *time / beats(1.0) == beat).collect();
+            assert!(strum.len() >= 3 && strum.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", strum);
+        }
+        assert_eq!(track_length(&track), beats(16.0));
+    }
+
+    #[test]
+    fn bass_lines_follow_the_chords() {
+        let config = GenerationConfig::builder()
+            .key("Ebmaj")
+            .generation_mode(GenerationMode::Intended)
+            .num_chords(8)
+            .bass(BassLine::new(BassStyle::Walking))
+            .build();
+        let bass_notes = |config: &GenerationConfig| {
+            let mut m = Music::smoke_hash(Default::default(), config).unwrap();
+            let tracks = m.make_tracks(config).unwrap();
+            assert_eq!(tracks.len(), 2);
+            let notes: Vec<(u32, u8)> = note_ons(&tracks[1]).iter().map(|(time, key, _)| (*time, *key)).collect();
+            (m.placed_chords().to_vec(), notes)
+        };
+        let (placed_chords, notes) = bass_notes(&config);
+        assert_eq!(placed_chords.len(), 8);
+        assert_eq!(notes.len(), 32);
+        for (chord, beat) in placed_chords.iter().zip(notes.chunks(4)) {
+            // every bar starts on the root
+            assert_eq!(beat[0].0, beats(chord.start));
+            assert_eq!(beat[0].1 as i16 % 12, chord.root);
+        }
+        assert!(notes.iter().all(|(_, key)| (27..=52).contains(key)));
+        // the bass line is seeded by the same hash
+        assert_eq!(bass_notes(&config).1, notes);
+    }
+
+    #[test]
+    fn scores() {
+        let config = GenerationConfig::builder()
+            .key("Ebmaj")
+            .generation_mode(GenerationMode::Chords)
+            .num_chords(4)
+            .bass(BassLine::new(BassStyle::Root))
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        m.make_tracks(&config).unwrap();
+        let score = m.score().unwrap();
+        assert_eq!(score.parts.len(), 2);
+        assert_eq!(score.chords.len(), 4);
+        // the roots are spelled in E flat major
+        assert!(score.chords.iter().all(|chord| !chord.name.split(' ').next().unwrap().contains('#')), "{:?}", score.chords);
+        let xml = score.to_musicxml();
+        assert!(xml.contains("<key><fifths>-3</fifths><mode>major</mode></key>"));
+        assert!(xml.contains("<part-name>Bass</part-name>"));
+        assert_eq!(xml.matches("<harmony>").count(), 4);
+        assert_eq!(xml.matches("<measure number=").count(), 8);
+    }
+
+    #[test]
+    fn reharmonizing_a_melody() {
+        // G B D B | C E C A | F# A D C | G
+        let melody: Vec<ImportedNote> = [67, 71, 74, 71, 72, 76, 72, 69, 78, 69, 74, 72]
+            .iter()
+            .enumerate()
+            .map(|(i, pitch)| ImportedNote { start: i as f64, length: 1.0, pitch: *pitch, velocity: 70, channel: 0 })
+            .chain(std::iter::once(ImportedNote { start: 12.0, length: 4.0, pitch: 67, velocity: 70, channel: 0 }))
+            .collect();
+        let config = GenerationConfig::builder()
+            .key("Gmaj")
+            .chord_type_group(ChordTypeGroup::MajorAndMinor)
+            .scale(Scale::Natural)
+            .generation_mode(GenerationMode::Chords)
+            .reharmonize(Reharmonizer { melody, ..Default::default() })
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let tracks = m.make_tracks(&config).unwrap();
+        // the chords and the melody
+        assert_eq!(tracks.len(), 2);
+        let placed = m.placed_chords();
+        assert_eq!(placed.iter().map(|chord| chord.start).collect::<Vec<f64>>(), [0.0, 4.0, 8.0, 12.0]);
+        for (chord, strong) in placed.iter().zip([[7, 2], [0, 4], [6, 2], [7, 7]]) {
+            assert!(strong.iter().all(|pitch_class| chord.pitch_classes.contains(pitch_class)), "{:?}", chord);
+        }
+
+        assert!(GenerationConfig::builder().reharmonize(Reharmonizer::default()).build().validate().is_err());
+    }
+
+    #[test]
+    fn melodies_follow_the_scale() {
+        let config = GenerationConfig::builder()
+            .key("Dmaj")
+            .scale(Scale::Natural)
+            .generation_mode(GenerationMode::Chords)
+            .num_chords(8)
+            .melody(Melody::new(PhraseShape::CallAndResponse))
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let tracks = m.make_tracks(&config).unwrap();
+        assert_eq!(tracks.len(), 2);
+        let keys: Vec<u8> = note_ons(&tracks[1]).iter().map(|(_, key, _)| *key).collect();
+        assert!(keys.len() >= 16, "{:?}", keys);
+        // B natural minor, which is the relative minor of D major
+        assert!(keys.iter().all(|key| [11, 1, 2, 4, 6, 7, 9].contains(&(key % 12))), "{:?}", keys);
+        assert!(keys.iter().all(|key| (60..=81).contains(key)));
+    }
+
+    #[test]
+    fn custom_pruning_keeps_every_note() {
+        let config = GenerationConfig::builder()
+            .chord_type_group(ChordTypeGroup::CustomPruning)
+            .chord_selection(["major13".to_string(), "augmented".to_string()].into_iter().collect())
+            .scale(Scale::AllNotes)
+            .build();
+        let m = Music::smoke_hash(Default::default(), &config).unwrap();
+        // major 13 on every root, and augmented on every root with and without
+        // its optional octave
+        let mut note_counts: Vec<usize> = m.chord_list.iter().map(|chord| chord.get_notes().len()).collect();
+        note_counts.sort_unstable();
+        assert_eq!(note_counts, [vec![3; 12], vec![4; 12], vec![6; 12]].concat());
+    }
+
+    #[test]
+    fn major_modes_in_major_keys() {
+        let config = GenerationConfig::builder()
+            .key("Cmaj")
+            .scale(Scale::Ionian)
+            .generation_mode(GenerationMode::Chords)
+            .num_chords(8)
+            .melody(Melody::new(PhraseShape::CallAndResponse))
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let tracks = m.make_tracks(&config).unwrap();
+        let c_major = [0, 2, 4, 5, 7, 9, 11];
+        let keys: Vec<u8> = note_ons(&tracks[1]).iter().map(|(_, key, _)| *key).collect();
+        assert!(!keys.is_empty());
+        assert!(keys.iter().all(|key| c_major.contains(&((key % 12) as i16))), "{:?}", keys);
+        for chord in m.placed_chords() {
+            assert!(chord.pitch_classes.iter().all(|pitch_class| c_major.contains(pitch_class)), "{:?}", chord);
+        }
+        assert_eq!(m.score().unwrap().key_signature, (0, false));
+    }
+
+    #[test]
+    fn drums_on_the_percussion_channel() {
+        let config = GenerationConfig::builder()
+            .generation_mode(GenerationMode::Chords)
+            .pattern_str("[verse] 1-2-3-4 [chorus] 5-6").unwrap()
+            .drums(Drums { fill_every: 0, ..Drums::new(DrumGenre::House) })
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let tracks = m.make_tracks(&config).unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert!(tracks[1].iter().all(|event| match event.kind {
+            midly::TrackEventKind::Midi { channel, .. } => channel.as_int() == 9,
+            _ => true
+        }));
+        let hits = note_ons(&tracks[1]);
+        // a crash at the start of the chorus
+        assert_eq!(hits.iter().filter(|(_, key, _)| *key == 49).map(|(time, ..)| *time).collect::<Vec<u32>>(), [beats(16.0)]);
+        assert!(hits.iter().all(|(time, _, _)| *time < beats(24.0)));
+    }
+
+    #[test]
+    fn voice_leading_placement() {
+        // a key that is not in the chord table, so the chords are transposed
+        let config = GenerationConfig::builder()
+            .key("F#min")
+            .voice_leading(VoiceLeading { lowest_note: 50, highest_note: 80, ..Default::default() })
+            .build();
+        let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+        let mut previous: Option<Vec<u8>> = None;
+        for _ in 0..20 {
+            let chord = m.pick_chord_1d();
+            let voicing = m.voiced_notes(&chord, 4);
+            assert!(voicing.iter().all(|note| (50..=80).contains(note)), "{:?}", voicing);
+            if let Some(previous) = previous {
+                let notes: Vec<i16> = chord.get_notes().iter().map(|note| note + m.key).collect();
+                let smoothest = config.voice_leading.unwrap().cost(&previous, &notes).unwrap();
+                assert_eq!(voicing_cost(&previous, &voicing), smoothest);
+            }
+            previous = Some(voicing);
+        }
+    }
+
+    #[test]
+    fn voice_leading_picking_prefers_smooth_progressions() {
+        let movement = |method: ChordPickingMethod| {
+            let config = GenerationConfig::builder().key("Amin").chord_picking_method(method).build();
+            let mut m = Music::smoke_hash(Default::default(), &config).unwrap();
+            let mut total = 0;
+            for _ in 0..40 {
+                let chord = match method {
+                    ChordPickingMethod::VoiceLeading => m.pick_chord_voice_leading(),
+                    _ => m.pick_chord_1d(),
+                };
+                let previous = m.picked_voicing.clone();
+                m.remember_voicing(&chord);
+                if let Some(previous) = previous {
+                    total += voicing_cost(&previous, m.picked_voicing.as_ref().unwrap()).movement;
+                }
+            }
+            total
+        };
+        assert!(movement(ChordPickingMethod::VoiceLeading) < movement(ChordPickingMethod::OneDimensional));
     }
 }
\ No newline at end of file
@@ -0,0 +1,246 @@
+//! MusicXML export.
+//!
+//! Writes a `Score` as a document that notation software can open, with a
+//! part for each track and the chords as chord symbols above the first part.
+
+use super::{key::NoteName, music::PlacedChord, score::{Accidentals, Clef, Score, ScoreEvent, DIVISIONS}};
+
+/// Escapes the characters of `text` that are special in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// The MusicXML kind of a chord symbol, given the intervals of its notes
+/// above the root.
+fn harmony_kind(intervals: &[i16]) -> &'static str {
+    match intervals {
+        [0, 4, 7] => "major",
+        [0, 3, 7] => "minor",
+        [0, 3, 6] => "diminished",
+        [0, 4, 8] => "augmented",
+        [0, 4, 7, 10] => "dominant",
+        [0, 4, 7, 11] => "major-seventh",
+        [0, 3, 7, 10] => "minor-seventh",
+        [0, 3, 6, 9] => "diminished-seventh",
+        [0, 3, 6, 10] => "half-diminished",
+        [0, 4, 8, 10] => "augmented-seventh",
+        [0, 3, 7, 11] => "major-minor",
+        [0, 4, 7, 9] => "major-sixth",
+        [0, 3, 7, 9] => "minor-sixth",
+        [0, 2, 7] => "suspended-second",
+        [0, 5, 7] => "suspended-fourth",
+        [0, 7] => "power",
+        _ => "other"
+    }
+}
+
+impl Score {
+    /// Writes the score as an uncompressed MusicXML document.
+    pub fn to_musicxml(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+        out.push_str("<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n");
+        out.push_str("<score-partwise version=\"4.0\">\n");
+        if let Some(title) = self.title.as_ref() {
+            out.push_str(&format!("  <work>\n    <work-title>{}</work-title>\n  </work>\n", escape(title)));
+        }
+        out.push_str("  <part-list>\n");
+        for (index, part) in self.parts.iter().enumerate() {
+            let (id, name) = (index + 1, escape(&self.part_name(index)));
+            out.push_str(&format!("    <score-part id=\"P{}\">\n", id));
+            out.push_str(&format!("      <part-name>{}</part-name>\n", name));
+            out.push_str(&format!("      <score-instrument id=\"P{}-I1\">\n        <instrument-name>{}</instrument-name>\n      </score-instrument>\n", id, name));
+            out.push_str(&format!("      <midi-instrument id=\"P{}-I1\">\n        <midi-channel>{}</midi-channel>\n", id, part.settings.channel + 1));
+            if let Some(program) = part.settings.program {
+                out.push_str(&format!("        <midi-program>{}</midi-program>\n", program as u16 + 1));
+            }
+            out.push_str("      </midi-instrument>\n    </score-part>\n");
+        }
+        out.push_str("  </part-list>\n");
+        for index in 0..self.parts.len() {
+            self.write_part(&mut out, index);
+        }
+        out.push_str("</score-partwise>\n");
+        out
+    }
+
+    /// Writes the measures of the part at `index`.
+    fn write_part(&self, out: &mut String, index: usize) {
+        let part = &self.parts[index];
+        let clef = match part.clef() {
+            Clef::Percussion => "<sign>percussion</sign>",
+            Clef::Bass => "<sign>F</sign><line>4</line>",
+            Clef::Treble => "<sign>G</sign><line>2</line>"
+        };
+        let mut accidentals = Accidentals::new(self.key_signature.0);
+
+        out.push_str(&format!("  <part id=\"P{}\">\n", index + 1));
+        for (number, events) in self.measures(index).iter().enumerate() {
+            out.push_str(&format!("    <measure number=\"{}\">\n", number + 1));
+            accidentals.clear();
+            if number == 0 {
+                let (fifths, is_minor) = self.key_signature;
+                out.push_str(&format!("      <attributes>\n        <divisions>{}</divisions>\n", DIVISIONS));
+                out.push_str(&format!("        <key><fifths>{}</fifths><mode>{}</mode></key>\n", fifths, if is_minor { "minor" } else { "major" }));
+                out.push_str(&format!(
+                    "        <time><beats>{}</beats><beat-type>{}</beat-type></time>\n",
+                    self.time_signature.numerator(),
+                    self.time_signature.denominator()
+                ));
+                out.push_str(&format!("        <clef>{}</clef>\n      </attributes>\n", clef));
+                if index == 0 {
+                    out.push_str(&format!(
+                        "      <direction placement=\"above\">\n        <direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{}</per-minute></metronome></direction-type>\n        <sound tempo=\"{}\"/>\n      </direction>\n",
+                        self.bpm,
+                        self.bpm
+                    ));
+                }
+            }
+            for event in events {
+                for chord in event.chords.iter() {
+                    write_harmony(out, chord);
+                }
+                self.write_event(out, event, part.is_percussion(), &mut accidentals);
+            }
+            out.push_str("    </measure>\n");
+        }
+        out.push_str("  </part>\n");
+    }
+
+    /// Writes the notes of an event, or a rest when it does not have any.
+    fn write_event(&self, out: &mut String, event: &ScoreEvent, is_percussion: bool, accidentals: &mut Accidentals) {
+        if event.notes.is_empty() {
+            out.push_str("      <note>\n        <rest/>\n");
+            write_note_value(out, event, None, (false, false));
+            return;
+        }
+        for (i, note) in event.notes.iter().enumerate() {
+            out.push_str("      <note>\n");
+            if i > 0 {
+                out.push_str("        <chord/>\n");
+            }
+            let (name, octave) = self.spell(note.pitch);
+            let accidental = match is_percussion {
+                true => {
+                    out.push_str(&format!(
+                        "        <unpitched><display-step>{}</display-step><display-octave>{}</display-octave></unpitched>\n",
+                        name.letter(),
+                        octave
+                    ));
+                    None
+                },
+                false => {
+                    let alter = match name.accidental() {
+                        0 => String::new(),
+                        accidental => format!("<alter>{}</alter>", accidental)
+                    };
+                    out.push_str(&format!("        <pitch><step>{}</step>{}<octave>{}</octave></pitch>\n", name.letter(), alter, octave));
+                    accidentals.next(name, octave, note.tie_stop)
+                }
+            };
+            write_note_value(out, event, accidental, (note.tie_stop, note.tie_start));
+        }
+    }
+}
+
+/// Writes the rest of a note after its pitch: the duration, ties, type and
+/// accidental.
+fn write_note_value(out: &mut String, event: &ScoreEvent, accidental: Option<i8>, (tie_stop, tie_start): (bool, bool)) {
+    out.push_str(&format!("        <duration>{}</duration>\n", event.duration));
+    if tie_stop {
+        out.push_str("        <tie type=\"stop\"/>\n");
+    }
+    if tie_start {
+        out.push_str("        <tie type=\"start\"/>\n");
+    }
+    out.push_str(&format!("        <voice>1</voice>\n        <type>{}</type>\n", event.kind));
+    if event.dotted {
+        out.push_str("        <dot/>\n");
+    }
+    if let Some(accidental) = accidental {
+        let accidental = match accidental {
+            -2 => "flat-flat",
+            -1 => "flat",
+            1 => "sharp",
+            2 => "double-sharp",
+            _ => "natural"
+        };
+        out.push_str(&format!("        <accidental>{}</accidental>\n", accidental));
+    }
+    if tie_stop || tie_start {
+        out.push_str("        <notations>\n");
+        if tie_stop {
+            out.push_str("          <tied type=\"stop\"/>\n");
+        }
+        if tie_start {
+            out.push_str("          <tied type=\"start\"/>\n");
+        }
+        out.push_str("        </notations>\n");
+    }
+    out.push_str("      </note>\n");
+}
+
+/// Writes the chord symbol of a chord, using its name for the root and the
+/// text of the symbol.
+fn write_harmony(out: &mut String, chord: &PlacedChord) {
+    let Some((root, text)) = NoteName::parse_prefix(&chord.name, 2) else {
+        return;
+    };
+    let mut intervals: Vec<i16> = chord.pitch_classes.iter().map(|pitch_class| (pitch_class - chord.root).rem_euclid(12)).collect();
+    intervals.sort_unstable();
+    intervals.dedup();
+    let alter = match root.accidental() {
+        0 => String::new(),
+        accidental => format!("<root-alter>{}</root-alter>", accidental)
+    };
+    out.push_str(&format!(
+        "      <harmony>\n        <root><root-step>{}</root-step>{}</root>\n        <kind text=\"{}\">{}</kind>\n      </harmony>\n",
+        root.letter(),
+        alter,
+        escape(text.trim()),
+        harmony_kind(&intervals)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_modules_v2::{meter::TimeSignature, midi::TrackSettings, score::{ScoreNote, ScorePart}};
+
+    #[test]
+    fn ties_accidentals_and_chord_symbols() {
+        let note = |pitch: u8, start: f64, length: f64| ScoreNote { pitch, start, length };
+        let score = Score {
+            title: Some("Rock & Roll".to_string()),
+            bpm: 90.0,
+            time_signature: TimeSignature::default(),
+            key_signature: (-3, true),
+            key: "Cmin".parse().unwrap(),
+            chords: vec![PlacedChord { name: "C major".to_string(), root: 0, pitch_classes: vec![0, 4, 7], start: 0.0, length: 6.0 }],
+            parts: vec![ScorePart {
+                settings: TrackSettings::default(),
+                // a C major chord over the barline, then an E flat
+                notes: vec![note(60, 0.0, 6.0), note(64, 0.0, 6.0), note(67, 0.0, 6.0), note(63, 6.0, 1.0), note(64, 7.0, 1.0)],
+            }],
+        };
+        let xml = score.to_musicxml();
+        assert!(xml.contains("<work-title>Rock &amp; Roll</work-title>"), "{}", xml);
+        assert!(xml.contains("<key><fifths>-3</fifths><mode>minor</mode></key>"));
+        assert!(xml.contains("<per-minute>90</per-minute>"));
+        assert!(xml.contains("<root><root-step>C</root-step></root>\n        <kind text=\"major\">major</kind>"), "{}", xml);
+        assert_eq!(xml.matches("<measure number=").count(), 2);
+        // the chord is a whole note tied to a half note
+        assert_eq!(xml.matches("<tie type=\"start\"/>").count(), 3);
+        assert_eq!(xml.matches("<tie type=\"stop\"/>").count(), 3);
+        assert_eq!(xml.matches("<chord/>").count(), 4);
+        // the tied E natural carries over the barline, so the E flat after it
+        // needs an accidental even though it is in the key signature
+        assert_eq!(xml.matches("<accidental>natural</accidental>").count(), 2);
+        assert!(xml.contains("<pitch><step>E</step><alter>-1</alter><octave>4</octave></pitch>"));
+        assert_eq!(xml.matches("<accidental>flat</accidental>").count(), 1);
+    }
+}
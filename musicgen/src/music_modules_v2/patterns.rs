@@ -131,36 +131,44 @@ pub mod old_validation {
 }
 
 pub mod validation {
-    pub fn validate_pattern(input: &str) -> (bool, Vec<u8>) {
+    use crate::music_modules_v2::error::Error;
+
+    /// Parses a pattern of the form `X-X-...-X`, where `X` is a chord number 
+    /// between 1 and 255.
+    /// 
+    /// The error contains the position of the first character that could not 
+    /// be parsed.
+    pub fn validate_pattern(input: &str) -> Result<Vec<u8>, Error> {
+        let invalid = |position: usize, reason: &'static str| Error::InvalidPattern {
+            pattern: input.to_string(),
+            position,
+            reason
+        };
         let mut result = Vec::with_capacity(input.len() / 2 + 5);
-        let mut chars = input.chars();
-        loop {
-            let mut n: u8 = 0;
-            'inner: loop {
-                let next = match chars.next() {
-                    Some(c) => c,
-                    None => {
-                        result.push(n);
-                        return (n != 0, result)
-                    }
-                };
-                if next == '-' {
+        let mut n: u8 = 0;
+        for (position, next) in input.char_indices() {
+            match next {
+                '-' => {
                     if n == 0 {
-                        return (false, result);
+                        return Err(invalid(position, "expected a chord number between 1 and 255 before `-`"));
                     }
                     result.push(n);
-                    break 'inner;
-                }
-                if (next as u8) < '0' as u8 {
-                    return (false, result);
-                }
-                let digit = next as u8 - '0' as u8;
-                if digit > 9 {
-                    return (false, result);
-                }
-                n = n * 10 + digit;
+                    n = 0;
+                },
+                '0'..='9' => {
+                    let digit = next as u8 - b'0';
+                    n = n.checked_mul(10)
+                        .and_then(|n| n.checked_add(digit))
+                        .ok_or_else(|| invalid(position, "chord numbers cannot be greater than 255"))?;
+                },
+                _ => return Err(invalid(position, "expected a digit or `-`"))
             }
         }
+        if n == 0 {
+            return Err(invalid(input.len(), "expected the pattern to end with a chord number between 1 and 255"));
+        }
+        result.push(n);
+        Ok(result)
     }
 
     #[cfg(test)]
@@ -182,7 +190,7 @@ pub mod validation {
             ];
 
             for (input, output) in data {
-                assert_eq!(validate_pattern(input).0, output)
+                assert_eq!(validate_pattern(input).is_ok(), output)
             }
         }
 
@@ -196,8 +204,457 @@ pub mod validation {
             ];
 
             for (input, output) in data {
-                assert_eq!(validate_pattern(input).1, output);
+                assert_eq!(validate_pattern(input).unwrap(), output);
+            }
+        }
+
+        #[test]
+        fn error_positions() {
+            let data = [
+                ("1-2-x-4", 4),
+                ("-1-2", 0),
+                ("1-2--3", 4),
+                ("1-2-", 4),
+                ("1-300", 4),
+                ("", 0),
+            ];
+
+            for (input, expected_position) in data {
+                match validate_pattern(input) {
+                    Err(Error::InvalidPattern { position, .. }) => assert_eq!(position, expected_position, "input: {}", input),
+                    other => panic!("expected an invalid pattern error for `{}`, got {:?}", input, other)
+                }
+            }
+        }
+    }
+}
+
+pub mod grammar {
+    //! The chord pattern language.
+    //! 
+    //! A pattern is a list of chord numbers separated by `-`, such as 
+    //! `1-2-3-4`, where each number is the index of a chord starting at 1. On
+    //! top of that:
+    //! 
+    //! * `1:2` plays the chord for two bars, and `2:0.5` for half a bar. 
+    //!   Without a duration, a chord lasts for the bars per chord of the meter.
+    //! * `_` or `r` is a rest, which can also have a duration, like `_:2`.
+    //! * `(1-2)x4` plays `1-2` four times. Repeats can be nested.
+    //! * `[verse]` starts a section, which is written to the MIDI file as a 
+    //!   marker. Sections are separated by whitespace, like 
+    //!   `[verse] 1-2-3-4 [chorus] 5-6-(1-2)x2`.
+    
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::music_modules_v2::error::Error;
+
+    /// The most bars that a chord or rest can last.
+    const MAX_BARS: f64 = 64.0;
+
+    /// The most chords and rests that a pattern can play once its repeats 
+    /// are expanded.
+    const MAX_STEPS: usize = 4096;
+    const TOO_MANY_STEPS: &str = "a pattern can play at most 4096 chords and rests";
+
+    /// A parsed chord pattern.
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct Pattern {
+        pub sections: Vec<Section>,
+    }
+
+    /// A part of a pattern, with an optional label such as `verse`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Section {
+        pub label: Option<String>,
+        pub items: Vec<PatternItem>,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum PatternItem {
+        /// A chord number starting at 1, which lasts for `bars` bars, or for 
+        /// the bars per chord of the meter when `bars` is `None`.
+        Chord { number: u8, bars: Option<f64> },
+        /// Silence, which lasts as long as a chord would.
+        Rest { bars: Option<f64> },
+        /// Items that are played `times` times in a row.
+        Repeat { items: Vec<PatternItem>, times: u8 },
+    }
+
+    impl PatternItem {
+        /// The number of chords and rests that the item plays.
+        fn step_count(&self) -> usize {
+            match self {
+                PatternItem::Repeat { items, times } => {
+                    items.iter().map(PatternItem::step_count).fold(0, usize::saturating_add).saturating_mul(*times as usize)
+                },
+                _ => 1
+            }
+        }
+    }
+
+    /// A chord or rest of a pattern after the repeats are expanded.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct PatternStep<'a> {
+        /// The chord number, or `None` for a rest.
+        pub chord: Option<u8>,
+        pub bars: Option<f64>,
+        /// The label of the section that the step is in.
+        pub section: Option<&'a str>,
+    }
+
+    impl Pattern {
+        /// Whether the pattern has no chords or rests.
+        pub fn is_empty(&self) -> bool {
+            self.sections.iter().all(|section| section.items.is_empty())
+        }
+
+        /// Returns the chords and rests in the order that they are played.
+        pub fn steps(&self) -> Vec<PatternStep<'_>> {
+            fn expand<'a>(items: &[PatternItem], section: Option<&'a str>, steps: &mut Vec<PatternStep<'a>>) {
+                for item in items {
+                    match item {
+                        PatternItem::Chord { number, bars } => steps.push(PatternStep { chord: Some(*number), bars: *bars, section }),
+                        PatternItem::Rest { bars } => steps.push(PatternStep { chord: None, bars: *bars, section }),
+                        PatternItem::Repeat { items, times } => {
+                            for _ in 0..*times {
+                                expand(items, section, steps);
+                            }
+                        }
+                    }
+                }
+            }
+            let mut steps = Vec::new();
+            for section in self.sections.iter() {
+                expand(&section.items, section.label.as_deref(), &mut steps);
+            }
+            steps
+        }
+
+        /// Returns the chord numbers in the order that they are played, 
+        /// leaving out the rests.
+        pub fn chord_numbers(&self) -> Vec<u8> {
+            self.steps().iter().filter_map(|step| step.chord).collect()
+        }
+
+        /// Checks a pattern that was built without parsing it. The positions 
+        /// in the errors refer to the pattern as it is displayed.
+        pub fn validate(&self) -> Result<(), Error> {
+            self.to_string().parse::<Pattern>().map(|_| ())
+        }
+    }
+
+    /// A pattern that plays each chord for the default length, in order.
+    impl From<Vec<u8>> for Pattern {
+        fn from(numbers: Vec<u8>) -> Self {
+            let items = numbers.into_iter().map(|number| PatternItem::Chord { number, bars: None }).collect();
+            Pattern { sections: vec![Section { label: None, items }] }
+        }
+    }
+
+    impl FromStr for Pattern {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Parser { input: s, position: 0 }.pattern()
+        }
+    }
+
+    impl TryFrom<String> for Pattern {
+        type Error = Error;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            match value.trim().is_empty() {
+                true => Ok(Pattern::default()),
+                false => value.parse()
             }
         }
     }
+
+    impl From<Pattern> for String {
+        fn from(value: Pattern) -> Self {
+            value.to_string()
+        }
+    }
+
+    impl Display for Pattern {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            for (i, section) in self.sections.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                if let Some(label) = section.label.as_ref() {
+                    write!(f, "[{}] ", label)?;
+                }
+                write_items(f, &section.items)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn write_items(f: &mut std::fmt::Formatter<'_>, items: &[PatternItem]) -> std::fmt::Result {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, "-")?;
+            }
+            let bars = match item {
+                PatternItem::Chord { number, bars } => {
+                    write!(f, "{}", number)?;
+                    bars
+                },
+                PatternItem::Rest { bars } => {
+                    write!(f, "_")?;
+                    bars
+                },
+                PatternItem::Repeat { items, times } => {
+                    write!(f, "(")?;
+                    write_items(f, items)?;
+                    write!(f, ")x{}", times)?;
+                    &None
+                }
+            };
+            if let Some(bars) = bars {
+                write!(f, ":{}", bars)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A recursive descent parser that keeps track of the position in the 
+    /// input for its errors.
+    struct Parser<'a> {
+        input: &'a str,
+        position: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        fn error(&self, position: usize, reason: &'static str) -> Error {
+            Error::InvalidPattern { pattern: self.input.to_string(), position, reason }
+        }
+
+        fn peek(&self) -> Option<char> {
+            self.input[self.position..].chars().next()
+        }
+
+        fn skip_whitespace(&mut self) {
+            while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+                self.position += c.len_utf8();
+            }
+        }
+
+        /// Skips whitespace, then consumes `c` if it is next.
+        fn eat(&mut self, c: char) -> bool {
+            self.skip_whitespace();
+            if self.peek() == Some(c) {
+                self.position += c.len_utf8();
+                return true;
+            }
+            false
+        }
+
+        /// Consumes the characters matching `f`.
+        fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+            let start = self.position;
+            while let Some(c) = self.peek().filter(|c| f(*c)) {
+                self.position += c.len_utf8();
+            }
+            &self.input[start..self.position]
+        }
+
+        fn pattern(&mut self) -> Result<Pattern, Error> {
+            let mut sections = Vec::new();
+            let mut step_count = 0;
+            loop {
+                self.skip_whitespace();
+                let section_start = self.position;
+                let label = match self.eat('[') {
+                    true => {
+                        let start = self.position;
+                        let label = self.take_while(|c| c != ']' && c != '[').trim();
+                        if !self.eat(']') {
+                            return Err(self.error(self.position, "expected `]` after the section label"));
+                        }
+                        if label.is_empty() {
+                            return Err(self.error(start, "section labels cannot be empty"));
+                        }
+                        Some(label.to_string())
+                    },
+                    false => None
+                };
+                let items = self.sequence()?;
+                step_count += items.iter().map(PatternItem::step_count).sum::<usize>();
+                if step_count > MAX_STEPS {
+                    return Err(self.error(section_start, TOO_MANY_STEPS));
+                }
+                sections.push(Section { label, items });
+
+                self.skip_whitespace();
+                match self.peek() {
+                    None => return Ok(Pattern { sections }),
+                    Some('[') => continue,
+                    Some(')') => return Err(self.error(self.position, "`)` does not close a repeat")),
+                    Some(_) => return Err(self.error(self.position, "expected `-` between chords, or `[` to start a section"))
+                }
+            }
+        }
+
+        /// Parses items separated by `-`.
+        fn sequence(&mut self) -> Result<Vec<PatternItem>, Error> {
+            let mut items = vec![self.item()?];
+            let mut step_count = items[0].step_count();
+            while self.eat('-') {
+                self.skip_whitespace();
+                let start = self.position;
+                let item = self.item()?;
+                step_count += item.step_count();
+                if step_count > MAX_STEPS {
+                    return Err(self.error(start, TOO_MANY_STEPS));
+                }
+                items.push(item);
+            }
+            Ok(items)
+        }
+
+        fn item(&mut self) -> Result<PatternItem, Error> {
+            self.skip_whitespace();
+            let start = self.position;
+            match self.peek() {
+                Some('0'..='9') => {
+                    let number = match self.take_while(|c| c.is_ascii_digit()).parse::<u8>() {
+                        Ok(0) => return Err(self.error(start, "chord numbers start at 1")),
+                        Ok(number) => number,
+                        Err(_) => return Err(self.error(start, "chord numbers cannot be greater than 255"))
+                    };
+                    Ok(PatternItem::Chord { number, bars: self.bars()? })
+                },
+                Some('_' | 'r') => {
+                    self.position += 1;
+                    Ok(PatternItem::Rest { bars: self.bars()? })
+                },
+                Some('(') => {
+                    self.position += 1;
+                    let items = self.sequence()?;
+                    if !self.eat(')') {
+                        return Err(self.error(self.position, "expected `)` to close the repeat"));
+                    }
+                    if !self.eat('x') {
+                        return Err(self.error(self.position, "expected `x` and the number of repeats after `)`"));
+                    }
+                    self.skip_whitespace();
+                    let times_position = self.position;
+                    match self.take_while(|c| c.is_ascii_digit()).parse::<u8>() {
+                        Ok(0) => Err(self.error(times_position, "a repeat must play at least once")),
+                        Ok(times) => {
+                            let repeat = PatternItem::Repeat { items, times };
+                            match repeat.step_count() > MAX_STEPS {
+                                true => Err(self.error(times_position, TOO_MANY_STEPS)),
+                                false => Ok(repeat)
+                            }
+                        },
+                        Err(_) => Err(self.error(times_position, "expected the number of repeats between 1 and 255 after `x`"))
+                    }
+                },
+                _ => Err(self.error(start, "expected a chord number, a rest or `(`"))
+            }
+        }
+
+        /// Parses an optional duration such as `:2` or `:0.5`.
+        fn bars(&mut self) -> Result<Option<f64>, Error> {
+            if self.peek() != Some(':') {
+                return Ok(None);
+            }
+            self.position += 1;
+            let start = self.position;
+            match self.take_while(|c| c.is_ascii_digit() || c == '.').parse::<f64>() {
+                Ok(bars) if bars > 0.0 && bars <= MAX_BARS => Ok(Some(bars)),
+                Ok(_) => Err(self.error(start, "a chord or rest must last more than 0 and at most 64 bars")),
+                Err(_) => Err(self.error(start, "expected a number of bars after `:`"))
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parsing() {
+            let pattern: Pattern = "[verse] 1-2:2-_-(3-r:0.5)x2 [chorus] 4:0.5".parse().unwrap();
+            assert_eq!(pattern.sections.len(), 2);
+            assert_eq!(pattern.sections[0].label.as_deref(), Some("verse"));
+            assert_eq!(pattern.sections[0].items, [
+                PatternItem::Chord { number: 1, bars: None },
+                PatternItem::Chord { number: 2, bars: Some(2.0) },
+                PatternItem::Rest { bars: None },
+                PatternItem::Repeat { items: vec![PatternItem::Chord { number: 3, bars: None }, PatternItem::Rest { bars: Some(0.5) }], times: 2 },
+            ]);
+            assert_eq!(pattern.to_string(), "[verse] 1-2:2-_-(3-_:0.5)x2 [chorus] 4:0.5");
+            assert_eq!(pattern.chord_numbers(), [1, 2, 3, 3, 4]);
+
+            let steps = pattern.steps();
+            assert_eq!(steps.len(), 8);
+            assert_eq!(steps[6], PatternStep { chord: None, bars: Some(0.5), section: Some("verse") });
+            assert_eq!(steps[7], PatternStep { chord: Some(4), bars: Some(0.5), section: Some("chorus") });
+
+            // plain patterns still work, and whitespace is ignored
+            assert_eq!("1-2-3-4".parse::<Pattern>().unwrap(), Pattern::from(vec![1, 2, 3, 4]));
+            assert_eq!(" 1 - 2 ".parse::<Pattern>().unwrap(), Pattern::from(vec![1, 2]));
+            assert_eq!("((1)x2-2)x3".parse::<Pattern>().unwrap().chord_numbers(), [1, 1, 2, 1, 1, 2, 1, 1, 2]);
+            assert_eq!("(((1)x16)x16)x16".parse::<Pattern>().unwrap().steps().len(), 4096);
+        }
+
+        #[test]
+        fn error_positions() {
+            let data = [
+                ("", 0),
+                ("1-2-x-4", 4),
+                ("-1-2", 0),
+                ("1-2--3", 4),
+                ("1-2-", 4),
+                ("1-300", 2),
+                ("1-0", 2),
+                ("1 2", 2),
+                ("1:", 2),
+                ("1:0", 2),
+                ("1:100", 2),
+                ("(1-2", 4),
+                ("(1-2)", 5),
+                ("(1-2)x0", 6),
+                ("1-2)", 3),
+                ("[] 1", 1),
+                ("[verse 1-2", 10),
+                ("(((1)x255)x255)x255", 11),
+                ("((((1)x16)x16)x16)x2", 19),
+                ("((1)x255)x16-(1-2)x9", 13),
+                ("[a] ((1)x255)x16 [b] (1-2)x9", 17),
+            ];
+
+            for (input, expected_position) in data {
+                match input.parse::<Pattern>() {
+                    Err(Error::InvalidPattern { position, .. }) => assert_eq!(position, expected_position, "input: {}", input),
+                    other => panic!("expected an invalid pattern error for `{}`, got {:?}", input, other)
+                }
+            }
+        }
+
+        #[test]
+        fn validating_built_patterns() {
+            assert!(Pattern::from(vec![1, 2]).validate().is_ok());
+            let pattern = Pattern { sections: vec![Section { 
+                label: None, 
+                items: vec![PatternItem::Chord { number: 1, bars: None }, PatternItem::Rest { bars: Some(-1.0) }]
+            }] };
+            assert!(matches!(pattern.validate(), Err(Error::InvalidPattern { position: 4, .. })));
+        }
+
+        #[test]
+        fn serde() {
+            let pattern: Pattern = serde_json::from_str(r#""1:2-(2-3)x2""#).unwrap();
+            assert_eq!(serde_json::to_string(&pattern).unwrap(), r#""1:2-(2-3)x2""#);
+            assert_eq!(serde_json::from_str::<Pattern>(r#""""#).unwrap(), Pattern::default());
+            assert!(serde_json::from_str::<Pattern>(r#""1--2""#).is_err());
+        }
+    }
 }
\ No newline at end of file
@@ -2,30 +2,16 @@
 
 use std::{collections::HashSet, hash::{DefaultHasher, Hash, Hasher}};
 
-use super::{chord::{expand_chords, Chord}, utils::sets::SetOpsCollection, music::notes::*};
+use super::{chord::{expand_chords, Chord}, scale::PitchClassSet, utils::sets::SetOpsCollection};
 
 /// Returns the good notes set and the bad notes set for a given scale in the 
 /// key of C minor.
 /// 
-/// Returns `Option<(good_notes_set, bad_notes_set)>`
-fn get_good_notes_set(scale: &str) -> Option<(HashSet<i16>, Vec<usize>)> {
-    let good_notes_set: HashSet<i16> = match scale {
-        "disabled" => return None,
-        "natural" => HashSet::from([0, 2, 3, 5, 7, 8, 10]),
-        "melodic" => HashSet::from([0, 2, 3, 5, 7, 9, 11]),
-        "harmonic" => HashSet::from([0, 2, 3, 5, 7, 8, 11]),
-        "pentatonic" => HashSet::from([0, 3, 5, 7, 10]),
-        "romanian" => HashSet::from([0, 2, 3, 6, 7, 9, 10]),
-        "hungarian" => HashSet::from([0, 2, 3, 6, 7, 8, 11]),
-        // "all_notes" restructures `chord_table` and `chord_list` with the
-        // optional notes vecs getting converted to new chords
-        "all_notes" => HashSet::from([0,1,2,3,4,5,6,7,8,9,10,11]),
-        "half_whole" => HashSet::from_iter([C, CSHARP, DSHARP, E, FSHARP, G, A, ASHARP].iter().map(|note| *note as i16)),
-        "whole_half" => HashSet::from_iter([C, D, DSHARP, F, FSHARP, GSHARP, A, B].iter().map(|note| *note as i16)),
-        _ => return None
-    };
-    let bad_notes = HashSet::from_iter(0..12).difference(&good_notes_set).map(|v| *v as usize).collect();
-    return Some((good_notes_set, bad_notes))
+/// Returns `(good_notes_set, bad_notes_set)`
+pub fn get_good_notes_set(scale: PitchClassSet) -> (HashSet<i16>, Vec<usize>) {
+    let good_notes_set: HashSet<i16> = scale.to_set();
+    let bad_notes = scale.complement().iter().map(|v| v as usize).collect();
+    return (good_notes_set, bad_notes)
 }
 
 /// Removes chords that have notes outside of the chosen scale
@@ -35,14 +21,18 @@ fn get_good_notes_set(scale: &str) -> Option<(HashSet<i16>, Vec<usize>)> {
 /// * F# - 6
 /// * A  - 9
 /// * B - 11
+/// 
+/// Nothing is pruned when `scale` is `None`. A scale with every note, such as 
+/// `Scale::AllNotes`, does not prune anything either, but still turns the 
+/// optional notes of each chord into new chords.
 pub fn prune_chords(
     chord_table: &mut Vec<Vec<Chord>>, 
     chord_list: &mut Vec<Chord>, 
-    scale: &str, 
+    scale: Option<PitchClassSet>, 
     is_reproducible: bool
 ) {
-    let (_good_notes_set, bad_notes) = match get_good_notes_set(scale) {
-        Some(v) => v,
+    let (_good_notes_set, bad_notes) = match scale {
+        Some(scale) => get_good_notes_set(scale),
         None => { return; }
     };
     
@@ -86,7 +76,7 @@ pub fn prune_chords(
 
 #[cfg(test)]
 mod tests {
-    use crate::music_modules_v2::{utils::{parse_key, sets::ToSet}, Music};
+    use crate::music_modules_v2::{config::{GenerationConfig, Scale}, music::notes::*, utils::{parse_key, sets::ToSet}, Music};
 
     use super::*;
 
@@ -114,12 +104,11 @@ mod tests {
     fn num_chords_equal_in_list_and_table() {
         let musician = Music::smoke_hash(
             Default::default(),
-            "Cmin",
-            &HashSet::new(),
-            "default",
-            "pentatonic",
-            true,
-            false
+            &GenerationConfig::builder()
+                .key("Cmin")
+                .scale(Scale::Pentatonic)
+                .is_reproducible(true)
+                .build()
         ).unwrap();
 
         let mut chords = musician.chord_table[0].to_set();
@@ -134,12 +123,11 @@ mod tests {
     fn pruning_c_minor() {
         let musician = Music::smoke_hash(
             Default::default(),
-            "Cmin",
-            &HashSet::new(),
-            "default",
-            "pentatonic",
-            true,
-            false
+            &GenerationConfig::builder()
+                .key("Cmin")
+                .scale(Scale::Pentatonic)
+                .is_reproducible(true)
+                .build()
         ).unwrap();
 
         assert!(musician.chord_table[CSHARP as usize].len() == 0, "C# had some chords in it");
@@ -157,7 +145,7 @@ mod tests {
         assert!(musician.chord_table[ASHARP as usize].len() != 0, "A# was empty");
 
         // check for chords that shouldn't be there
-        let (_good_notes, bad_notes) = get_good_notes_set("pentatonic").unwrap();
+        let (_good_notes, bad_notes) = get_good_notes_set(Scale::Pentatonic.pitch_classes().unwrap());
         let bad_notes_set: HashSet<i16> = bad_notes.iter().map(|n| *n as i16).collect();
         let mut bad_chords: HashSet<Chord> = HashSet::new();
         for chords in musician.chord_table.iter() {
@@ -175,16 +163,16 @@ mod tests {
 
     #[test]
     fn prune_in_fsharp_min() {
-        let mut musician = Music::smoke_hash_all_pruning_chords("Cmin", "pentatonic");
+        let mut musician = Music::smoke_hash_all_pruning_chords("Cmin", Scale::Pentatonic);
         print_chord_counts!(musician, "prune_in_fsharp_min");
 
-        musician.rotate_chords("F#min");
+        musician.rotate_chords("F#min").unwrap();
 
         print_chord_counts!(musician, "prune_in_fsharp_min");
 
-        let (_good_notes, bad_notes) = get_good_notes_set("pentatonic").unwrap();
+        let (_good_notes, bad_notes) = get_good_notes_set(Scale::Pentatonic.pitch_classes().unwrap());
 
-        let bad_notes_set: HashSet<i16> = HashSet::from_iter(bad_notes.iter().map(|n| (*n as i16 + parse_key("F#min")) % 12));
+        let bad_notes_set: HashSet<i16> = HashSet::from_iter(bad_notes.iter().map(|n| (*n as i16 + parse_key("F#min").unwrap()) % 12));
 
         // bad notes
         assert!(musician.chord_table[G as usize].len() == 0, "G had some chords in it");
@@ -229,12 +217,11 @@ mod tests {
     fn prune_c_minor_natural() {
         let musician = Music::smoke_hash(
             Default::default(),
-            "Cmin",
-            &HashSet::new(),
-            "default",
-            "natural",
-            true,
-            false
+            &GenerationConfig::builder()
+                .key("Cmin")
+                .scale(Scale::Natural)
+                .is_reproducible(true)
+                .build()
         ).unwrap();
 
         assert!(musician.chord_table[CSHARP as usize].len() == 0, "C# had some chords in it");
@@ -253,7 +240,7 @@ mod tests {
         assert!(musician.chord_table[ASHARP as usize].len() != 0, "A# was empty");
 
         // check for chords that shouldn't be there
-        let (_good_notes, bad_notes) = get_good_notes_set("natural").unwrap();
+        let (_good_notes, bad_notes) = get_good_notes_set(Scale::Natural.pitch_classes().unwrap());
         let bad_notes_set: HashSet<i16> = bad_notes.iter().map(|n| *n as i16).collect();
         let mut bad_chords: HashSet<Chord> = HashSet::new();
         for chords in musician.chord_table.iter() {
@@ -268,4 +255,26 @@ mod tests {
         println!("Num chords: {}", musician.chord_list.len());
         assert_eq!(bad_chords.len(), 0);
     }
+
+    #[test]
+    fn prune_to_custom_scale() {
+        // C Dorian with a raised 4th
+        let scale: PitchClassSet = "2-1-3-1-2-1-2".parse().unwrap();
+        let musician = Music::smoke_hash(
+            Default::default(),
+            &GenerationConfig::builder()
+                .key("Cmin")
+                .custom_scale(scale)
+                .is_reproducible(true)
+                .build()
+        ).unwrap();
+
+        for note in scale.complement().iter() {
+            assert!(musician.chord_table[note as usize].is_empty(), "{} had some chords in it", note);
+        }
+        for chord in musician.chord_list.iter() {
+            assert!(chord.get_notes().iter().all(|n| scale.contains((n % 12) as u8)), "{} is not in the scale", chord.get_name());
+        }
+        assert!(!musician.chord_list.is_empty());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+//! Reharmonizing a melody.
+//!
+//! Rather than picking chords at random, the chords are picked from the chord
+//! table so that they contain the notes that a melody plays on the strong
+//! beats of each chord. The melody is read from a MIDI file, and its highest
+//! notes are used when it has more than one voice.
+
+use serde::{Deserialize, Serialize};
+
+use super::{analysis::{ImportedNote, MidiClip}, error::Error, meter::Meter, midi::TrackSettings};
+
+/// The settings for reharmonizing a melody, which replace the random chord
+/// picking.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Reharmonizer {
+    /// Writes the melody to its own track, along with the chords.
+    pub keep_melody: bool,
+    pub melody_track: TrackSettings,
+    /// The notes of the melody, one at a time. They are read from the
+    /// uploaded MIDI file by `generate_midi_with_config`.
+    #[serde(skip)]
+    pub melody: Vec<ImportedNote>,
+}
+
+impl Default for Reharmonizer {
+    fn default() -> Self {
+        Reharmonizer {
+            keep_melody: true,
+            melody_track: TrackSettings::new("Melody", 1, None),
+            melody: Vec::new(),
+        }
+    }
+}
+
+impl MidiClip {
+    /// The highest note that is playing at each time, where a note is cut
+    /// short when a higher note starts.
+    pub fn melody(&self) -> Vec<ImportedNote> {
+        let mut melody: Vec<ImportedNote> = Vec::new();
+        for note in self.notes.iter() {
+            if let Some(last) = melody.last_mut() {
+                if last.start + last.length > note.start + 1e-9 {
+                    match (last.pitch > note.pitch, (last.start - note.start).abs() < 1e-9) {
+                        // the note is hidden below the melody
+                        (true, _) => continue,
+                        // notes are sorted by pitch when they start together
+                        (false, true) => {
+                            melody.pop();
+                        },
+                        (false, false) => last.length = note.start - last.start
+                    }
+                }
+            }
+            melody.push(*note);
+        }
+        melody
+    }
+}
+
+impl Reharmonizer {
+    /// Reharmonizes the melody of a MIDI clip.
+    pub fn new(clip: &MidiClip) -> Self {
+        Reharmonizer { melody: clip.melody(), ..Default::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        self.melody_track.validate()?;
+        if self.melody.is_empty() {
+            return Err(Error::InvalidInput("there is no melody to reharmonize".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The time that the melody ends, in beats.
+    pub fn length(&self) -> f64 {
+        self.melody.iter().map(|note| note.start + note.length).fold(0.0, f64::max)
+    }
+
+    /// Returns the pitch classes of the melody between `start` and `end`, and
+    /// how many of them are played on strong beats. The pitch classes on the
+    /// strong beats come first, in the order that they are played, followed
+    /// by the other pitch classes from the longest to the shortest.
+    pub fn pitch_classes(&self, start: f64, end: f64, meter: &Meter) -> (Vec<i16>, usize) {
+        // the middle of bars with an even number of beats is also strong
+        let half_bar = match meter.beats_per_bar() % 2 {
+            0 => meter.bar_length() / 2.0,
+            _ => meter.bar_length()
+        };
+        let mut pitch_classes: Vec<i16> = Vec::new();
+        let mut time = (start / half_bar - 1e-9).ceil() * half_bar;
+        while time < end - 1e-9 {
+            let playing = self.melody
+                .iter()
+                .find(|note| note.start <= time + 1e-9 && time + 1e-9 < note.start + note.length);
+            if let Some(note) = playing {
+                let pitch_class = (note.pitch % 12) as i16;
+                if !pitch_classes.contains(&pitch_class) {
+                    pitch_classes.push(pitch_class);
+                }
+            }
+            time += half_bar;
+        }
+        let strong = pitch_classes.len();
+
+        let mut lengths = [0.0; 12];
+        for note in self.melody.iter() {
+            lengths[(note.pitch % 12) as usize] += ((note.start + note.length).min(end) - note.start.max(start)).max(0.0);
+        }
+        let mut others: Vec<i16> = (0..12)
+            .filter(|pitch_class| lengths[*pitch_class as usize] > 1e-9 && !pitch_classes.contains(pitch_class))
+            .collect();
+        others.sort_by(|a, b| lengths[*b as usize].total_cmp(&lengths[*a as usize]));
+        pitch_classes.extend(others);
+        (pitch_classes, strong)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(start: f64, length: f64, pitch: u8) -> ImportedNote {
+        ImportedNote { start, length, pitch, velocity: 100, channel: 0 }
+    }
+
+    #[test]
+    fn the_melody_is_the_top_line() {
+        let clip = MidiClip {
+            notes: vec![note(0.0, 4.0, 48), note(0.0, 2.0, 64), note(1.0, 1.0, 60), note(2.0, 2.0, 67), note(3.0, 1.0, 72)],
+            time_signature: Default::default(),
+        };
+        let melody: Vec<(f64, f64, u8)> = clip.melody().iter().map(|note| (note.start, note.length, note.pitch)).collect();
+        assert_eq!(melody, [(0.0, 2.0, 64), (2.0, 1.0, 67), (3.0, 1.0, 72)]);
+    }
+
+    #[test]
+    fn strong_beats_come_first() {
+        let reharmonizer = Reharmonizer {
+            melody: vec![note(0.0, 1.0, 60), note(1.0, 1.0, 62), note(2.0, 0.5, 64), note(2.5, 1.5, 65), note(4.0, 4.0, 67)],
+            ..Default::default()
+        };
+        reharmonizer.validate().unwrap();
+        assert_eq!(reharmonizer.length(), 8.0);
+        let meter = Meter::default();
+        // beats 1 and 3 of 4/4 are strong
+        assert_eq!(reharmonizer.pitch_classes(0.0, 4.0, &meter), (vec![0, 4, 5, 2], 2));
+        assert_eq!(reharmonizer.pitch_classes(4.0, 8.0, &meter), (vec![7], 1));
+        // only the downbeat is strong in 3/4
+        let meter = Meter::new("3/4".parse().unwrap(), 1.0).unwrap();
+        assert_eq!(reharmonizer.pitch_classes(0.0, 3.0, &meter), (vec![0, 2, 4, 5], 1));
+        assert!(Reharmonizer::default().validate().is_err());
+    }
+}
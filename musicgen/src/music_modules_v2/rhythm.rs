@@ -0,0 +1,266 @@
+//! Rhythms for placing chords.
+//!
+//! A rhythm can be written in two ways:
+//!
+//! * On a 16th note grid, where `x` is a hit, `X` is an accented hit, `-`
+//!   holds the previous hit for another 16th and `.` is a rest. Spaces and
+//!   `|` can be used to separate beats and bars, like `x..x ..x. x... x-..`.
+//! * As note values separated by spaces, where `w`, `h`, `q`, `e` and `s` are
+//!   whole, half, quarter, eighth and sixteenth notes. A trailing `.` dots the
+//!   note, an `r` in front makes it a rest, and an uppercase letter accents
+//!   it, like `q q e e rq. Q`.
+//!
+//! A rhythm is repeated until the chord ends, and the hits that go past the
+//! end of the chord are cut short.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::Groove, error::Error};
+
+/// The length of a step of the grid, in beats.
+const GRID_STEP: f64 = 0.25;
+
+/// A note of a rhythm. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub start: f64,
+    pub length: f64,
+    pub accent: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rhythm {
+    hits: Vec<Hit>,
+    /// The length of the rhythm in beats, including any rests at the end.
+    length: f64,
+    /// The rhythm as it was written, or the name of the groove.
+    text: String,
+}
+
+impl Rhythm {
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    /// The length of the rhythm in beats.
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    /// Returns the hits that fill `length` beats, repeating the rhythm and
+    /// cutting the last hits short.
+    pub fn hits_in(&self, length: f64) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let mut offset = 0.0;
+        while offset < length {
+            for hit in self.hits.iter() {
+                let start = offset + hit.start;
+                if start >= length {
+                    break;
+                }
+                hits.push(Hit { start, length: hit.length.min(length - start), accent: hit.accent });
+            }
+            offset += self.length;
+        }
+        hits
+    }
+
+    /// Parses a rhythm on a 16th note grid.
+    fn parse_grid(text: &str) -> Result<Rhythm, Error> {
+        let invalid = |position, reason| Error::InvalidRhythm { rhythm: text.to_string(), position, reason };
+        let mut hits: Vec<Hit> = Vec::new();
+        let mut time = 0.0;
+        // whether `-` holds the last hit rather than following a rest
+        let mut is_holding = false;
+        for (position, c) in text.char_indices() {
+            match c {
+                'x' | 'X' => {
+                    hits.push(Hit { start: time, length: GRID_STEP, accent: c == 'X' });
+                    is_holding = true;
+                },
+                '-' => match (is_holding, hits.last_mut()) {
+                    (true, Some(hit)) => hit.length += GRID_STEP,
+                    _ => return Err(invalid(position, "`-` must follow a hit"))
+                },
+                '.' => is_holding = false,
+                ' ' | '|' => continue,
+                _ => return Err(invalid(position, "expected `x`, `X`, `-`, `.`, `|` or a space"))
+            }
+            time += GRID_STEP;
+        }
+        Self::new(hits, time, text)
+    }
+
+    /// Parses a rhythm that is written with note values.
+    fn parse_note_values(text: &str) -> Result<Rhythm, Error> {
+        let invalid = |position, reason| Error::InvalidRhythm { rhythm: text.to_string(), position, reason };
+        let mut hits = Vec::new();
+        let mut time = 0.0;
+        let mut chars = text.char_indices().peekable();
+        while let Some((position, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let is_rest = c == 'r';
+            let (position, c) = match is_rest {
+                true => chars.next().ok_or_else(|| invalid(text.len(), "expected a note value after `r`"))?,
+                false => (position, c)
+            };
+            let mut length = match c.to_ascii_lowercase() {
+                'w' => 4.0,
+                'h' => 2.0,
+                'q' => 1.0,
+                'e' => 0.5,
+                's' => 0.25,
+                _ => return Err(invalid(position, "expected `w`, `h`, `q`, `e` or `s`"))
+            };
+            if chars.next_if(|(_, c)| *c == '.').is_some() {
+                length *= 1.5;
+            }
+            if let Some((position, _)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+                return Err(invalid(position, "expected a space between note values"));
+            }
+            if !is_rest {
+                hits.push(Hit { start: time, length, accent: c.is_ascii_uppercase() });
+            }
+            time += length;
+        }
+        Self::new(hits, time, text)
+    }
+
+    fn new(hits: Vec<Hit>, length: f64, text: &str) -> Result<Rhythm, Error> {
+        if hits.is_empty() {
+            return Err(Error::InvalidRhythm { rhythm: text.to_string(), position: 0, reason: "a rhythm needs at least one hit" });
+        }
+        Ok(Rhythm { hits, length, text: text.to_string() })
+    }
+}
+
+impl Groove {
+    /// Returns the rhythm of the groove.
+    pub fn rhythm(&self) -> Rhythm {
+        let grid = match self {
+            Groove::Reggaeton => "x..x..x.x..x..x.",
+            Groove::FourOnTheFloor => "x...x...x...x...",
+            Groove::Charleston => "x-----x-........",
+            Groove::Bossa => "x..x..x...x..x.."
+        };
+        let rhythm = Rhythm::parse_grid(grid).expect("the grooves are valid rhythms");
+        Rhythm { text: self.to_string(), ..rhythm }
+    }
+}
+
+impl From<Groove> for Rhythm {
+    fn from(value: Groove) -> Self {
+        value.rhythm()
+    }
+}
+
+/// Parses the name of a groove, a grid or note values.
+impl FromStr for Rhythm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(groove) = s.trim().parse::<Groove>() {
+            return Ok(groove.rhythm());
+        }
+        // note values never start with the characters of a grid
+        match s.trim_start().starts_with(['x', 'X', '-', '.', '|']) || s.trim().is_empty() {
+            true => Rhythm::parse_grid(s),
+            false => Rhythm::parse_note_values(s)
+        }
+    }
+}
+
+impl TryFrom<String> for Rhythm {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rhythm> for String {
+    fn from(value: Rhythm) -> Self {
+        value.text
+    }
+}
+
+impl Display for Rhythm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts(rhythm: &Rhythm) -> Vec<f64> {
+        rhythm.hits().iter().map(|hit| hit.start).collect()
+    }
+
+    #[test]
+    fn grids() {
+        let rhythm: Rhythm = "x..x ..X- |x---".parse().unwrap();
+        assert_eq!(rhythm.length(), 3.0);
+        assert_eq!(starts(&rhythm), [0.0, 0.75, 1.5, 2.0]);
+        assert_eq!(rhythm.hits()[2], Hit { start: 1.5, length: 0.5, accent: true });
+        assert_eq!(rhythm.hits()[3].length, 1.0);
+        assert_eq!(rhythm.to_string(), "x..x ..X- |x---");
+    }
+
+    #[test]
+    fn note_values() {
+        let rhythm: Rhythm = "q q e e rq. Q".parse().unwrap();
+        assert_eq!(rhythm.length(), 5.5);
+        assert_eq!(starts(&rhythm), [0.0, 1.0, 2.0, 2.5, 4.5]);
+        assert!(rhythm.hits()[4].accent);
+        assert_eq!("h. s".parse::<Rhythm>().unwrap().hits()[0].length, 3.0);
+    }
+
+    #[test]
+    fn grooves() {
+        for groove in Groove::ALL {
+            let rhythm = groove.rhythm();
+            assert_eq!(rhythm.length(), 4.0);
+            assert_eq!(rhythm.to_string().parse::<Rhythm>().unwrap(), rhythm);
+        }
+        assert_eq!(starts(&"charleston".parse().unwrap()), [0.0, 1.5]);
+        assert_eq!(serde_json::to_string(&Groove::Bossa.rhythm()).unwrap(), r#""bossa""#);
+    }
+
+    #[test]
+    fn repeating_and_cutting_short() {
+        let rhythm: Rhythm = "x-.x".parse().unwrap();
+        let hits = rhythm.hits_in(2.5);
+        assert_eq!(hits.iter().map(|hit| hit.start).collect::<Vec<f64>>(), [0.0, 0.75, 1.0, 1.75, 2.0]);
+        assert_eq!(hits[4].length, 0.5);
+        // the last hit is cut short by the end of the chord
+        let hits = Groove::Charleston.rhythm().hits_in(1.75);
+        assert_eq!(hits, [Hit { start: 0.0, length: 1.5, accent: false }, Hit { start: 1.5, length: 0.25, accent: false }]);
+    }
+
+    #[test]
+    fn errors() {
+        let data = [
+            ("-x", 0),
+            ("x.-", 2),
+            ("x..o", 3),
+            ("....", 0),
+            ("q q z", 4),
+            ("q qq", 3),
+            ("q r", 3),
+            ("", 0),
+        ];
+        for (input, expected_position) in data {
+            match input.parse::<Rhythm>() {
+                Err(Error::InvalidRhythm { position, .. }) => assert_eq!(position, expected_position, "input: {}", input),
+                other => panic!("expected an invalid rhythm error for `{}`, got {:?}", input, other)
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Roman numerals, such as `ii7`, `V7/V` or `bVI`, relative to a key.
+//!
+//! Uppercase numerals are major and lowercase numerals are minor, and may be
+//! followed by `°` (or `o`) for diminished, `ø` for half-diminished or `+` for
+//! augmented. Anything after that, such as `7`, `maj7` or `sus4`, is read the
+//! same way as the rest of a chord symbol. The degrees of minor keys are taken
+//! from the natural minor scale, so `VII` in C minor is Bb.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::Serialize;
+
+use super::{chord::Chord, chord_symbol::{split_dashes, ChordSymbol}, error::Error, key::{Key, Mode, NoteName, PitchClass}};
+
+const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+
+/// The role that a chord plays in a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HarmonicFunction {
+    Tonic,
+    Subdominant,
+    Dominant,
+}
+
+impl HarmonicFunction {
+    /// The function of each scale degree: I, iii and vi are tonic, ii and IV
+    /// are subdominant, and V and vii are dominant.
+    pub fn of_degree(degree: u8) -> Self {
+        match degree {
+            2 | 4 => HarmonicFunction::Subdominant,
+            5 | 7 => HarmonicFunction::Dominant,
+            _ => HarmonicFunction::Tonic,
+        }
+    }
+}
+
+impl Display for HarmonicFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HarmonicFunction::Tonic => "tonic",
+            HarmonicFunction::Subdominant => "subdominant",
+            HarmonicFunction::Dominant => "dominant",
+        })
+    }
+}
+
+/// A Roman numeral.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomanNumeral {
+    /// -1 for a flat and 1 for a sharp before the numeral.
+    pub accidental: i8,
+    /// The scale degree, from 1 to 7.
+    pub degree: u8,
+    /// Whether the numeral is uppercase.
+    pub is_major: bool,
+    /// `°`, `ø` or `+`.
+    pub marker: Option<char>,
+    /// The rest of the chord, such as `7` or `maj9`.
+    pub suffix: String,
+    /// The degree that a secondary chord, such as `V7/V`, is relative to.
+    pub secondary: Option<Box<RomanNumeral>>,
+}
+
+impl RomanNumeral {
+    /// Returns the harmonic function of this numeral. Secondary chords have the
+    /// function of the degree that they lead to.
+    pub fn function(&self) -> HarmonicFunction {
+        match &self.secondary {
+            Some(target) => target.function(),
+            None => HarmonicFunction::of_degree(self.degree),
+        }
+    }
+
+    /// Turns this numeral into a chord symbol in `key`.
+    pub fn to_chord_symbol(&self, key: &Key) -> Result<ChordSymbol, Error> {
+        let key = match &self.secondary {
+            Some(target) => {
+                let tonic = target.root_in(key)?;
+                let mode = match target.is_major {
+                    true => Mode::Major,
+                    false => Mode::Minor,
+                };
+                Key::from_note_name(tonic, mode)
+            },
+            None => *key
+        };
+        let mut symbol = self.chord_on_c()?;
+        symbol.root = self.root_in(&key)?;
+        Ok(symbol)
+    }
+
+    /// Reads the quality and suffix as a chord symbol on C.
+    fn chord_on_c(&self) -> Result<ChordSymbol, Error> {
+        let quality = match (self.is_major, self.marker) {
+            (_, Some('°')) => "dim",
+            (_, Some('ø')) => "ø",
+            (_, Some('+')) => "aug",
+            (true, _) => "",
+            (false, _) => "m",
+        };
+        let invalid = || Error::InvalidRomanNumeral {
+            numeral: self.to_string(),
+            reason: "the chord after the numeral could not be read"
+        };
+        let symbol: ChordSymbol = format!("C{}{}", quality, self.suffix).parse().map_err(|_| invalid())?;
+        // a suffix such as `#7` would otherwise change the root
+        match symbol.root.to_string().as_str() {
+            "C" => Ok(symbol),
+            _ => Err(invalid())
+        }
+    }
+
+    /// Turns this numeral into a chord in `key`. The root is spelled from the
+    /// numeral, so `bVI` in C major is Ab rather than G#.
+    pub fn to_chord(&self, key: &Key) -> Result<Chord, Error> {
+        Ok(self.to_chord_symbol(key)?.to_chord())
+    }
+
+    fn root_in(&self, key: &Key) -> Result<NoteName, Error> {
+        key.degree(self.degree, self.accidental).ok_or_else(|| Error::InvalidRomanNumeral {
+            numeral: self.to_string(),
+            reason: "the root cannot be spelled in this key"
+        })
+    }
+
+    /// Names a chord as a Roman numeral in `key`.
+    pub fn from_chord(chord: &Chord, key: &Key) -> Self {
+        Self::from_notes(PitchClass::new(chord.root as i16 + chord.key), &chord.get_notes(), key)
+    }
+
+    /// Names the chord with the given root and notes as a Roman numeral in
+    /// `key`.
+    pub fn from_notes(root: PitchClass, notes: &[i16], key: &Key) -> Self {
+        let interval = PitchClass::new(root.value() as i16 - key.tonic.value() as i16).value();
+        let (degree, accidental) = Self::degree_of(interval, key.mode);
+
+        let notes: Vec<u8> = notes
+            .iter()
+            .map(|note| PitchClass::new(*note - root.value() as i16).value())
+            .collect();
+        let has = |interval: u8| notes.contains(&interval);
+        let (is_major, marker) = if has(4) && has(8) && !has(7) {
+            (true, Some('+'))
+        } else if has(4) {
+            (true, None)
+        } else if has(3) && has(6) && !has(7) {
+            match has(10) {
+                true => (false, Some('ø')),
+                false => (false, Some('°')),
+            }
+        } else if has(3) {
+            (false, None)
+        } else {
+            (true, None)
+        };
+        let mut suffix = String::new();
+        if !has(3) && !has(4) {
+            if has(5) {
+                suffix.push_str("sus4");
+            } else if has(2) {
+                suffix.push_str("sus2");
+            }
+        }
+        let seventh = match marker {
+            Some('°') if has(9) => "7",
+            Some('°') => "",
+            Some('ø') => "7",
+            _ if has(11) => "maj7",
+            _ if has(10) => "7",
+            _ => "",
+        };
+        suffix.insert_str(0, seventh);
+        RomanNumeral { accidental, degree, is_major, marker, suffix, secondary: None }
+    }
+
+    /// Finds the scale degree of a pitch class, given as an interval above the
+    /// tonic. Notes outside of major keys are written as flats, apart from the
+    /// raised 4th, and notes outside of minor keys are written as sharps,
+    /// apart from the lowered 2nd.
+    fn degree_of(interval: u8, mode: Mode) -> (u8, i8) {
+        let intervals = mode.intervals();
+        if let Some(degree) = intervals.iter().position(|i| *i == interval) {
+            return (degree as u8 + 1, 0);
+        }
+        let prefers_flat = match mode {
+            Mode::Major => interval != 6,
+            Mode::Minor => interval == 1,
+        };
+        let flat = intervals.iter().position(|i| *i == interval + 1);
+        let sharp = intervals.iter().position(|i| *i + 1 == interval);
+        match (flat, sharp, prefers_flat) {
+            (Some(degree), _, true) | (Some(degree), None, false) => (degree as u8 + 1, -1),
+            (_, Some(degree), _) => (degree as u8 + 1, 1),
+            // every chromatic note is next to a note in the scale
+            _ => unreachable!("{} is not next to a note in the scale", interval),
+        }
+    }
+}
+
+impl FromStr for RomanNumeral {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Error::InvalidRomanNumeral { numeral: s.to_string(), reason };
+        let (numeral, secondary) = match s.trim().split_once('/') {
+            Some((numeral, target)) => {
+                let target: RomanNumeral = target.parse()?;
+                if target.secondary.is_some() {
+                    return Err(invalid("only one `/` is allowed"));
+                }
+                (numeral, Some(Box::new(target)))
+            },
+            None => (s.trim(), None)
+        };
+
+        let mut rest = numeral;
+        let accidental = if let Some(r) = rest.strip_prefix(['b', '♭']) {
+            rest = r;
+            -1
+        } else if let Some(r) = rest.strip_prefix(['#', '♯']) {
+            rest = r;
+            1
+        } else {
+            0
+        };
+
+        // the longest numerals are listed first
+        let (degree, is_major) = [7, 6, 4, 5, 3, 2, 1]
+            .iter()
+            .find_map(|degree: &u8| {
+                let upper = NUMERALS[*degree as usize - 1];
+                let lower = upper.to_lowercase();
+                if rest.starts_with(upper) {
+                    Some((*degree, true))
+                } else if rest.starts_with(&lower) {
+                    Some((*degree, false))
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| invalid("expected a numeral from I to VII"))?;
+        rest = &rest[NUMERALS[degree as usize - 1].len()..];
+
+        let marker = match rest.chars().next() {
+            Some('°') | Some('o') if !rest.starts_with("omit") => Some('°'),
+            Some('ø') => Some('ø'),
+            Some('+') => Some('+'),
+            _ => None
+        };
+        if let Some(m) = rest.chars().next().filter(|_| marker.is_some()) {
+            rest = &rest[m.len_utf8()..];
+        }
+
+        let numeral = RomanNumeral { accidental, degree, is_major, marker, suffix: rest.to_string(), secondary };
+        numeral.chord_on_c()?;
+        Ok(numeral)
+    }
+}
+
+impl Display for RomanNumeral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let accidental = match self.accidental {
+            -1 => "b",
+            1 => "#",
+            _ => ""
+        };
+        let numeral = NUMERALS[self.degree as usize - 1];
+        match self.is_major {
+            true => write!(f, "{}{}", accidental, numeral)?,
+            false => write!(f, "{}{}", accidental, numeral.to_lowercase())?,
+        }
+        if let Some(marker) = self.marker {
+            write!(f, "{}", marker)?;
+        }
+        f.write_str(&self.suffix)?;
+        match &self.secondary {
+            Some(target) => write!(f, "/{}", target),
+            None => Ok(())
+        }
+    }
+}
+
+/// Parses a progression of Roman numerals separated by spaces, `-`, `|` or
+/// `,`, such as `ii7-V7-Imaj7`.
+pub fn parse_progression(progression: &str) -> Result<Vec<RomanNumeral>, Error> {
+    let numerals = progression
+        .split(|c: char| c.is_whitespace() || c == '|' || c == ',')
+        .flat_map(|part| split_dashes(part))
+        .filter(|numeral| !numeral.is_empty())
+        .map(|numeral| numeral.parse())
+        .collect::<Result<Vec<RomanNumeral>, Error>>()?;
+    match numerals.is_empty() {
+        true => Err(Error::InvalidRomanNumeral { numeral: progression.to_string(), reason: "there are no chords" }),
+        false => Ok(numerals)
+    }
+}
+
+/// Realizes a progression of Roman numerals as chords in `key`.
+pub fn realize_progression(progression: &str, key: &Key) -> Result<Vec<Chord>, Error> {
+    parse_progression(progression)?
+        .iter()
+        .map(|numeral| numeral.to_chord(key))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.parse().unwrap()
+    }
+
+    fn realize(progression: &str, k: &str) -> Vec<String> {
+        realize_progression(progression, &key(k))
+            .unwrap()
+            .iter()
+            .map(|chord| format!("{} ({})", chord.get_name(), chord.get_note_names()))
+            .collect()
+    }
+
+    #[test]
+    fn realizing_progressions() {
+        assert_eq!(
+            realize("ii7-V7-Imaj7", "Cmaj"),
+            ["D m7 (D, F, A, C)", "G 7 (G, B, D, F)", "C maj7 (C, E, G, B)"]
+        );
+        assert_eq!(
+            realize("i - VI - III - VII", "Amin"),
+            ["A m (A, C, E)", "F major (F, A, C)", "C major (C, E, G)", "G major (G, B, D)"]
+        );
+        assert_eq!(
+            realize("ii7-V7-Imaj7", "Bbmaj"),
+            ["C m7 (C, Eb, G, Bb)", "F 7 (F, A, C, Eb)", "Bb maj7 (Bb, D, F, A)"]
+        );
+        assert_eq!(realize("bVI bVII viiø7 #iv°", "Cmaj"), [
+            "Ab major (Ab, C, Eb)",
+            "Bb major (Bb, D, F)",
+            "B ø7 (B, D, F, A)",
+            "F# dim (F#, A, C)"
+        ]);
+        assert_eq!(realize("V7/V V7sus4", "Cmaj"), ["D 7 (D, F#, A, C)", "G 7sus4 (G, C, D, F)"]);
+    }
+
+    #[test]
+    fn parsing_errors() {
+        for numeral in ["", "VIII", "H", "V7/", "V/V/V", "IV#7", "x"] {
+            assert!(numeral.parse::<RomanNumeral>().is_err(), "`{}` should not parse", numeral);
+        }
+        assert!(parse_progression(" - ").is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        for numeral in ["ii7", "V7", "Imaj7", "bVII", "vii°7", "iiø7", "V7/V", "III+", "imaj7"] {
+            assert_eq!(numeral.parse::<RomanNumeral>().unwrap().to_string(), numeral);
+        }
+    }
+
+    #[test]
+    fn naming_chords() {
+        let name = |numeral: &str, k: &str| {
+            let k = key(k);
+            let chord = numeral.parse::<RomanNumeral>().unwrap().to_chord(&k).unwrap();
+            RomanNumeral::from_chord(&chord, &k).to_string()
+        };
+        for numeral in ["I", "ii7", "iii", "IVmaj7", "V7", "vi", "viiø7", "bVII", "#iv°", "bII", "V7sus4"] {
+            assert_eq!(name(numeral, "Ebmaj"), numeral);
+        }
+        for numeral in ["i", "ii°", "III", "iv7", "v", "VI", "VII", "#vii°7", "V7", "bII"] {
+            assert_eq!(name(numeral, "F#min"), numeral);
+        }
+    }
+
+    #[test]
+    fn functions() {
+        let function = |numeral: &str| numeral.parse::<RomanNumeral>().unwrap().function();
+        assert_eq!(function("I"), HarmonicFunction::Tonic);
+        assert_eq!(function("vi"), HarmonicFunction::Tonic);
+        assert_eq!(function("ii7"), HarmonicFunction::Subdominant);
+        assert_eq!(function("IV"), HarmonicFunction::Subdominant);
+        assert_eq!(function("V7"), HarmonicFunction::Dominant);
+        assert_eq!(function("vii°"), HarmonicFunction::Dominant);
+        assert_eq!(function("V7/V"), HarmonicFunction::Dominant);
+        assert_eq!(function("V7/ii"), HarmonicFunction::Subdominant);
+    }
+}
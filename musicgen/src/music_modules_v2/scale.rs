@@ -0,0 +1,327 @@
+//! The notes of the scales that chords can be pruned to.
+//!
+//! Like the chord tables, every scale is rooted on C and transposed along with
+//! the key. The modes are moved up to the tonic of major keys, since the chord
+//! tables of major keys are built on their relative minor.
+
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::Scale, error::Error, key::{Key, Mode, PitchClass}, music::notes::*};
+
+/// A set of pitch classes, where C is 0 and B is 11.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "PitchClassSetRepr", into = "Vec<u8>")]
+pub struct PitchClassSet(u16);
+
+impl PitchClassSet {
+    /// Every pitch class.
+    pub const CHROMATIC: PitchClassSet = PitchClassSet(0xfff);
+
+    pub fn from_pitch_classes<I: IntoIterator<Item = u8>>(pitch_classes: I) -> Self {
+        Self(pitch_classes.into_iter().fold(0, |set, pc| set | 1 << (pc % 12)))
+    }
+
+    /// Creates a set from the steps between each note of a scale, starting on
+    /// C. The steps may add up to an octave, in which case the last step leads
+    /// back to C.
+    pub fn from_intervals(intervals: &[u8]) -> Option<Self> {
+        let mut note = 0u8;
+        let mut notes = vec![0];
+        for interval in intervals {
+            note += interval;
+            if *interval == 0 || note > 12 {
+                return None;
+            }
+            notes.push(note);
+        }
+        Some(Self::from_pitch_classes(notes))
+    }
+
+    pub fn contains(&self, pitch_class: u8) -> bool {
+        pitch_class < 12 && self.0 & 1 << pitch_class != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Iterates over the pitch classes in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..12).filter(|pc| self.contains(*pc))
+    }
+
+    /// Returns the pitch classes that are not in this set.
+    pub fn complement(&self) -> Self {
+        Self(!self.0 & Self::CHROMATIC.0)
+    }
+
+    /// Returns the set moved up by `semitones`.
+    pub fn transposed(&self, semitones: u8) -> Self {
+        Self::from_pitch_classes(self.iter().map(|pc| pc + semitones % 12))
+    }
+
+    pub fn to_set(&self) -> HashSet<i16> {
+        self.iter().map(|pc| pc as i16).collect()
+    }
+}
+
+impl From<PitchClassSet> for Vec<u8> {
+    fn from(value: PitchClassSet) -> Self {
+        value.iter().collect()
+    }
+}
+
+impl FromStr for PitchClassSet {
+    type Err = Error;
+
+    /// Parses either a string of intervals separated by `-`, such as
+    /// `2-1-2-2-1-2-2`, or a list of pitch classes separated by commas or
+    /// spaces, such as `0, 2, 3, 5, 7, 8, 10` or `C D Eb F G Ab Bb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Error::InvalidScale { scale: s.to_string(), reason };
+        let s = s.trim();
+        let set = if s.contains('-') {
+            let intervals = s
+                .split('-')
+                .map(|interval| interval.trim().parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| invalid("expected numbers of semitones separated by `-`"))?;
+            Self::from_intervals(&intervals)
+                .ok_or_else(|| invalid("the intervals must be at least 1 and add up to at most 12"))?
+        } else {
+            let mut pitch_classes = Vec::new();
+            for note in s.split(|c: char| c == ',' || c.is_whitespace()).filter(|n| !n.is_empty()) {
+                let pitch_class = match note.parse::<u8>() {
+                    Ok(pc) if pc < 12 => pc,
+                    Ok(_) => return Err(invalid("pitch classes must be between 0 and 11")),
+                    Err(_) => note
+                        .parse::<PitchClass>()
+                        .map_err(|_| invalid("expected pitch classes or note names"))?
+                        .value()
+                };
+                pitch_classes.push(pitch_class);
+            }
+            Self::from_pitch_classes(pitch_classes)
+        };
+        match set.is_empty() {
+            true => Err(invalid("the scale does not contain any notes")),
+            false => Ok(set)
+        }
+    }
+}
+
+impl Display for PitchClassSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let notes = self.iter().map(|pc| pc.to_string()).collect::<Vec<String>>();
+        f.write_str(&notes.join(", "))
+    }
+}
+
+/// A custom scale can be written as a string or as an array of pitch classes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PitchClassSetRepr {
+    Text(String),
+    PitchClasses(Vec<u8>),
+}
+
+impl TryFrom<PitchClassSetRepr> for PitchClassSet {
+    type Error = Error;
+
+    fn try_from(value: PitchClassSetRepr) -> Result<Self, Self::Error> {
+        match value {
+            PitchClassSetRepr::Text(s) => s.parse(),
+            PitchClassSetRepr::PitchClasses(pitch_classes) => {
+                match pitch_classes.iter().all(|pc| *pc < 12) && !pitch_classes.is_empty() {
+                    true => Ok(Self::from_pitch_classes(pitch_classes)),
+                    false => Err(Error::InvalidScale {
+                        scale: format!("{:?}", pitch_classes),
+                        reason: "expected a non-empty list of pitch classes between 0 and 11"
+                    })
+                }
+            }
+        }
+    }
+}
+
+macro_rules! pitch_classes {
+    ($($note:expr),*) => {
+        Some(PitchClassSet::from_pitch_classes([$($note),*]))
+    };
+}
+
+impl Scale {
+    /// Returns the notes of this scale on C. `Disabled` does not have any
+    /// notes, and neither does `Custom`, whose notes are in the config.
+    pub fn pitch_classes(&self) -> Option<PitchClassSet> {
+        match self {
+            Scale::Disabled | Scale::Custom => None,
+            Scale::Natural => pitch_classes!(C, D, DSHARP, F, G, GSHARP, ASHARP),
+            Scale::Melodic => pitch_classes!(C, D, DSHARP, F, G, A, B),
+            Scale::Harmonic => pitch_classes!(C, D, DSHARP, F, G, GSHARP, B),
+            Scale::Pentatonic => pitch_classes!(C, DSHARP, F, G, ASHARP),
+            Scale::Romanian => pitch_classes!(C, D, DSHARP, FSHARP, G, A, ASHARP),
+            Scale::Hungarian => pitch_classes!(C, D, DSHARP, FSHARP, G, GSHARP, B),
+            Scale::AllNotes => Some(PitchClassSet::CHROMATIC),
+            Scale::HalfWhole => pitch_classes!(C, CSHARP, DSHARP, E, FSHARP, G, A, ASHARP),
+            Scale::WholeHalf => pitch_classes!(C, D, DSHARP, F, FSHARP, GSHARP, A, B),
+            Scale::Ionian => pitch_classes!(C, D, E, F, G, A, B),
+            Scale::Dorian => pitch_classes!(C, D, DSHARP, F, G, A, ASHARP),
+            Scale::Phrygian => pitch_classes!(C, CSHARP, DSHARP, F, G, GSHARP, ASHARP),
+            Scale::Lydian => pitch_classes!(C, D, E, FSHARP, G, A, B),
+            Scale::Mixolydian => pitch_classes!(C, D, E, F, G, A, ASHARP),
+            Scale::Locrian => pitch_classes!(C, CSHARP, DSHARP, F, FSHARP, GSHARP, ASHARP),
+            Scale::Blues => pitch_classes!(C, DSHARP, F, FSHARP, G, ASHARP),
+            Scale::MajorPentatonic => pitch_classes!(C, D, E, G, A),
+            Scale::Bebop => pitch_classes!(C, D, E, F, G, A, ASHARP, B),
+            Scale::Altered => pitch_classes!(C, CSHARP, DSHARP, E, FSHARP, GSHARP, ASHARP),
+            Scale::WholeTone => pitch_classes!(C, D, E, FSHARP, GSHARP, ASHARP),
+            Scale::PhrygianDominant => pitch_classes!(C, CSHARP, E, F, G, GSHARP, ASHARP),
+        }
+    }
+
+    /// Returns the number of semitones from the tonic of the chord table, 
+    /// which is the relative minor of major keys, up to the root of this scale 
+    /// in `key`. Every mode is rooted on the tonic of the key, and the other 
+    /// scales on the tonic of the chord table.
+    pub fn root(&self, key: &Key) -> u8 {
+        let is_mode = matches!(self, 
+            Scale::Ionian | Scale::Dorian | Scale::Phrygian | Scale::Lydian | Scale::Mixolydian | Scale::Locrian 
+            | Scale::MajorPentatonic | Scale::Bebop
+        );
+        match (is_mode, key.mode) {
+            (true, Mode::Major) => 3,
+            _ => 0
+        }
+    }
+
+    /// Returns the MIDI key signature of music in `key` that is pruned to this 
+    /// scale, as the number of sharps (or flats when negative) and whether it 
+    /// is minor.
+    ///
+    /// The scales are rooted on `Scale::root`. The church modes use the key 
+    /// signature of their parent major scale, and the other scales use the key 
+    /// itself.
+    pub fn key_signature(&self, key: &Key) -> (i8, bool) {
+        // the number of semitones from the root of the mode up to the tonic 
+        // of its parent major scale
+        let mode = match self {
+            Scale::Ionian | Scale::MajorPentatonic => Some((0, Mode::Major)),
+            Scale::Dorian => Some((10, Mode::Minor)),
+            Scale::Phrygian => Some((8, Mode::Minor)),
+            Scale::Lydian => Some((7, Mode::Major)),
+            Scale::Mixolydian | Scale::Bebop => Some((5, Mode::Major)),
+            Scale::Locrian => Some((1, Mode::Minor)),
+            _ => None
+        };
+        match mode {
+            Some((to_parent, mode)) => {
+                let parent = Key::new(PitchClass::new(key.offset() + self.root(key) as i16 + to_parent), Mode::Major);
+                (parent.fifths(), mode == Mode::Minor)
+            },
+            None => (key.fifths(), key.mode == Mode::Minor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_intervals() {
+        let natural: PitchClassSet = "2-1-2-2-1-2-2".parse().unwrap();
+        assert_eq!(Some(natural), Scale::Natural.pitch_classes());
+        // the last step back to C can be left out
+        let pentatonic: PitchClassSet = "3-2-2-3".parse().unwrap();
+        assert_eq!(Some(pentatonic), Scale::Pentatonic.pitch_classes());
+        assert!("2-2-2-2-2-2-2".parse::<PitchClassSet>().is_err());
+        assert!("2-0-2".parse::<PitchClassSet>().is_err());
+        assert!("2-a".parse::<PitchClassSet>().is_err());
+    }
+
+    #[test]
+    fn parsing_pitch_classes() {
+        let blues: PitchClassSet = "0, 3, 5, 6, 7, 10".parse().unwrap();
+        assert_eq!(Some(blues), Scale::Blues.pitch_classes());
+        let lydian: PitchClassSet = "C D E F# G A B".parse().unwrap();
+        assert_eq!(Some(lydian), Scale::Lydian.pitch_classes());
+        assert_eq!(lydian.to_string(), "0, 2, 4, 6, 7, 9, 11");
+        assert!("0 12".parse::<PitchClassSet>().is_err());
+        assert!("".parse::<PitchClassSet>().is_err());
+        assert!("C H".parse::<PitchClassSet>().is_err());
+    }
+
+    #[test]
+    fn every_scale_contains_c() {
+        for scale in Scale::ALL {
+            if let Some(notes) = scale.pitch_classes() {
+                assert!(notes.contains(C), "{} does not contain C", scale);
+            }
+        }
+        assert_eq!(Scale::Bebop.pitch_classes().unwrap().len(), 8);
+        assert_eq!(Scale::WholeTone.pitch_classes().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn key_signatures() {
+        let c_minor: Key = "Cmin".parse().unwrap();
+        assert_eq!(Scale::Disabled.key_signature(&c_minor), (-3, true));
+        assert_eq!(Scale::Harmonic.key_signature(&c_minor), (-3, true));
+        assert_eq!(Scale::Dorian.key_signature(&c_minor), (-2, true));
+        assert_eq!(Scale::Ionian.key_signature(&c_minor), (0, false));
+        assert_eq!(Scale::Lydian.key_signature(&c_minor), (1, false));
+        assert_eq!(Scale::Locrian.key_signature(&c_minor), (-5, true));
+        let e_major: Key = "Emaj".parse().unwrap();
+        assert_eq!(Scale::Natural.key_signature(&e_major), (4, false));
+        // E Mixolydian has the notes of A major
+        assert_eq!(Scale::Mixolydian.key_signature(&e_major), (3, false));
+        assert_eq!(Scale::Ionian.key_signature(&e_major), (4, false));
+        assert_eq!(Scale::Lydian.key_signature(&"Fmaj".parse().unwrap()), (0, false));
+    }
+
+    #[test]
+    fn modes_are_rooted_on_the_tonic() {
+        let c_major: Key = "Cmaj".parse().unwrap();
+        assert_eq!(Scale::Ionian.root(&c_major), 3);
+        assert_eq!(Scale::Natural.root(&c_major), 0);
+        assert_eq!(Scale::Ionian.root(&"Cmin".parse().unwrap()), 0);
+        // the chord table of C major is built on A, so C is 3 semitones up
+        let notes = Scale::Ionian.pitch_classes().unwrap().transposed(Scale::Ionian.root(&c_major));
+        let mut pitch_classes: Vec<i16> = notes.iter().map(|note| (note as i16 + c_major.offset()) % 12).collect();
+        pitch_classes.sort_unstable();
+        assert_eq!(pitch_classes, [0, 2, 4, 5, 7, 9, 11]);
+    }
+
+    #[test]
+    fn modes_share_the_tonic_of_major_keys() {
+        let e_flat_major: Key = "Ebmaj".parse().unwrap();
+        let notes = |scale: Scale| scale.pitch_classes().unwrap().transposed(scale.root(&e_flat_major));
+        // E flat Dorian is E flat Ionian with a minor third and seventh
+        let ionian = notes(Scale::Ionian);
+        let lowered = ionian.iter().map(|note| match (note + 12 - 3) % 12 {
+            4 | 11 => (note + 11) % 12,
+            _ => note
+        });
+        assert_eq!(notes(Scale::Dorian), PitchClassSet::from_pitch_classes(lowered));
+        // D flat major is the parent of E flat Dorian
+        assert_eq!(Scale::Dorian.key_signature(&e_flat_major), (-5, true));
+        assert_eq!(Scale::Ionian.key_signature(&e_flat_major), (-3, false));
+        assert_eq!(Scale::Locrian.root(&e_flat_major), Scale::Ionian.root(&e_flat_major));
+    }
+
+    #[test]
+    fn serde() {
+        let set: PitchClassSet = serde_json::from_str(r#""2-2-1-2-2-2-1""#).unwrap();
+        assert_eq!(Some(set), Scale::Ionian.pitch_classes());
+        let set: PitchClassSet = serde_json::from_str("[0, 4, 7]").unwrap();
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[0,4,7]");
+        assert!(serde_json::from_str::<PitchClassSet>("[0, 13]").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Scores.
+//!
+//! A `Score` is a generated song as notation: the notes of every track, the
+//! chord symbols, and the key, meter and tempo. It is built from the notes
+//! that each `MidiFile` collects, so every generation mode can be written as
+//! MusicXML, LilyPond or ABC notation.
+//!
+//! Every part is written as a single voice, where notes that sound together
+//! are written as chords, and notes that last past a barline or past the
+//! start of another note are split and tied. Start times and lengths are
+//! quantized to the shortest of `NOTE_LENGTHS`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::{config::NotationFormat, error::Error, key::{Key, Mode, NoteName, PitchClass}, meter::{Meter, TimeSignature}, midi::TrackSettings, music::PlacedChord};
+
+/// The note values that durations are written with, longest first, as their
+/// length in beats, their type and whether they are dotted. The types are
+/// named like MusicXML names them.
+const NOTE_LENGTHS: [(f64, &str, bool); 8] = [
+    (4.0, "whole", false),
+    (3.0, "half", true),
+    (2.0, "half", false),
+    (1.5, "quarter", true),
+    (1.0, "quarter", false),
+    (0.75, "eighth", true),
+    (0.5, "eighth", false),
+    (0.25, "16th", false),
+];
+
+/// The number of divisions in a beat, so that the shortest of `NOTE_LENGTHS`
+/// is one division.
+pub const DIVISIONS: u32 = 4;
+
+/// The order that sharps are added to a key signature. Flats are added in the
+/// opposite order.
+const SHARPS: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// A note of a part. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreNote {
+    pub pitch: u8,
+    pub start: f64,
+    pub length: f64,
+}
+
+/// A track of a score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScorePart {
+    /// The name, channel and instrument of the part. Parts on channel 10 are
+    /// written as percussion.
+    pub settings: TrackSettings,
+    pub notes: Vec<ScoreNote>,
+}
+
+/// The clef of a part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clef {
+    Treble,
+    Bass,
+    Percussion,
+}
+
+impl ScorePart {
+    pub fn is_percussion(&self) -> bool {
+        self.settings.channel == 9
+    }
+
+    /// The bass clef for parts that are mostly below middle C, and the
+    /// treble clef otherwise.
+    pub fn clef(&self) -> Clef {
+        if self.is_percussion() {
+            return Clef::Percussion;
+        }
+        let average_pitch = self.notes.iter().map(|note| note.pitch as f64).sum::<f64>() / self.notes.len().max(1) as f64;
+        match average_pitch < 60.0 && !self.notes.is_empty() {
+            true => Clef::Bass,
+            false => Clef::Treble
+        }
+    }
+}
+
+/// A note of a `ScoreEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TiedNote {
+    pub pitch: u8,
+    /// Continues the same note of the event before it.
+    pub tie_stop: bool,
+    /// Continues into the same note of the event after it.
+    pub tie_start: bool,
+}
+
+/// A note, chord or rest of a part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreEvent<'a> {
+    /// The start time in divisions.
+    pub start: u32,
+    /// The length in divisions, which is one of `NOTE_LENGTHS`.
+    pub duration: u32,
+    /// The type of the note value, such as `quarter` or `16th`.
+    pub kind: &'static str,
+    pub dotted: bool,
+    /// The notes from the lowest to the highest, or none for a rest.
+    pub notes: Vec<TiedNote>,
+    /// The chords that start with this event. Chords are only given for the
+    /// first part.
+    pub chords: Vec<&'a PlacedChord>,
+}
+
+/// A song that can be written as notation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    pub title: Option<String>,
+    pub bpm: f64,
+    pub time_signature: TimeSignature,
+    /// The number of sharps, or flats when negative, and whether the key is
+    /// minor.
+    pub key_signature: (i8, bool),
+    /// The key that the notes are spelled in.
+    pub key: Key,
+    /// The chords, which are written as chord symbols above the first part.
+    pub chords: Vec<PlacedChord>,
+    pub parts: Vec<ScorePart>,
+}
+
+/// Converts beats to divisions, rounding to the nearest division.
+pub fn quantize(beats: f64) -> u32 {
+    (beats * DIVISIONS as f64).round().max(0.0) as u32
+}
+
+/// Checks that a bar of `time_signature` is a whole number of divisions
+/// long, since the barlines of a score would be misplaced otherwise.
+pub fn check_time_signature(time_signature: TimeSignature) -> Result<(), Error> {
+    match (time_signature.numerator() as u32 * 4 * DIVISIONS).is_multiple_of(time_signature.denominator() as u32) {
+        true => Ok(()),
+        false => Err(Error::InvalidTimeSignature {
+            time_signature: time_signature.to_string(),
+            reason: "a score can only be written when a bar is a whole number of 16th notes long"
+        })
+    }
+}
+
+/// Splits a duration in divisions into note values from `NOTE_LENGTHS`,
+/// longest first. Each note value is its duration in divisions, its type and
+/// whether it is dotted.
+pub fn note_values(mut duration: u32) -> Vec<(u32, &'static str, bool)> {
+    let mut values = Vec::new();
+    while duration > 0 {
+        let value = NOTE_LENGTHS
+            .iter()
+            .map(|(beats, kind, dotted)| (quantize(*beats), *kind, *dotted))
+            .find(|(length, ..)| *length <= duration)
+            .expect("the shortest note value is one division");
+        values.push(value);
+        duration -= value.0;
+    }
+    values
+}
+
+/// How many semitones a key signature with `fifths` sharps, or flats when
+/// negative, raises `letter`.
+fn key_alter(fifths: i8, letter: char) -> i8 {
+    let position = SHARPS.iter().position(|sharp| *sharp == letter).expect("the letter of a note") as i8;
+    match fifths >= 0 {
+        true => (position < fifths) as i8,
+        false => -((6 - position < -fifths) as i8)
+    }
+}
+
+/// The accidentals that have been written in a measure, for formats where
+/// accidentals last until the end of the measure.
+#[derive(Clone, Debug, Default)]
+pub struct Accidentals {
+    fifths: i8,
+    /// The alteration of each letter and octave.
+    written: HashMap<(char, i32), i8>,
+}
+
+impl Accidentals {
+    /// Starts with the accidentals of a key signature with `fifths` sharps,
+    /// or flats when negative.
+    pub fn new(fifths: i8) -> Self {
+        Accidentals { fifths, written: HashMap::new() }
+    }
+
+    /// Goes back to the key signature, at the start of a measure.
+    pub fn clear(&mut self) {
+        self.written.clear();
+    }
+
+    /// Returns the accidental to write on a note, or `None` when the key
+    /// signature or an earlier note of the measure already has it. The second
+    /// note of a tie does not get an accidental.
+    pub fn next(&mut self, name: NoteName, octave: i32, tie_stop: bool) -> Option<i8> {
+        let previous = self.written
+            .insert((name.letter(), octave), name.accidental())
+            .unwrap_or_else(|| key_alter(self.fifths, name.letter()));
+        (previous != name.accidental() && !tie_stop).then_some(name.accidental())
+    }
+}
+
+impl Score {
+    /// Writes the score in `format`.
+    pub fn write(&self, format: NotationFormat) -> String {
+        match format {
+            NotationFormat::MusicXml => self.to_musicxml(),
+            NotationFormat::LilyPond => self.to_lilypond(),
+            NotationFormat::Abc => self.to_abc()
+        }
+    }
+
+    /// The length of a measure in divisions, which is exact for the time 
+    /// signatures that `check_time_signature` accepts.
+    pub fn measure_length(&self) -> u32 {
+        quantize(Meter::new(self.time_signature, 1.0).unwrap_or_default().bar_length())
+    }
+
+    /// The number of measures, which is at least one.
+    pub fn measure_count(&self) -> u32 {
+        let end = self.parts
+            .iter()
+            .flat_map(|part| part.notes.iter())
+            .map(|note| quantize(note.start + note.length).max(quantize(note.start) + 1))
+            .chain(self.chords.iter().map(|chord| quantize(chord.start + chord.length)))
+            .max()
+            .unwrap_or(0);
+        end.div_ceil(self.measure_length()).max(1)
+    }
+
+    /// The name of the part at `index`. The chord track is called `Chords`
+    /// when it does not have a name.
+    pub fn part_name(&self, index: usize) -> String {
+        self.parts[index].settings.name.clone().unwrap_or_else(|| match index {
+            0 => "Chords".to_string(),
+            _ => format!("Part {}", index + 1)
+        })
+    }
+
+    /// The tonic of the key signature.
+    pub fn key_signature_tonic(&self) -> Key {
+        let (fifths, is_minor) = self.key_signature;
+        let mode = if is_minor { Mode::Minor } else { Mode::Major };
+        Key::from_fifths(fifths, mode).unwrap_or(self.key)
+    }
+
+    /// Spells a MIDI note in the key of the score, returning its name and its
+    /// octave, where middle C is in octave 4.
+    pub fn spell(&self, pitch: u8) -> (NoteName, i32) {
+        let name = self.key.spell(PitchClass::new(pitch as i16));
+        (name, (pitch as i32 - name.accidental() as i32) / 12 - 1)
+    }
+
+    /// Splits the part at `index` into the events of each measure.
+    pub fn measures(&self, index: usize) -> Vec<Vec<ScoreEvent<'_>>> {
+        let (measure, measures) = (self.measure_length(), self.measure_count());
+        let notes: Vec<(u8, u32, u32)> = self.parts[index].notes
+            .iter()
+            .map(|note| {
+                let start = quantize(note.start);
+                (note.pitch, start, quantize(note.start + note.length).max(start + 1))
+            })
+            .collect();
+        let chords: &[PlacedChord] = match index {
+            0 => &self.chords,
+            _ => &[]
+        };
+        let mut boundaries: BTreeSet<u32> = (0..=measures).map(|i| i * measure).collect();
+        boundaries.extend(notes.iter().flat_map(|(_, start, end)| [*start, *end]));
+        boundaries.extend(chords.iter().map(|chord| quantize(chord.start)));
+        let boundaries: Vec<u32> = boundaries.into_iter().filter(|time| *time <= measures * measure).collect();
+
+        let mut events: Vec<Vec<ScoreEvent>> = (0..measures).map(|_| Vec::new()).collect();
+        for window in boundaries.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            // the pitches that sound for the whole window, and whether they
+            // are tied to the notes before and after it
+            let mut sounding: Vec<(u8, bool, bool)> = Vec::new();
+            for (pitch, note_start, note_end) in notes.iter().filter(|(_, note_start, note_end)| *note_start <= start && *note_end >= end) {
+                if !sounding.iter().any(|(sounding_pitch, ..)| sounding_pitch == pitch) {
+                    sounding.push((*pitch, *note_start < start, *note_end > end));
+                }
+            }
+            sounding.sort_unstable();
+            let mut time = start;
+            for (duration, kind, dotted) in note_values(end - start) {
+                events[(time / measure) as usize].push(ScoreEvent {
+                    start: time,
+                    duration,
+                    kind,
+                    dotted,
+                    notes: sounding
+                        .iter()
+                        .map(|(pitch, tied_before, tied_after)| TiedNote {
+                            pitch: *pitch,
+                            tie_stop: *tied_before || time > start,
+                            tie_start: *tied_after || time + duration < end,
+                        })
+                        .collect(),
+                    chords: match time == start {
+                        true => chords.iter().filter(|chord| quantize(chord.start) == start).collect(),
+                        false => Vec::new()
+                    },
+                });
+                time += duration;
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_values_and_key_signatures() {
+        let kinds = |beats: f64| note_values(quantize(beats)).iter().map(|(_, kind, dotted)| format!("{}{}", kind, if *dotted { "." } else { "" })).collect::<Vec<String>>();
+        assert_eq!(kinds(4.0), ["whole"]);
+        assert_eq!(kinds(2.5), ["half", "eighth"]);
+        assert_eq!(kinds(3.5), ["half.", "eighth"]);
+        // rounded to a sixteenth note
+        assert_eq!(kinds(0.3), ["16th"]);
+        assert!(kinds(0.1).is_empty());
+
+        // C minor has B, E and A flat
+        assert_eq!(['B', 'E', 'A', 'D'].map(|letter| key_alter(-3, letter)), [-1, -1, -1, 0]);
+        // E major has F, C, G and D sharp
+        assert_eq!(['F', 'C', 'G', 'D', 'A'].map(|letter| key_alter(4, letter)), [1, 1, 1, 1, 0]);
+        assert_eq!(key_alter(0, 'F'), 0);
+    }
+
+    #[test]
+    fn measures_are_split_and_tied() {
+        let score = Score {
+            title: None,
+            bpm: 120.0,
+            time_signature: "3/4".parse().unwrap(),
+            key_signature: (0, false),
+            key: "Amin".parse().unwrap(),
+            chords: vec![PlacedChord { name: "A minor".to_string(), root: 9, pitch_classes: vec![9, 0, 4], start: 0.0, length: 3.0 }],
+            parts: vec![ScorePart {
+                settings: TrackSettings::default(),
+                // a note over the barline, and another note that starts while
+                // it is playing
+                notes: vec![ScoreNote { pitch: 57, start: 0.0, length: 5.0 }, ScoreNote { pitch: 64, start: 1.0, length: 0.5 }],
+            }],
+        };
+        assert_eq!(score.measure_count(), 2);
+        let measures = score.measures(0);
+        let events: Vec<Vec<(u32, Vec<u8>, bool)>> = measures
+            .iter()
+            .map(|events| events.iter().map(|event| (event.duration, event.notes.iter().map(|note| note.pitch).collect(), event.notes.iter().any(|note| note.tie_start))).collect())
+            .collect();
+        assert_eq!(events, [
+            vec![(4, vec![57], true), (2, vec![57, 64], true), (6, vec![57], true)],
+            vec![(8, vec![57], false), (4, vec![], false)],
+        ]);
+        assert_eq!(measures[0][0].chords.len(), 1);
+        assert!(!measures[0][1].notes[1].tie_start && measures[0][1].notes[0].tie_stop);
+        assert_eq!(measures[0][2].kind, "quarter");
+        assert!(measures[0][2].dotted);
+        assert_eq!(score.part_name(0), "Chords");
+        assert_eq!(score.parts[0].clef(), Clef::Treble);
+
+        let (name, octave) = score.spell(61);
+        assert_eq!((name.to_string(), octave), ("C#".to_string(), 4));
+        let mut accidentals = Accidentals::new(-1);
+        let b_flat = "Bb".parse::<Key>().unwrap().tonic_name();
+        assert_eq!(accidentals.next(b_flat, 3, false), None);
+        assert_eq!(accidentals.next(score.spell(59).0, 3, false), Some(0));
+        assert_eq!(accidentals.next(score.spell(59).0, 3, false), None);
+        accidentals.clear();
+        assert_eq!(accidentals.next(score.spell(59).0, 3, true), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Strummed and rolled chords.
+//!
+//! Rather than starting every note of a chord on the same tick, a strum
+//! starts each note a little after the one before it, like the strings of a
+//! guitar. A roll does the same once per chord with a wider spread, like a
+//! harp.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::StrumDirection, error::Error, meter::Meter, utils::MathMagician};
+
+/// The settings for `GenerationMode::Strum`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Strum {
+    pub direction: StrumDirection,
+    /// The time between the onsets of two notes, in beats.
+    pub spread: f64,
+    /// How much each onset can randomly move, as a fraction of `spread`.
+    pub variation: f64,
+    /// Rolls the chord once instead of strumming it on every beat.
+    pub roll: bool,
+}
+
+impl Default for Strum {
+    fn default() -> Self {
+        Strum { direction: StrumDirection::Alternating, spread: 0.03, variation: 0.3, roll: false }
+    }
+}
+
+/// A note of a strum. The times are in beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrummedNote {
+    pub start: f64,
+    pub length: f64,
+    pub pitch: u8,
+    pub velocity: u8,
+}
+
+impl Strum {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&self.spread) {
+            return Err(Error::InvalidInput(format!("the spread of a strum must be between 0 and 1 beat, but it is {}", self.spread)));
+        }
+        if !(0.0..=1.0).contains(&self.variation) {
+            return Err(Error::InvalidInput(format!("the variation of a strum must be between 0 and 1, but it is {}", self.variation)));
+        }
+        Ok(())
+    }
+
+    /// Strums `notes` for a chord that lasts the chord length of `meter`.
+    /// Every note is held until the next strum, and down strums are played a
+    /// little harder than up strums.
+    pub fn notes_in(&self, notes: &[u8], meter: &Meter, math_magician: &mut MathMagician) -> Vec<StrummedNote> {
+        let mut sorted = notes.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let chord_length = meter.chord_length();
+        let strum_length = match self.roll {
+            true => chord_length,
+            false => meter.beat_length()
+        };
+
+        let mut result = Vec::new();
+        let mut strum = 0;
+        while (strum as f64) * strum_length < chord_length {
+            let start = strum as f64 * strum_length;
+            let length = strum_length.min(chord_length - start);
+            let is_down = match self.direction {
+                StrumDirection::Down => true,
+                StrumDirection::Up => false,
+                StrumDirection::Alternating => strum % 2 == 0
+            };
+            let order: Box<dyn Iterator<Item = &u8>> = match is_down {
+                true => Box::new(sorted.iter()),
+                false => Box::new(sorted.iter().rev())
+            };
+            // the last note still has to start before the strum ends
+            let spread = self.spread.min(length / 2.0 / sorted.len().max(1) as f64);
+            let mut offset = 0.0;
+            for (i, pitch) in order.enumerate() {
+                if i > 0 {
+                    let variation = math_magician.big_decision(0, 200) as f64 / 100.0 - 1.0;
+                    offset += spread * (1.0 + self.variation * variation);
+                }
+                result.push(StrummedNote {
+                    start: start + offset,
+                    length: length - offset,
+                    pitch: *pitch,
+                    velocity: if is_down { 85 } else { 75 },
+                });
+            }
+            strum += 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strum(strum: Strum, time_signature: &str) -> Vec<StrummedNote> {
+        let meter = Meter::new(time_signature.parse().unwrap(), 1.0).unwrap();
+        strum.notes_in(&[64, 60, 67], &meter, &mut MathMagician::share_hash([0; 32]))
+    }
+
+    #[test]
+    fn alternating_strums() {
+        let notes = strum(Strum { spread: 0.05, variation: 0.0, ..Default::default() }, "4/4");
+        assert_eq!(notes.len(), 12);
+        let pitches: Vec<u8> = notes.iter().map(|note| note.pitch).collect();
+        assert_eq!(pitches[..6], [60, 64, 67, 67, 64, 60]);
+        assert!((notes[1].start - 0.05).abs() < 1e-9);
+        assert!((notes[5].start - 1.1).abs() < 1e-9);
+        // every note ends with the beat
+        assert!(notes.iter().all(|note| ((note.start + note.length) % 1.0).abs() < 1e-9));
+        assert_eq!((notes[0].velocity, notes[3].velocity), (85, 75));
+    }
+
+    #[test]
+    fn rolls_and_variation() {
+        let notes = strum(Strum { direction: StrumDirection::Up, spread: 0.25, roll: true, ..Default::default() }, "6/8");
+        assert_eq!(notes.iter().map(|note| note.pitch).collect::<Vec<u8>>(), [67, 64, 60]);
+        assert!(notes.windows(2).all(|pair| pair[0].start < pair[1].start));
+        assert!(notes.iter().all(|note| (note.start + note.length - 3.0).abs() < 1e-9));
+
+        // 6/8 is strummed on every eighth note
+        assert_eq!(strum(Strum::default(), "6/8").len(), 18);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Strum::default().validate().is_ok());
+        assert!(Strum { spread: -0.1, ..Default::default() }.validate().is_err());
+        assert!(Strum { variation: 2.0, ..Default::default() }.validate().is_err());
+    }
+}
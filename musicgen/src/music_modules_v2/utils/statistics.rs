@@ -68,23 +68,20 @@ impl Music {
 
 #[cfg(test)]
 mod tests {
+    use crate::music_modules_v2::config::{GenerationConfig, TableScheme};
+
     use super::*;
 
     #[test]
     fn set_probabilities_test() {
-        let key = "Cmin";
-        let scale = "disabled";
-        let mut musician = Music::smoke_hash(Default::default(), &key, &HashSet::new(), "default", &scale, true, false).unwrap();
+        let config = GenerationConfig::builder()
+            .key("Cmin")
+            .is_reproducible(true)
+            .build();
+        let mut musician = Music::smoke_hash(Default::default(), &config).unwrap();
         //let mut musician = Music::smoke_hash_all_pruning_chords(key, &scale);
         
-        let table_scheme = "contains_note";
-
-        match table_scheme {
-            "contains_note" => musician.rotate_chords("F#min"),
-            "highest_note" => musician.rearrange_by_highest_note("F#min"),
-            "lowest_note" => musician.rearrange_by_lowest_note("F#min"),
-            _ => panic!()
-        }
+        musician.arrange_chord_table("F#min", TableScheme::ContainsNote).unwrap();
     
         let chord_list_len = musician.chord_list.len();
         musician.set_probabilities();
@@ -4,12 +4,12 @@ use std::collections::HashSet;
 
 use sha2::{digest::Output, Sha256};
 
-use crate::music_modules_v2::{chord::Chord, Music};
+use crate::music_modules_v2::{chord::Chord, config::{ChordTypeGroup, GenerationConfig, Scale}, Music};
 
 impl Music {
     /// Creates a Music struct with all chords and with pruning chords.
     #[allow(unused)]
-    pub fn smoke_hash_all_pruning_chords(key: &str, scale: &str) -> Self {
+    pub fn smoke_hash_all_pruning_chords(key: &str, scale: Scale) -> Self {
         let chord_selection = HashSet::from_iter([
             "minor7",
             "major7",
@@ -29,7 +29,14 @@ impl Music {
             "dominant9",
             "add9"
         ].iter().map(|str| str.to_string()));
-        let mut musician = Music::smoke_hash(Default::default(), key, &chord_selection, "custom_pruning", scale, true, false).unwrap();
+        let config = GenerationConfig::builder()
+            .key(key)
+            .chord_type_group(ChordTypeGroup::CustomPruning)
+            .chord_selection(chord_selection)
+            .scale(scale)
+            .is_reproducible(true)
+            .build();
+        let mut musician = Music::smoke_hash(Default::default(), &config).unwrap();
         musician
     }
 
@@ -54,7 +61,13 @@ impl Music {
             "dominant9",
             "add9"
         ].iter().map(|str| str.to_string()));
-        let mut musician = Music::smoke_hash(hash, key, &chord_selection, "custom", "disabled", true, false);
+        let config = GenerationConfig::builder()
+            .key(key)
+            .chord_type_group(ChordTypeGroup::Custom)
+            .chord_selection(chord_selection)
+            .is_reproducible(true)
+            .build();
+        let mut musician = Music::smoke_hash(hash, &config);
         musician.unwrap()
     }
 }
@@ -74,7 +87,7 @@ mod tests {
 
     #[test]
     fn chords_are_present() {
-        let m = Music::smoke_hash_all_pruning_chords("Cmin", "pentatonic");
+        let m = Music::smoke_hash_all_pruning_chords("Cmin", Scale::Pentatonic);
         assert!(m.chord_list.len() > 0);
     }
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use std::time::SystemTime;
 
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::music_modules_v2::{chord::Chord, music::KEYS};
+use crate::music_modules_v2::{chord::Chord, error::Error, key::Key};
 
 /**
  * Convert beats to ticks
@@ -63,6 +63,22 @@ impl MathMagician {
         let index: i16 = self.noggin.gen_range(0..columns.len() as i16);
         return columns[index as usize]
     }
+
+    /// Math magician picks an index, where each index is picked with a 
+    /// probability proportional to its weight. The weights must not all be 
+    /// zero.
+    pub fn weighted_decision(&mut self, weights: &[f64]) -> usize {
+        let total: f64 = weights.iter().sum();
+        let mut target = self.noggin.gen_range(0.0..total);
+        for (index, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                return index;
+            }
+            target -= weight;
+        }
+        // rounding errors can leave a tiny bit of the target
+        weights.iter().rposition(|weight| *weight > 0.0).unwrap_or(0)
+    }
 }
 
 #[allow(unused)]
@@ -70,10 +86,12 @@ pub fn time_ms() -> u128 {
     return SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
 }
 
-/// Returns the index of the `note_lengths` array where `note_lengths[i] == 4.0 
-/// \- total_time`.
+/// Returns the index of the `note_lengths` array where `note_lengths[i] == 
+/// chord_length - total_time`.
+/// 
+/// `note_lengths = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0, ...]`
 /// 
-/// `note_lengths = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0]`
+/// When less than half a beat is left, the index is 0.
 /// 
 /// The original way that I did this was like so:
 /// 
@@ -89,34 +107,35 @@ pub fn time_ms() -> u128 {
 /// i = 2 * note_lengths(i) - 1
 /// ```
 #[inline(always)]
-pub fn get_max_note_length_index(total_time: f64) -> u16 {
-    let max_value = 4f64 - total_time;
+pub fn get_max_note_length_index(total_time: f64, chord_length: f64) -> u16 {
+    let max_value = chord_length - total_time;
     return (max_value * 2f64 - 1f64) as u16;
 }
 
-/// The key is expected to be of this form
+/// Parses a key signature. The key is expected to be of this form
 /// 
 /// ```txt
 /// Dmaj
-/// D#maj
-/// Bmin
+/// Ebmaj
+/// Bb minor
+/// c#m
+/// F## major
+/// D#
 /// ```
 /// 
-/// The keys cannot be flat, such as `Bb` or `Eb`.
-#[inline(always)]
-pub fn parse_key(key: &str) -> i16 {
-    if key.ne("random") {
-        let is_sharp = key.chars().nth(1).unwrap_or(' ') == '#';
-        let start_idx = if is_sharp {2} else {1};
-        let is_major = key[start_idx..] == *"maj";
-        for (i, k) in KEYS.iter().enumerate() {
-            let len = std::cmp::min(k.len(), key.len());
-            if key[..len] == **k {
-                return  (i as i16 + is_major as i16 * 3 + is_sharp as i16) % 12;
-            }
-        }
+/// A key without a mode is minor. `random` is parsed as C minor.
+pub fn parse_key_signature(key: &str) -> Result<Key, Error> {
+    if key.eq("random") {
+        return Ok(Key::default());
     }
-    return 0;
+    key.parse()
+}
+
+/// Parses a key, returning the number of semitones to transpose the chord 
+/// table by. See `parse_key_signature` for the accepted keys.
+#[inline(always)]
+pub fn parse_key(key: &str) -> Result<i16, Error> {
+    Ok(parse_key_signature(key)?.offset())
 }
 
 #[cfg(test)]
@@ -125,45 +144,66 @@ mod tests {
 
     #[test]
     fn parsing_notes_with_parse_key() {
-        assert_eq!(parse_key("C"), 0);
-        assert_eq!(parse_key("C#"), 1);
-        assert_eq!(parse_key("D"), 2);
-        assert_eq!(parse_key("D#"), 3);
-        assert_eq!(parse_key("E"), 4);
-        assert_eq!(parse_key("F"), 5);
-        assert_eq!(parse_key("F#"), 6);
-        assert_eq!(parse_key("G"), 7);
-        assert_eq!(parse_key("G#"), 8);
-        assert_eq!(parse_key("A"), 9);
-        assert_eq!(parse_key("A#"), 10);
-        assert_eq!(parse_key("B"), 11);
+        assert_eq!(parse_key("C").unwrap(), 0);
+        assert_eq!(parse_key("C#").unwrap(), 1);
+        assert_eq!(parse_key("D").unwrap(), 2);
+        assert_eq!(parse_key("D#").unwrap(), 3);
+        assert_eq!(parse_key("E").unwrap(), 4);
+        assert_eq!(parse_key("F").unwrap(), 5);
+        assert_eq!(parse_key("F#").unwrap(), 6);
+        assert_eq!(parse_key("G").unwrap(), 7);
+        assert_eq!(parse_key("G#").unwrap(), 8);
+        assert_eq!(parse_key("A").unwrap(), 9);
+        assert_eq!(parse_key("A#").unwrap(), 10);
+        assert_eq!(parse_key("B").unwrap(), 11);
     }
     #[test]
     fn parsing_keys() {
-        assert_eq!(parse_key("Cmin"), 0);
-        assert_eq!(parse_key("C#min"), 1);
-        assert_eq!(parse_key("Dmin"), 2);
-        assert_eq!(parse_key("D#min"), 3);
-        assert_eq!(parse_key("Emin"), 4);
-        assert_eq!(parse_key("Fmin"), 5);
-        assert_eq!(parse_key("F#min"), 6);
-        assert_eq!(parse_key("Gmin"), 7);
-        assert_eq!(parse_key("G#min"), 8);
-        assert_eq!(parse_key("Amin"), 9);
-        assert_eq!(parse_key("A#min"), 10);
-        assert_eq!(parse_key("Bmin"), 11);
-
-        assert_eq!(parse_key("Cmaj"), 3);
-        assert_eq!(parse_key("C#maj"), 4);
-        assert_eq!(parse_key("Dmaj"), 5);
-        assert_eq!(parse_key("D#maj"), 6);
-        assert_eq!(parse_key("Emaj"), 7);
-        assert_eq!(parse_key("Fmaj"), 8);
-        assert_eq!(parse_key("F#maj"), 9);
-        assert_eq!(parse_key("Gmaj"), 10);
-        assert_eq!(parse_key("G#maj"), 11);
-        assert_eq!(parse_key("Amaj"), 0);
-        assert_eq!(parse_key("A#maj"), 1);
-        assert_eq!(parse_key("Bmaj"), 2);
+        assert_eq!(parse_key("Cmin").unwrap(), 0);
+        assert_eq!(parse_key("C#min").unwrap(), 1);
+        assert_eq!(parse_key("Dmin").unwrap(), 2);
+        assert_eq!(parse_key("D#min").unwrap(), 3);
+        assert_eq!(parse_key("Emin").unwrap(), 4);
+        assert_eq!(parse_key("Fmin").unwrap(), 5);
+        assert_eq!(parse_key("F#min").unwrap(), 6);
+        assert_eq!(parse_key("Gmin").unwrap(), 7);
+        assert_eq!(parse_key("G#min").unwrap(), 8);
+        assert_eq!(parse_key("Amin").unwrap(), 9);
+        assert_eq!(parse_key("A#min").unwrap(), 10);
+        assert_eq!(parse_key("Bmin").unwrap(), 11);
+
+        // major keys use their relative minor
+        assert_eq!(parse_key("Cmaj").unwrap(), 9);
+        assert_eq!(parse_key("C#maj").unwrap(), 10);
+        assert_eq!(parse_key("Dmaj").unwrap(), 11);
+        assert_eq!(parse_key("D#maj").unwrap(), 0);
+        assert_eq!(parse_key("Emaj").unwrap(), 1);
+        assert_eq!(parse_key("Fmaj").unwrap(), 2);
+        assert_eq!(parse_key("F#maj").unwrap(), 3);
+        assert_eq!(parse_key("Gmaj").unwrap(), 4);
+        assert_eq!(parse_key("G#maj").unwrap(), 5);
+        assert_eq!(parse_key("Amaj").unwrap(), 6);
+        assert_eq!(parse_key("A#maj").unwrap(), 7);
+        assert_eq!(parse_key("Bmaj").unwrap(), 8);
+    }
+
+    #[test]
+    fn parsing_flats_and_long_names() {
+        assert_eq!(parse_key("Bbmaj").unwrap(), 7);
+        assert_eq!(parse_key("Eb minor").unwrap(), 3);
+        assert_eq!(parse_key("c#m").unwrap(), 1);
+        assert_eq!(parse_key("C minor").unwrap(), 0);
+        assert_eq!(parse_key("Cmajor").unwrap(), 9);
+        assert_eq!(parse_key("c").unwrap(), 0);
+        assert_eq!(parse_key("Abb aeolian").unwrap(), 7);
+        assert_eq!(parse_key("random").unwrap(), 0);
+        assert_eq!(parse_key_signature("D#maj").unwrap().to_string(), "Eb major");
+    }
+
+    #[test]
+    fn invalid_keys() {
+        for key in ["", "H", "Cmajor7", "#maj", "C###", "X#min", "C mi", "Random"] {
+            assert!(parse_key(key).is_err(), "`{}` should not parse", key);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,215 @@
+//! Voice leading.
+//!
+//! Given the notes of the previous chord, the next chord is voiced by trying
+//! every inversion, drop voicing and octave of it that fits in the range, and
+//! keeping the one whose notes move the fewest semitones in total.
+
+use serde::{Deserialize, Serialize};
+
+/// The settings for voicing chords.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceLeading {
+    /// The lowest MIDI note that a voicing may use.
+    pub lowest_note: u8,
+    /// The highest MIDI note that a voicing may use.
+    pub highest_note: u8,
+    /// The most semitones that any voice should move between two chords.
+    /// When no voicing is within the limit, the smoothest one is used anyway.
+    pub max_leap: u8,
+    /// Also tries drop-2 and drop-3 voicings of chords with 4 or more notes.
+    pub drop_voicings: bool,
+}
+
+impl Default for VoiceLeading {
+    fn default() -> Self {
+        VoiceLeading { lowest_note: 40, highest_note: 84, max_leap: 7, drop_voicings: true }
+    }
+}
+
+/// How far apart two voicings are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VoiceLeadingCost {
+    /// The total number of semitones that the voices move.
+    pub movement: u32,
+    /// The most semitones that a single voice moves.
+    pub max_leap: u8,
+}
+
+impl VoiceLeading {
+    /// Returns every voicing of `notes` that fits in the range, sorted from
+    /// low to high. Each voicing is sorted from its lowest note up.
+    pub fn voicings(&self, notes: &[i16]) -> Vec<Vec<u8>> {
+        let mut notes: Vec<i16> = notes.iter().map(|note| note.rem_euclid(128)).collect();
+        notes.sort_unstable();
+        notes.dedup();
+        if notes.is_empty() {
+            return Vec::new();
+        }
+
+        // the inversions keep the spacing of the chord, and move the lowest
+        // notes up an octave
+        let mut shapes: Vec<Vec<i16>> = Vec::new();
+        for inversion in 0..notes.len() {
+            let mut shape: Vec<i16> = notes
+                .iter()
+                .enumerate()
+                .map(|(i, note)| if i < inversion { note + 12 } else { *note })
+                .collect();
+            shape.sort_unstable();
+            shapes.push(shape);
+        }
+        if self.drop_voicings && notes.len() >= 4 {
+            for shape in shapes.clone() {
+                for drop in [2, 3] {
+                    let mut dropped = shape.clone();
+                    let index = dropped.len() - drop;
+                    dropped[index] -= 12;
+                    dropped.sort_unstable();
+                    shapes.push(dropped);
+                }
+            }
+        }
+
+        let mut voicings: Vec<Vec<u8>> = Vec::new();
+        for shape in shapes {
+            let lowest = shape[0];
+            // every octave that puts the lowest note in range
+            let first = (self.lowest_note as i16 - lowest).div_euclid(12) + ((self.lowest_note as i16 - lowest).rem_euclid(12) != 0) as i16;
+            let mut octave = first;
+            while lowest + 12 * octave <= self.highest_note as i16 {
+                let voicing: Vec<i16> = shape.iter().map(|note| note + 12 * octave).collect();
+                if voicing.iter().all(|note| (self.lowest_note as i16..=self.highest_note as i16).contains(note))
+                    && voicing.windows(2).all(|pair| pair[0] != pair[1])
+                {
+                    voicings.push(voicing.iter().map(|note| *note as u8).collect());
+                }
+                octave += 1;
+            }
+        }
+        voicings.sort_unstable();
+        voicings.dedup();
+        voicings
+    }
+
+    /// Voices `notes` as smoothly as possible after `previous`. Without a
+    /// previous voicing, or when nothing fits in the range, the chord is kept
+    /// as it is if it fits, and otherwise moved to the middle of the range.
+    pub fn voice(&self, previous: Option<&[u8]>, notes: &[i16]) -> Vec<u8> {
+        let voicings = self.voicings(notes);
+        let as_is = || notes.iter().map(|note| note.clamp(&0, &127).to_owned() as u8).collect::<Vec<u8>>();
+        if voicings.is_empty() {
+            return as_is();
+        }
+        match previous {
+            Some(previous) if !previous.is_empty() => self.smoothest(previous, voicings).0,
+            _ => {
+                let chord = as_is();
+                if chord.iter().all(|note| (self.lowest_note..=self.highest_note).contains(note)) {
+                    return chord;
+                }
+                let middle = (self.lowest_note as i32 + self.highest_note as i32) / 2;
+                voicings
+                    .into_iter()
+                    .min_by_key(|voicing| (voicing.iter().map(|note| *note as i32).sum::<i32>() / voicing.len() as i32 - middle).abs())
+                    .expect("there is at least one voicing")
+            }
+        }
+    }
+
+    /// Returns the voicing with the lowest cost after `previous`, preferring
+    /// voicings that stay within `max_leap`.
+    pub fn smoothest(&self, previous: &[u8], voicings: Vec<Vec<u8>>) -> (Vec<u8>, VoiceLeadingCost) {
+        voicings
+            .into_iter()
+            .map(|voicing| {
+                let cost = cost(previous, &voicing);
+                (voicing, cost)
+            })
+            .min_by_key(|(_, cost)| (cost.max_leap > self.max_leap, cost.movement, cost.max_leap))
+            .expect("there is at least one voicing")
+    }
+
+    /// The cost of the smoothest voicing of `notes` after `previous`, or
+    /// `None` when no voicing of `notes` fits in the range.
+    pub fn cost(&self, previous: &[u8], notes: &[i16]) -> Option<VoiceLeadingCost> {
+        let voicings = self.voicings(notes);
+        match voicings.is_empty() || previous.is_empty() {
+            true => None,
+            false => Some(self.smoothest(previous, voicings).1),
+        }
+    }
+}
+
+/// Measures how far the voices move from `from` to `to`, which are both
+/// sorted. Voicings with the same number of notes are matched from the bottom
+/// up. Otherwise, each note is matched to the nearest note of the other
+/// voicing, and the movement is counted in both directions and halved.
+pub fn cost(from: &[u8], to: &[u8]) -> VoiceLeadingCost {
+    let distance = |a: &u8, b: &u8| (*a as i16 - *b as i16).unsigned_abs() as u8;
+    if from.len() == to.len() {
+        let leaps: Vec<u8> = from.iter().zip(to).map(|(a, b)| distance(a, b)).collect();
+        return VoiceLeadingCost {
+            movement: leaps.iter().map(|leap| *leap as u32).sum(),
+            max_leap: leaps.into_iter().max().unwrap_or(0),
+        };
+    }
+    let nearest = |note: &u8, notes: &[u8]| notes.iter().map(|other| distance(note, other)).min().unwrap_or(0);
+    let forward: Vec<u8> = to.iter().map(|note| nearest(note, from)).collect();
+    let backward: u32 = from.iter().map(|note| nearest(note, to) as u32).sum();
+    VoiceLeadingCost {
+        movement: (forward.iter().map(|leap| *leap as u32).sum::<u32>() + backward).div_ceil(2),
+        max_leap: forward.into_iter().max().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voicings_fit_in_the_range() {
+        let settings = VoiceLeading { lowest_note: 48, highest_note: 67, ..Default::default() };
+        let voicings = settings.voicings(&[0, 4, 7]);
+        assert!(voicings.contains(&vec![48, 52, 55]));
+        assert!(voicings.contains(&vec![52, 55, 60]));
+        assert!(voicings.contains(&vec![55, 60, 64]));
+        assert!(voicings.iter().flatten().all(|note| (48..=67).contains(note)));
+
+        // drop-2 voicings of a 7th chord
+        let voicings = settings.voicings(&[0, 4, 7, 11]);
+        assert!(voicings.contains(&vec![48, 55, 59, 64]));
+        let no_drops = VoiceLeading { drop_voicings: false, ..settings };
+        assert!(!no_drops.voicings(&[0, 4, 7, 11]).contains(&vec![48, 55, 59, 64]));
+    }
+
+    #[test]
+    fn voice_leading_moves_the_fewest_semitones() {
+        let settings = VoiceLeading::default();
+        // C major to F major keeps C and moves E and G up by step
+        let c = settings.voice(None, &[48, 52, 55]);
+        assert_eq!(c, [48, 52, 55]);
+        let f = settings.voice(Some(&c), &[53, 57, 60]);
+        assert_eq!(f, [48, 53, 57]);
+        assert_eq!(cost(&c, &f), VoiceLeadingCost { movement: 3, max_leap: 2 });
+        // G7 after F major
+        let g7 = settings.voice(Some(&f), &[55, 59, 62, 65]);
+        assert_eq!(cost(&f, &g7).max_leap, 2, "{:?}", g7);
+    }
+
+    #[test]
+    fn chords_outside_the_range_are_moved_in() {
+        let settings = VoiceLeading { lowest_note: 60, highest_note: 76, ..Default::default() };
+        let voicing = settings.voice(None, &[36, 40, 43]);
+        assert!(voicing.iter().all(|note| (60..=76).contains(note)), "{:?}", voicing);
+        // nothing fits, so the chord is left alone
+        let tiny = VoiceLeading { lowest_note: 60, highest_note: 62, ..Default::default() };
+        assert_eq!(tiny.voice(None, &[48, 52, 55]), [48, 52, 55]);
+    }
+
+    #[test]
+    fn costs_with_different_numbers_of_notes() {
+        assert_eq!(cost(&[48, 52, 55], &[48, 52, 55, 58]).max_leap, 3);
+        assert_eq!(cost(&[48, 52, 55, 58], &[48, 52, 55]).movement, 2);
+    }
+}